@@ -1,35 +1,100 @@
 use rodio::Source;
 use std::fs::File;
+use std::io;
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use symphonia::core::audio::{AudioBufferRef, Signal};
+use symphonia::core::audio::{AudioBuffer, AudioBufferRef, Signal};
 use symphonia::core::codecs::{Decoder, DecoderOptions, CODEC_TYPE_NULL};
 use symphonia::core::errors::Error;
-use symphonia::core::formats::{FormatOptions, FormatReader};
+use symphonia::core::formats::{FormatOptions, FormatReader, SeekMode, SeekTo};
 use symphonia::core::io::MediaSourceStream;
 use symphonia::core::meta::MetadataOptions;
 use symphonia::core::probe::Hint;
+use symphonia::core::sample::Sample;
+use symphonia::core::units::Time;
+
+mod dsd;
+mod stream_info;
+mod tags;
+pub use dsd::{is_dsd_file, probe_dsd, DsdRate, DsdSource};
+pub use stream_info::{format_bitrate, khz, StreamInfo};
+pub use tags::TrackInfo;
 
 pub struct AudioDecoder {
     format: Box<dyn FormatReader>,
     decoder: Box<dyn Decoder>,
     track_id: u32,
     current_frame: u64,
+    gapless: Option<GaplessInfo>,
+    tags: TrackInfo,
+    // Размер файла - для среднего битрейта сжатых форматов
+    file_size: u64,
+    // Ожидаемая метка времени следующего пакета - по разрыву видно потерянные кадры
+    next_ts: Option<u64>,
+    lost_packets: u64,
+}
+
+// Задержка кодера и длина без добивки из тега iTunSMPB (AAC в MP4).
+// Для MP3 то же самое делает сама symphonia при enable_gapless.
+#[derive(Clone, Copy)]
+struct GaplessInfo {
+    delay: u64,
+    frames: u64,
+}
+
+impl GaplessInfo {
+    // Формат: " 00000000 00000840 000001CA 00000000003F31F6 ..." -
+    // резерв, задержка, добивка, исходное число кадров (hex)
+    fn parse_itunsmpb(value: &str) -> Option<Self> {
+        let fields: Vec<u64> = value
+            .split_whitespace()
+            .take(4)
+            .map(|f| u64::from_str_radix(f, 16).ok())
+            .collect::<Option<_>>()?;
+        match fields[..] {
+            [_, delay, _, frames] if frames > 0 => Some(GaplessInfo { delay, frames }),
+            _ => None,
+        }
+    }
 }
 
 impl AudioDecoder {
     pub fn new(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
         let file = File::open(path)?;
+        let file_size = file.metadata()?.len();
         let mss = MediaSourceStream::new(Box::new(file), Default::default());
 
         let hint = Hint::new();
-        let format_opts = FormatOptions::default();
+        // Обрезаем задержку кодера и добивку MP3 (LAME/Xing)
+        let format_opts = FormatOptions {
+            enable_gapless: true,
+            ..Default::default()
+        };
         let metadata_opts = MetadataOptions::default();
 
-        let probed =
+        let mut probed =
             symphonia::default::get_probe().format(&hint, mss, &format_opts, &metadata_opts)?;
 
-        let format = probed.format;
+        let mut format = probed.format;
+
+        // Теги контейнера (Vorbis comments, MP4, RIFF INFO) главнее,
+        // ID3v2 перед началом потока - запасной вариант
+        let mut tags = TrackInfo::default();
+        if let Some(revision) = format.metadata().current() {
+            tags.merge(revision);
+        }
+        if let Some(revision) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
+            tags.merge(revision);
+        }
+
+        let gapless = format.metadata().current().and_then(|rev| {
+            rev.tags()
+                .iter()
+                .find(|tag| tag.key.ends_with("iTunSMPB"))
+                .and_then(|tag| GaplessInfo::parse_itunsmpb(&tag.value.to_string()))
+        });
 
         let track = format
             .tracks()
@@ -47,36 +112,131 @@ impl AudioDecoder {
             decoder,
             track_id,
             current_frame: 0,
+            gapless,
+            tags,
+            file_size,
+            next_ts: None,
+            lost_packets: 0,
         })
     }
 
-    // pub fn seek(&mut self, time: Duration) -> Result<(), Error> {
-    //     let seek_to = SeekTo::Time {
-    //         time: Time::from(time),
-    //         track_id: Some(self.track_id),
-    //     };
-    //     let seeked_to = self.format.seek(SeekMode::Accurate, seek_to)?;
-    //     self.current_frame = seeked_to.actual_ts;
-    //     Ok(())
-    // }
+    /// Перемотка на указанное время. Возвращает число кадров, которые нужно
+    /// отбросить после следующего декодирования, чтобы попасть точно в позицию
+    /// (формат прыгает на начало пакета, а не на нужный сэмпл).
+    pub fn seek(&mut self, time: Duration) -> Result<u64, Error> {
+        // Время в файле сдвинуто на задержку кодера
+        let delay = self.frames_to_duration(self.gapless.map_or(0, |g| g.delay));
+        let seek_to = SeekTo::Time {
+            time: Time::from(time + delay),
+            track_id: Some(self.track_id),
+        };
+        let seeked_to = self.format.seek(SeekMode::Accurate, seek_to)?;
+
+        // Внутреннее состояние декодера относится к старой позиции - сбрасываем
+        self.decoder.reset();
+        self.current_frame = seeked_to.actual_ts;
+        self.next_ts = None;
+
+        let skip_ts = seeked_to.required_ts.saturating_sub(seeked_to.actual_ts);
+        Ok(self.ts_to_frames(skip_ts))
+    }
+
+    fn frames_to_duration(&self, frames: u64) -> Duration {
+        let sample_rate = self.decoder.codec_params().sample_rate.unwrap_or(0);
+        if sample_rate == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_nanos((frames as u128 * 1_000_000_000 / sample_rate as u128) as u64)
+    }
+
+    // Переводит метку времени дорожки в кадры (обычно time_base = 1/sample_rate)
+    fn ts_to_frames(&self, ts: u64) -> u64 {
+        let params = self.decoder.codec_params();
+        match (params.time_base, params.sample_rate) {
+            (Some(time_base), Some(sample_rate)) => {
+                let time = time_base.calc_time(ts);
+                ((time.seconds as f64 + time.frac) * sample_rate as f64).round() as u64
+            }
+            _ => ts,
+        }
+    }
 
+    /// `Ok(None)` - штатный конец потока; ошибки пакетов отдаются как есть,
+    /// решать, пропускать ли их, должен вызывающий
     pub fn decode_next(&mut self) -> Result<Option<AudioBufferRef<'_>>, Error> {
-        let packet = self.format.next_packet()?;
+        let packet = match self.format.next_packet() {
+            Ok(packet) => packet,
+            Err(Error::IoError(e)) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        };
 
         if packet.track_id() == self.track_id {
-            match self.decoder.decode(&packet)? {
-                decoded => {
-                    self.current_frame = packet.ts();
-                    Ok(Some(decoded))
-                }
+            // Некоторые форматы сами выбрасывают кадры с неверной CRC (FLAC),
+            // тогда повреждение видно только по разрыву меток времени
+            if self.next_ts.is_some_and(|ts| packet.ts() > ts) {
+                self.lost_packets += 1;
             }
+            self.next_ts = Some(packet.ts() + packet.dur());
+
+            let decoded = self.decoder.decode(&packet)?;
+            self.current_frame = packet.ts();
+            Ok(Some(decoded))
         } else {
             // Пропускаем пакеты других дорожек и декодируем следующий
             self.decode_next()
         }
     }
 
+    /// Теги трека вместе с длительностью
+    pub fn track_info(&self) -> TrackInfo {
+        TrackInfo {
+            duration: self.duration(),
+            ..self.tags.clone()
+        }
+    }
+
+    /// Кодек, частота, разрядность и битрейт потока
+    pub fn stream_info(&self) -> StreamInfo {
+        let params = self.decoder.codec_params();
+        let codec = symphonia::default::get_codecs()
+            .get_codec(params.codec)
+            .map_or("?", |descriptor| {
+                stream_info::codec_label(descriptor.short_name)
+            });
+        let sample_rate = params.sample_rate.unwrap_or(0);
+        let channels = params.channels.map_or(0, |c| c.count() as u16);
+        let bits_per_sample = params.bits_per_sample.or(params.bits_per_coded_sample);
+
+        // PCM - точно по параметрам, сжатые форматы - в среднем по размеру файла
+        let bitrate = match (codec, bits_per_sample) {
+            ("PCM", Some(bits)) => Some(sample_rate as u64 * bits as u64 * channels as u64),
+            _ => self
+                .duration()
+                .filter(|d| !d.is_zero())
+                .map(|d| (self.file_size as f64 * 8.0 / d.as_secs_f64()) as u64),
+        };
+
+        StreamInfo {
+            codec: codec.to_string(),
+            sample_rate,
+            bits_per_sample,
+            channels,
+            bitrate,
+            n_frames: self.gapless.map(|g| g.frames).or(params.n_frames),
+            pcm_rate: sample_rate,
+        }
+    }
+
+    /// Сколько кадров потеряно с прошлого вызова (выброшены контейнером)
+    pub fn take_lost_packets(&mut self) -> u64 {
+        std::mem::take(&mut self.lost_packets)
+    }
+
     pub fn duration(&self) -> Option<Duration> {
+        if let Some(gapless) = self.gapless {
+            return Some(self.frames_to_duration(gapless.frames));
+        }
+
         let track = self
             .format
             .tracks()
@@ -88,31 +248,166 @@ impl AudioDecoder {
         let time = time_base.calc_time(n_frames);
         Some(Duration::from_secs_f64(time.seconds as f64 + time.frac))
     }
+}
+
+// Нет запроса на перемотку
+const NO_SEEK: u64 = u64::MAX;
+// Источник играет до конца файла
+const NO_LIMIT: u64 = u64::MAX;
+
+// Столько битых пакетов подряд - уже не повреждение, а нечитаемый файл
+const MAX_CONSECUTIVE_ERRORS: u32 = 100;
+
+// Состояние, общее для источника (живет внутри Sink) и UI-потока
+struct SourceShared {
+    // Запрошенная позиция перемотки в наносекундах или NO_SEEK
+    seek_request: AtomicU64,
+    // Сколько сэмплов (всех каналов) источник уже отдал в Sink
+    samples_played: AtomicU64,
+    // Источник снят из очереди Sink и должен сразу закончиться
+    cancelled: AtomicBool,
+    // Сколько поврежденных пакетов пропущено
+    skipped_packets: AtomicU64,
+    // Сэмпл, на котором источник заканчивается раньше конца файла (трек CUE), или NO_LIMIT
+    end_samples: AtomicU64,
+    // Почему источник закончился раньше конца файла
+    failure: Mutex<Option<String>>,
+    sample_rate: u32,
+    channels: u16,
+}
+
+impl SourceShared {
+    fn new(sample_rate: u32, channels: u16) -> Arc<Self> {
+        Arc::new(SourceShared {
+            seek_request: AtomicU64::new(NO_SEEK),
+            samples_played: AtomicU64::new(0),
+            cancelled: AtomicBool::new(false),
+            skipped_packets: AtomicU64::new(0),
+            end_samples: AtomicU64::new(NO_LIMIT),
+            failure: Mutex::new(None),
+            sample_rate,
+            channels,
+        })
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+
+    fn fail(&self, error: impl ToString) {
+        if let Ok(mut failure) = self.failure.lock() {
+            failure.get_or_insert_with(|| error.to_string());
+        }
+    }
+
+    fn has_failed(&self) -> bool {
+        self.failure
+            .lock()
+            .map_or(true, |failure| failure.is_some())
+    }
+
+    // Забирает запрос на перемотку, если он есть
+    fn take_seek(&self) -> Option<Duration> {
+        match self.seek_request.swap(NO_SEEK, Ordering::AcqRel) {
+            NO_SEEK => None,
+            nanos => Some(Duration::from_nanos(nanos)),
+        }
+    }
+
+    fn publish(&self, samples_played: u64) {
+        self.samples_played.store(samples_played, Ordering::Relaxed);
+    }
+
+    fn frames_at(&self, time: Duration) -> u64 {
+        (time.as_nanos() * self.sample_rate as u128 / 1_000_000_000) as u64
+    }
+
+    // Сколько сэмплов осталось до конца отрезка; None - отрезок не задан
+    fn samples_left(&self, samples_played: u64) -> Option<u64> {
+        match self.end_samples.load(Ordering::Relaxed) {
+            NO_LIMIT => None,
+            end => Some(end.saturating_sub(samples_played)),
+        }
+    }
+}
+
+/// Управление источником после того, как он передан в `Sink`.
+/// Перемотка выполняется самим источником на границе кадра, поэтому
+/// `OutputStream` и `Sink` пересоздавать не нужно.
+/// Для трека CUE позиция и перемотка считаются от начала отрезка.
+#[derive(Clone)]
+pub struct SourceHandle {
+    shared: Arc<SourceShared>,
+    offset: Duration,
+}
+
+impl SourceHandle {
+    fn new(shared: &Arc<SourceShared>) -> Self {
+        SourceHandle {
+            shared: Arc::clone(shared),
+            offset: Duration::ZERO,
+        }
+    }
+
+    pub fn seek(&self, time: Duration) {
+        let time = time + self.offset;
+        let nanos = u64::try_from(time.as_nanos()).unwrap_or(NO_SEEK - 1);
+        self.shared.seek_request.store(nanos, Ordering::Release);
+
+        // Сразу показываем новую позицию, даже если источник на паузе
+        // и применит перемотку только при следующем чтении
+        let samples = self.shared.frames_at(time) * self.shared.channels as u64;
+        self.shared.samples_played.store(samples, Ordering::Relaxed);
+    }
+
+    /// Снимает источник с воспроизведения: из очереди `Sink` его не удалить,
+    /// поэтому он просто заканчивается на ближайшей границе кадра.
+    pub fn cancel(&self) {
+        self.shared.cancelled.store(true, Ordering::Release);
+    }
 
-    //     pub fn current_time(&self) -> Option<Duration> {
-    //         let track = self
-    //             .format
-    //             .tracks()
-    //             .iter()
-    //             .find(|t| t.id == self.track_id)?;
-    //         let time_base = track.codec_params.time_base?;
-    //
-    //         let time = time_base.calc_time(self.current_frame);
-    //         Some(Duration::from_secs_f64(time.seconds as f64 + time.frac))
-    //     }
+    /// Позиция воспроизведения по числу кадров, отданных в `Sink`.
+    /// В отличие от часов не уплывает при паузах и опустошении буфера.
+    pub fn position(&self) -> Duration {
+        let frames =
+            self.shared.samples_played.load(Ordering::Relaxed) / self.shared.channels.max(1) as u64;
+        let nanos = frames as u128 * 1_000_000_000 / self.shared.sample_rate.max(1) as u128;
+        Duration::from_nanos(nanos as u64).saturating_sub(self.offset)
+    }
+
+    /// Сколько поврежденных пакетов пропущено при декодировании
+    pub fn skipped_packets(&self) -> u64 {
+        self.shared.skipped_packets.load(Ordering::Relaxed)
+    }
+
+    /// Ошибка, из-за которой трек закончился раньше времени
+    pub fn failure(&self) -> Option<String> {
+        self.shared.failure.lock().ok()?.clone()
+    }
 }
 
 // Адаптер для преобразования Symphonia AudioBuffer в Rodio Source
 pub struct SymphoniaSource {
     decoder: AudioDecoder,
-    current_buffer: Option<AudioBufferRef<'static>>,
+    // Текущий пакет, скопированный из буфера декодера (каналы чередуются).
+    // Буфер декодера переиспользуется следующим пакетом, поэтому держим свою копию
+    buffer: Vec<f32>,
     buffer_pos: usize,
+    // Сколько сэмплов отбросить после перемотки до точной позиции
+    skip_samples: usize,
+    // Локальный счетчик отданных сэмплов, публикуется в shared на границе кадра
+    samples_played: u64,
+    // Граница конца трека без добивки кодера (в сэмплах всех каналов)
+    samples_limit: Option<u64>,
+    // Строгий режим: первый же битый пакет завершает трек с ошибкой
+    strict: bool,
     sample_rate: u32,
     channels: u16,
+    shared: Arc<SourceShared>,
 }
 
 impl SymphoniaSource {
-    pub fn new(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn new(path: &Path, strict: bool) -> Result<Self, Box<dyn std::error::Error>> {
         let decoder = AudioDecoder::new(path)?;
 
         // Получаем информацию о формате
@@ -128,115 +423,171 @@ impl SymphoniaSource {
             .ok_or("No channels info")?
             .count() as u16;
 
-        Ok(SymphoniaSource {
+        let gapless = decoder.gapless;
+        let mut source = SymphoniaSource {
             decoder,
-            current_buffer: None,
+            buffer: Vec::new(),
             buffer_pos: 0,
+            skip_samples: gapless.map_or(0, |g| g.delay as usize * channels as usize),
+            samples_played: 0,
+            samples_limit: gapless.map(|g| g.frames * channels as u64),
+            strict,
             sample_rate,
             channels,
-        })
+            shared: SourceShared::new(sample_rate, channels),
+        };
+
+        // Декодируем первый пакет заранее: трек в очереди Sink стартует без задержки,
+        // а current_frame_len сразу знает длину кадра
+        source.fill_buffer()?;
+        Ok(source)
+    }
+
+    pub fn handle(&self) -> SourceHandle {
+        SourceHandle::new(&self.shared)
+    }
+
+    /// Начать с заданной позиции: перемотка сразу, а не в потоке вывода,
+    /// чтобы трек в очереди Sink стартовал без задержки
+    pub fn start_at(&mut self, time: Duration) {
+        self.handle().seek(time);
+        self.apply_pending_seek();
+        self.refill();
     }
 
     fn fill_buffer(&mut self) -> Result<bool, Error> {
-        if self.current_buffer.is_none() || self.buffer_pos >= self.get_buffer_len() {
-            match self.decoder.decode_next()? {
-                Some(buffer) => {
-                    // Временное решение - преобразуем в 'static
-                    let buffer = unsafe { std::mem::transmute(buffer) };
-                    self.current_buffer = Some(buffer);
-                    self.buffer_pos = 0;
-                    Ok(true)
+        let mut consecutive_errors = 0;
+        while self.buffer_pos >= self.buffer.len() {
+            self.buffer.clear();
+            self.buffer_pos = 0;
+            match self.decoder.decode_next() {
+                Ok(Some(decoded)) => {
+                    copy_interleaved(&decoded, &mut self.buffer);
+
+                    let lost = self.decoder.take_lost_packets();
+                    if lost > 0 {
+                        if self.strict {
+                            return Err(Error::DecodeError("поврежденный кадр пропущен форматом"));
+                        }
+                        self.shared
+                            .skipped_packets
+                            .fetch_add(lost, Ordering::Relaxed);
+                    }
+
+                    // После перемотки отбрасываем начало пакета до нужного сэмпла
+                    let skip = self.skip_samples.min(self.buffer.len());
+                    self.skip_samples -= skip;
+                    self.buffer_pos = skip;
+                }
+                Ok(None) => return Ok(false), // Конец потока
+                // Поврежденный пакет: пропускаем и считаем, трек играет дальше
+                Err(Error::DecodeError(_))
+                    if !self.strict && consecutive_errors < MAX_CONSECUTIVE_ERRORS =>
+                {
+                    consecutive_errors += 1;
+                    self.shared.skipped_packets.fetch_add(1, Ordering::Relaxed);
                 }
-                None => Ok(false), // Конец потока
+                Err(e) => return Err(e),
             }
-        } else {
-            Ok(true)
         }
+        Ok(true)
     }
 
-    fn get_buffer_len(&self) -> usize {
-        self.current_buffer
-            .as_ref()
-            .map(|b| b.frames() * b.spec().channels.count())
-            .unwrap_or(0)
+    // Следующий пакет; ошибку запоминаем в shared, чтобы движок мог ее показать
+    fn refill(&mut self) -> bool {
+        if self.shared.has_failed() {
+            return false;
+        }
+        match self.fill_buffer() {
+            Ok(more) => more,
+            Err(e) => {
+                self.shared.fail(e);
+                false
+            }
+        }
     }
 
-    // ДОБАВЛЯЕМ ГЕТТЕРЫ
-    //     pub fn decoder(&self) -> &AudioDecoder {
-    //         &self.decoder
-    //     }
-    //
-    //     pub fn decoder_mut(&mut self) -> &mut AudioDecoder {
-    //         &mut self.decoder
-    //     }
-    //
-    //     // Удобные методы для часто используемых операций
-    //     pub fn current_time(&self) -> Option<Duration> {
-    //         self.decoder.current_time()
-    //     }
+    // Выполняем отложенную перемотку. Вызывается только на границе кадра,
+    // чтобы каналы не перепутались.
+    fn apply_pending_seek(&mut self) {
+        let Some(time) = self.shared.take_seek() else {
+            return;
+        };
 
-    pub fn duration(&self) -> Option<Duration> {
-        self.decoder.duration()
+        // Выбрасываем уже декодированный пакет - иначе после прыжка доиграет старый звук
+        self.buffer.clear();
+        self.buffer_pos = 0;
+        self.skip_samples = 0;
+
+        // При ошибке (например, позиция за концом файла) просто играем дальше
+        if let Ok(skip_frames) = self.decoder.seek(time) {
+            self.skip_samples = skip_frames as usize * self.channels as usize;
+            self.samples_played = self.shared.frames_at(time) * self.channels as u64;
+        }
+    }
+
+    // Сколько сэмплов осталось до конца трека без добивки или до конца отрезка
+    fn samples_left(&self) -> Option<u64> {
+        let gapless = self
+            .samples_limit
+            .map(|limit| limit.saturating_sub(self.samples_played));
+        let span = self.shared.samples_left(self.samples_played);
+        match (gapless, span) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    pub fn track_info(&self) -> TrackInfo {
+        self.decoder.track_info()
     }
 
-    // pub fn seek(&mut self, time: Duration) -> Result<(), Error> {
-    //     self.decoder.seek(time)
-    // }
+    pub fn stream_info(&self) -> StreamInfo {
+        self.decoder.stream_info()
+    }
 }
 
 impl Iterator for SymphoniaSource {
     type Item = f32;
 
     fn next(&mut self) -> Option<f32> {
-        if !self.fill_buffer().ok()? {
-            return None;
+        if self.buffer_pos.is_multiple_of(self.channels as usize) {
+            if self.shared.is_cancelled() {
+                return None;
+            }
+            self.apply_pending_seek();
+            self.shared.publish(self.samples_played);
         }
 
-        let buffer = self.current_buffer.as_ref()?;
-        let channels = buffer.spec().channels.count();
-
-        if self.buffer_pos >= buffer.frames() * channels {
-            self.current_buffer = None;
-            return self.next();
+        if self.samples_left() == Some(0) || !self.refill() {
+            return None;
         }
 
-        let frame = self.buffer_pos / channels;
-        let channel = self.buffer_pos % channels;
-
-        let sample = match buffer {
-            AudioBufferRef::F32(buf) => buf.chan(channel)[frame],
-            AudioBufferRef::F64(buf) => buf.chan(channel)[frame] as f32,
-            AudioBufferRef::S16(buf) => buf.chan(channel)[frame] as f32 / i16::MAX as f32,
-            AudioBufferRef::S24(buf) => {
-                let sample_i24 = buf.chan(channel)[frame];
-                let sample_i32 = sample_i24.0 as i32;
-                sample_i32 as f32 / 8_388_607.0
-            }
-            AudioBufferRef::S32(buf) => buf.chan(channel)[frame] as f32 / i32::MAX as f32,
-            AudioBufferRef::U8(buf) => (buf.chan(channel)[frame] as f32 - 128.0) / 128.0,
-            AudioBufferRef::U16(buf) => (buf.chan(channel)[frame] as f32 - 32768.0) / 32768.0,
-            AudioBufferRef::U24(buf) => {
-                let sample_u24 = buf.chan(channel)[frame];
-                let sample_u32 = sample_u24.0 as u32;
-                (sample_u32 as f32 - 8_388_608.0) / 8_388_607.0
-            }
-            AudioBufferRef::U32(buf) => (buf.chan(channel)[frame] as f32 - 2_147_483_648.0) / 2_147_483_647.0,
-            // Обработка всех остальных форматов
-            _ => {
-                // Для неподдерживаемых форматов возвращаем 0.0
-                // Можно добавить логирование для отладки
-                0.0
-            }
-        };
-
+        let sample = self.buffer[self.buffer_pos];
         self.buffer_pos += 1;
+        self.samples_played += 1;
+
+        // Пакет кончился - сразу декодируем следующий, чтобы current_frame_len
+        // указывал точную границу (и конец трека) для очереди Sink
+        if self.buffer_pos >= self.buffer.len() {
+            self.refill();
+        }
         Some(sample)
     }
 }
 
 impl Source for SymphoniaSource {
     fn current_frame_len(&self) -> Option<usize> {
-        None // Поток неизвестной длины
+        // Кадр = остаток текущего пакета. Так переход к следующему треку в очереди
+        // совпадает с границей кадра, даже если у треков разные частота и каналы.
+        if self.shared.is_cancelled() {
+            return Some(0);
+        }
+        let remaining = self.buffer.len().saturating_sub(self.buffer_pos);
+        match self.samples_left() {
+            Some(left) => Some(remaining.min(left as usize)),
+            None => Some(remaining),
+        }
     }
 
     fn channels(&self) -> u16 {
@@ -251,3 +602,385 @@ impl Source for SymphoniaSource {
         self.decoder.duration()
     }
 }
+
+// Копирует пакет декодера в f32 с чередованием каналов.
+// Целые масштабируются симметрично на 2^(n-1): полная шкала -> [-1.0, 1.0),
+// беззнаковые сначала сдвигаются на середину диапазона
+fn copy_interleaved(decoded: &AudioBufferRef<'_>, out: &mut Vec<f32>) {
+    match decoded {
+        AudioBufferRef::U8(buf) => interleave(buf, out, |s| (s as f32 - 128.0) / 128.0),
+        AudioBufferRef::U16(buf) => interleave(buf, out, |s| (s as f32 - 32_768.0) / 32_768.0),
+        AudioBufferRef::U24(buf) => {
+            interleave(buf, out, |s| (s.inner() as f32 - 8_388_608.0) / 8_388_608.0)
+        }
+        // 32 бита не помещаются в мантиссу f32 - считаем в f64, округляем один раз
+        AudioBufferRef::U32(buf) => interleave(buf, out, |s| {
+            ((s as f64 - 2_147_483_648.0) / 2_147_483_648.0) as f32
+        }),
+        AudioBufferRef::S8(buf) => interleave(buf, out, |s| s as f32 / 128.0),
+        AudioBufferRef::S16(buf) => interleave(buf, out, |s| s as f32 / 32_768.0),
+        AudioBufferRef::S24(buf) => interleave(buf, out, |s| s.inner() as f32 / 8_388_608.0),
+        AudioBufferRef::S32(buf) => interleave(buf, out, |s| (s as f64 / 2_147_483_648.0) as f32),
+        AudioBufferRef::F32(buf) => interleave(buf, out, |s| s),
+        AudioBufferRef::F64(buf) => interleave(buf, out, |s| s as f32),
+    }
+}
+
+fn interleave<S: Sample>(buf: &AudioBuffer<S>, out: &mut Vec<f32>, convert: impl Fn(S) -> f32) {
+    let channels = buf.spec().channels.count();
+    out.reserve(buf.frames() * channels);
+    for frame in 0..buf.frames() {
+        for channel in 0..channels {
+            out.push(convert(buf.chan(channel)[frame]));
+        }
+    }
+}
+
+// Все форматы, которые открывает движок (symphonia + собственный DSD)
+pub const SUPPORTED_EXTENSIONS: &[&str] = &[
+    "wav", "flac", "ogg", "aiff", "aif", "aifc", "m4a", "caf", "mp3", "aac", "dsf", "dff",
+];
+
+pub fn is_supported_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| SUPPORTED_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// Открытый трек, готовый к добавлению в Sink
+pub struct OpenedTrack {
+    pub source: Box<dyn Source<Item = f32> + Send>,
+    pub handle: SourceHandle,
+    pub info: TrackInfo,
+    pub stream: StreamInfo,
+}
+
+/// Часть файла, которая играет как отдельный трек: трек CUE в образе альбома.
+/// end None - до конца файла
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: Duration,
+    pub end: Option<Duration>,
+}
+
+/// Настройки открытия треков
+#[derive(Clone, Copy, Default)]
+pub struct OpenOptions {
+    pub dsd_rate: DsdRate,
+    /// Останавливаться на поврежденном пакете вместо пропуска
+    pub strict: bool,
+}
+
+/// Единая точка входа для воспроизведения: любой путь к треку идет сюда.
+/// span - играть только часть файла
+pub fn open_track(
+    path: &Path,
+    span: Option<Span>,
+    options: OpenOptions,
+) -> Result<OpenedTrack, Box<dyn std::error::Error>> {
+    let start = span.map_or(Duration::ZERO, |span| span.start);
+    // DSD symphonia не поддерживает - свой декодер
+    let mut track = if is_dsd_file(path) {
+        let mut source = DsdSource::new(path, options.dsd_rate)?;
+        if span.is_some() {
+            source.start_at(start);
+        }
+        OpenedTrack {
+            handle: source.handle(),
+            info: TrackInfo {
+                duration: source.duration(),
+                ..TrackInfo::default()
+            },
+            stream: source.stream_info(),
+            source: Box::new(source),
+        }
+    } else {
+        let mut source = SymphoniaSource::new(path, options.strict)?;
+        if span.is_some() {
+            source.start_at(start);
+        }
+        OpenedTrack {
+            handle: source.handle(),
+            info: source.track_info(),
+            stream: source.stream_info(),
+            source: Box::new(source),
+        }
+    };
+
+    if let Some(span) = span {
+        let shared = &track.handle.shared;
+        if let Some(end) = span.end {
+            let end_samples = shared.frames_at(end) * shared.channels as u64;
+            shared.end_samples.store(end_samples, Ordering::Relaxed);
+        }
+        track.handle.offset = start;
+        track.info.duration = span
+            .end
+            .or(track.info.duration)
+            .map(|end| end.saturating_sub(start));
+    }
+    Ok(track)
+}
+
+/// Разметка треков, встроенная во FLAC
+#[derive(Clone, Debug, PartialEq)]
+pub enum EmbeddedCue {
+    /// Текст CUE из тега CUESHEET - с названиями треков
+    Text(String),
+    /// Блок CUESHEET: только начала треков и конец последнего
+    Starts(Vec<Duration>, Option<Duration>),
+}
+
+/// Читает заголовки целиком - звать не из потока UI
+pub fn embedded_cue(path: &Path) -> Option<EmbeddedCue> {
+    if !path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("flac"))
+    {
+        return None;
+    }
+    let decoder = AudioDecoder::new(path).ok()?;
+    let mut format = decoder.format;
+    let text = format.metadata().current().and_then(|revision| {
+        revision
+            .tags()
+            .iter()
+            .find(|tag| tag.key.eq_ignore_ascii_case("CUESHEET"))
+            .map(|tag| tag.value.to_string())
+    });
+    if let Some(text) = text {
+        return Some(EmbeddedCue::Text(text));
+    }
+
+    let sample_rate = decoder.decoder.codec_params().sample_rate?;
+    let at =
+        |ts: u64| Duration::from_nanos((ts as u128 * 1_000_000_000 / sample_rate as u128) as u64);
+    let cues = format.cues();
+    // Последний трек блока - lead-out (170 для CD, 255 для остальных): это конец диска
+    let (tracks, lead_out) = match cues.split_last() {
+        Some((last, tracks)) if matches!(last.index, 170 | 255) => {
+            (tracks, Some(at(last.start_ts)))
+        }
+        _ => (cues, None),
+    };
+    // Номера индексов symphonia не сохраняет: при двух и больше точках
+    // первая - INDEX 00 (пауза перед треком), вторая - INDEX 01
+    let starts = tracks
+        .iter()
+        .map(|cue| {
+            let point = cue.points.get(1).or(cue.points.first());
+            at(cue.start_ts + point.map_or(0, |p| p.start_offset_ts))
+        })
+        .collect();
+    Some(EmbeddedCue::Starts(starts, lead_out))
+}
+
+/// Теги, длительность и параметры потока без запуска воспроизведения
+pub fn probe_track(path: &Path) -> Option<(TrackInfo, StreamInfo)> {
+    if is_dsd_file(path) {
+        let (duration, stream) = probe_dsd(path)?;
+        let info = TrackInfo {
+            duration: Some(duration),
+            ..TrackInfo::default()
+        };
+        return Some((info, stream));
+    }
+    // Пакеты не декодируем - хватает заголовков
+    AudioDecoder::new(path)
+        .ok()
+        .map(|decoder| (decoder.track_info(), decoder.stream_info()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow;
+    use symphonia::core::audio::{Channels, SignalSpec};
+    use symphonia::core::probe::Instantiate;
+    use symphonia::core::sample::{i24, u24};
+
+    // Начало контейнера для каждого расширения, которое открывает symphonia
+    fn signature(ext: &str) -> Option<&'static [u8]> {
+        Some(match ext {
+            "wav" => b"RIFF\0\0\0\0WAVE",
+            "flac" => b"fLaC",
+            "ogg" => b"OggS",
+            "aiff" | "aif" | "aifc" => b"FORM\0\0\0\0AIFF",
+            "m4a" => b"\0\0\0\x20ftypM4A ",
+            "caf" => b"caff",
+            "mp3" => &[0xff, 0xfb],
+            "aac" => &[0xff, 0xf1],
+            _ => return None,
+        })
+    }
+
+    // Файловый менеджер показывает файл - значит его откроет ровно один путь:
+    // свой декодер DSD или формат, который symphonia собрала с фичами из Cargo.toml
+    #[test]
+    fn every_supported_extension_has_a_decoder() {
+        for ext in SUPPORTED_EXTENSIONS {
+            let path = Path::new("track").with_extension(ext);
+            assert!(is_supported_file(&path), "{}", ext);
+            if is_dsd_file(&path) {
+                assert!(signature(ext).is_none(), "{}: и DSD, и symphonia", ext);
+                continue;
+            }
+            let signature = signature(ext).unwrap_or_else(|| panic!("{}: нет сигнатуры", ext));
+            let mut data = signature.to_vec();
+            data.resize(64, 0);
+            let mut mss =
+                MediaSourceStream::new(Box::new(std::io::Cursor::new(data)), Default::default());
+            let found = symphonia::default::get_probe().next(&mut mss);
+            assert!(
+                matches!(found, Ok(Instantiate::Format(_))),
+                "{}: symphonia собрана без этого формата",
+                ext
+            );
+        }
+    }
+
+    // Пакет декодера: каналы заданы по отдельности, как в AudioBuffer
+    fn planar<S: Sample>(channels: &[&[S]]) -> AudioBuffer<S> {
+        let layout = match channels.len() {
+            1 => Channels::FRONT_LEFT,
+            _ => Channels::FRONT_LEFT | Channels::FRONT_RIGHT,
+        };
+        let frames = channels[0].len();
+        let mut buf = AudioBuffer::new(frames as u64, SignalSpec::new(44_100, layout));
+        buf.render_reserved(Some(frames));
+        for (channel, samples) in channels.iter().enumerate() {
+            buf.chan_mut(channel).copy_from_slice(samples);
+        }
+        buf
+    }
+
+    fn copied(decoded: AudioBufferRef<'_>) -> Vec<f32> {
+        let mut out = Vec::new();
+        copy_interleaved(&decoded, &mut out);
+        out
+    }
+
+    // Минимум, ноль и максимум формата: моно как есть, в стерео правый канал
+    // идет в обратном порядке - заодно проверяется чередование
+    fn check<S: Sample>(
+        min_zero_max: [S; 3],
+        wrap: impl Fn(AudioBuffer<S>) -> AudioBufferRef<'static>,
+        expected: [f32; 3],
+    ) {
+        let [min, zero, max] = min_zero_max;
+        let [low, mid, high] = expected;
+        let mono = copied(wrap(planar(&[&min_zero_max])));
+        assert_eq!(mono, expected);
+        let stereo = copied(wrap(planar(&[&min_zero_max, &[max, zero, min]])));
+        assert_eq!(stereo, [low, high, mid, mid, high, low]);
+    }
+
+    // Сравнение без допуска: масштаб - степень двойки, максимум - ровно 1 - 2^-(n-1)
+    #[test]
+    fn copy_interleaved_is_bit_exact() {
+        check(
+            [u8::MIN, 128, u8::MAX],
+            |buf| AudioBufferRef::U8(Cow::Owned(buf)),
+            [-1.0, 0.0, 1.0 - 1.0 / 128.0],
+        );
+        check(
+            [u16::MIN, 32_768, u16::MAX],
+            |buf| AudioBufferRef::U16(Cow::Owned(buf)),
+            [-1.0, 0.0, 1.0 - 1.0 / 32_768.0],
+        );
+        check(
+            [u24::MIN, u24::from(8_388_608u32), u24::MAX],
+            |buf| AudioBufferRef::U24(Cow::Owned(buf)),
+            [-1.0, 0.0, 1.0 - 1.0 / 8_388_608.0],
+        );
+        check(
+            [i8::MIN, 0, i8::MAX],
+            |buf| AudioBufferRef::S8(Cow::Owned(buf)),
+            [-1.0, 0.0, 1.0 - 1.0 / 128.0],
+        );
+        check(
+            [i16::MIN, 0, i16::MAX],
+            |buf| AudioBufferRef::S16(Cow::Owned(buf)),
+            [-1.0, 0.0, 1.0 - 1.0 / 32_768.0],
+        );
+        check(
+            [i24::MIN, i24::from(0), i24::MAX],
+            |buf| AudioBufferRef::S24(Cow::Owned(buf)),
+            [-1.0, 0.0, 1.0 - 1.0 / 8_388_608.0],
+        );
+        // 1 - 2^-31 в f32 не представимо для обоих 32-битных форматов и округляется до 1.0
+        check(
+            [u32::MIN, 1 << 31, u32::MAX],
+            |buf| AudioBufferRef::U32(Cow::Owned(buf)),
+            [-1.0, 0.0, 1.0],
+        );
+        check(
+            [i32::MIN, 0, i32::MAX],
+            |buf| AudioBufferRef::S32(Cow::Owned(buf)),
+            [-1.0, 0.0, 1.0],
+        );
+        check(
+            [-1.0f32, 0.0, 1.0],
+            |buf| AudioBufferRef::F32(Cow::Owned(buf)),
+            [-1.0, 0.0, 1.0],
+        );
+        check(
+            [-1.0f64, 0.0, 1.0],
+            |buf| AudioBufferRef::F64(Cow::Owned(buf)),
+            [-1.0, 0.0, 1.0],
+        );
+    }
+
+    // Левый канал - номер кадра, правый - он же со знаком минус: 4410 кадров 16/44.1
+    const RAMP: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/ramp_s16_stereo.wav"
+    );
+
+    fn open_ramp() -> OpenedTrack {
+        open_track(Path::new(RAMP), None, OpenOptions::default()).expect("фикстура открывается")
+    }
+
+    #[test]
+    fn open_track_decodes_every_sample() {
+        let track = open_ramp();
+        assert_eq!(track.stream.sample_rate, 44_100);
+        assert_eq!(track.stream.pcm_rate, 44_100);
+        assert_eq!(track.stream.channels, 2);
+        assert_eq!(track.stream.bits_per_sample, Some(16));
+        assert_eq!(track.stream.n_frames, Some(4410));
+        assert_eq!(track.info.duration, Some(Duration::from_millis(100)));
+
+        let samples: Vec<f32> = track.source.collect();
+        assert_eq!(samples.len(), 4410 * 2);
+        for (frame, pair) in samples.chunks(2).enumerate() {
+            assert_eq!(pair, [frame as f32 / 32_768.0, -(frame as f32) / 32_768.0]);
+        }
+    }
+
+    #[test]
+    fn seek_lands_on_requested_frame() {
+        let mut track = open_ramp();
+        let frame = |source: &mut Box<dyn Source<Item = f32> + Send>| {
+            let left = source.next().unwrap() * 32_768.0;
+            let right = source.next().unwrap() * 32_768.0;
+            assert_eq!(left, -right);
+            left as u64
+        };
+        for _ in 0..100 {
+            frame(&mut track.source);
+        }
+        for (millis, target) in [(50, 2205), (10, 441), (0, 0), (90, 3969)] {
+            track.handle.seek(Duration::from_millis(millis));
+            assert_eq!(track.handle.position(), Duration::from_millis(millis));
+            assert_eq!(frame(&mut track.source), target, "{} мс", millis);
+        }
+
+        // Перемотка посреди кадра: сначала доигрывается его правый канал
+        assert_eq!(track.source.next(), Some(3970.0 / 32_768.0));
+        track.handle.seek(Duration::from_millis(30));
+        assert_eq!(track.source.next(), Some(-3970.0 / 32_768.0));
+        assert_eq!(frame(&mut track.source), 1323);
+    }
+}
@@ -0,0 +1,824 @@
+// Чтение DSD (DSF и DSDIFF) и преобразование 1-битного потока в PCM.
+// Symphonia не умеет DSD, поэтому контейнеры разбираем сами.
+use super::{stream_info, SourceHandle, SourceShared, StreamInfo};
+use rodio::Source;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+// Сколько байт на канал читаем за раз из DFF (у DSF размер блока задан в файле)
+const DFF_READ_BYTES: usize = 4096;
+
+// "Тишина" DSD: равное число единиц и нулей, после фильтра дает ноль
+const DSD_SILENCE: u8 = 0x69;
+// Частоты DSD кратны 64 * 44.1 кГц; делитель - минимум для децимации в PCM
+const DSD_RATE_UNIT: u32 = 8 * 44_100;
+
+/// Частота PCM, в которую преобразуется DSD
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DsdRate {
+    Hz88200,
+    #[default]
+    Hz176400,
+    Hz352800,
+}
+
+impl DsdRate {
+    pub fn hz(self) -> u32 {
+        match self {
+            DsdRate::Hz88200 => 88_200,
+            DsdRate::Hz176400 => 176_400,
+            DsdRate::Hz352800 => 352_800,
+        }
+    }
+}
+
+impl fmt::Display for DsdRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.hz())
+    }
+}
+
+impl FromStr for DsdRate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().trim_end_matches("khz") {
+            "88200" | "88.2" => Ok(DsdRate::Hz88200),
+            "176400" | "176.4" => Ok(DsdRate::Hz176400),
+            "352800" | "352.8" => Ok(DsdRate::Hz352800),
+            other => Err(format!(
+                "неподдерживаемая частота DSD->PCM: {} (88.2, 176.4 или 352.8 кГц)",
+                other
+            )),
+        }
+    }
+}
+
+pub fn is_dsd_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| matches!(ext.to_lowercase().as_str(), "dsf" | "dff"))
+        .unwrap_or(false)
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn read_array<const N: usize>(r: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut buf = [0u8; N];
+    r.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn read_u32_le(r: &mut impl Read) -> io::Result<u32> {
+    Ok(u32::from_le_bytes(read_array(r)?))
+}
+
+fn read_u64_le(r: &mut impl Read) -> io::Result<u64> {
+    Ok(u64::from_le_bytes(read_array(r)?))
+}
+
+fn read_u16_be(r: &mut impl Read) -> io::Result<u16> {
+    Ok(u16::from_be_bytes(read_array(r)?))
+}
+
+fn read_u32_be(r: &mut impl Read) -> io::Result<u32> {
+    Ok(u32::from_be_bytes(read_array(r)?))
+}
+
+fn read_u64_be(r: &mut impl Read) -> io::Result<u64> {
+    Ok(u64::from_be_bytes(read_array(r)?))
+}
+
+enum Layout {
+    // DSF: каналы чередуются блоками по block_size байт
+    Dsf { block_size: usize, lsb_first: bool },
+    // DSDIFF: каналы чередуются побайтно, старший бит первый
+    Dff,
+}
+
+// Сырой DSD поток: отдает байты каждого канала в порядке MSB-first
+struct DsdStream<R = BufReader<File>> {
+    reader: R,
+    layout: Layout,
+    channels: usize,
+    dsd_rate: u32,
+    data_start: u64,
+    // Число DSD сэмплов (бит) на канал
+    sample_frames: u64,
+    // Сколько байт каждого канала уже прочитано
+    pos: u64,
+}
+
+impl DsdStream {
+    fn open(path: &Path) -> io::Result<Self> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read + Seek> DsdStream<R> {
+    fn from_reader(mut reader: R) -> io::Result<Self> {
+        let magic: [u8; 4] = read_array(&mut reader)?;
+        let mut stream = match &magic {
+            b"DSD " => Self::open_dsf(reader)?,
+            b"FRM8" => Self::open_dff(reader)?,
+            _ => return Err(invalid("не DSF/DSDIFF файл")),
+        };
+        if stream.channels == 0 {
+            return Err(invalid("некорректный заголовок DSD"));
+        }
+        // Иначе частота PCM после децимации выйдет нулевой
+        if stream.dsd_rate == 0 || stream.dsd_rate % DSD_RATE_UNIT != 0 {
+            return Err(invalid("некорректная частота DSD"));
+        }
+        stream.rewind()?;
+        Ok(stream)
+    }
+
+    fn open_dsf(mut reader: R) -> io::Result<Self> {
+        // Заголовок "DSD ": размер чанка, размер файла, указатель на ID3
+        let header_size = read_u64_le(&mut reader)?;
+        reader.seek(SeekFrom::Start(header_size))?;
+
+        let fmt_id: [u8; 4] = read_array(&mut reader)?;
+        if &fmt_id != b"fmt " {
+            return Err(invalid("DSF: нет чанка fmt"));
+        }
+        let fmt_size = read_u64_le(&mut reader)?;
+        let _version = read_u32_le(&mut reader)?;
+        let format_id = read_u32_le(&mut reader)?;
+        if format_id != 0 {
+            return Err(invalid("DSF: поддерживается только DSD raw"));
+        }
+        let _channel_type = read_u32_le(&mut reader)?;
+        let channels = read_u32_le(&mut reader)? as usize;
+        let dsd_rate = read_u32_le(&mut reader)?;
+        let bits_per_sample = read_u32_le(&mut reader)?;
+        let sample_frames = read_u64_le(&mut reader)?;
+        let block_size = read_u32_le(&mut reader)? as usize;
+        if block_size == 0 {
+            return Err(invalid("DSF: нулевой размер блока"));
+        }
+
+        reader.seek(SeekFrom::Start(header_size + fmt_size))?;
+        let data_id: [u8; 4] = read_array(&mut reader)?;
+        if &data_id != b"data" {
+            return Err(invalid("DSF: нет чанка data"));
+        }
+        let _data_size = read_u64_le(&mut reader)?;
+        let data_start = reader.stream_position()?;
+
+        Ok(DsdStream {
+            reader,
+            layout: Layout::Dsf {
+                block_size,
+                lsb_first: bits_per_sample == 1,
+            },
+            channels,
+            dsd_rate,
+            data_start,
+            sample_frames,
+            pos: 0,
+        })
+    }
+
+    fn open_dff(mut reader: R) -> io::Result<Self> {
+        let _form_size = read_u64_be(&mut reader)?;
+        let form_type: [u8; 4] = read_array(&mut reader)?;
+        if &form_type != b"DSD " {
+            return Err(invalid("DSDIFF: неизвестный тип формы"));
+        }
+
+        let mut channels = 0;
+        let mut dsd_rate = 0;
+        loop {
+            let id: [u8; 4] = read_array(&mut reader)?;
+            let size = read_u64_be(&mut reader)?;
+            let chunk_start = reader.stream_position()?;
+            // Чанки выравниваются по четной границе
+            let chunk_end = chunk_start + size + (size & 1);
+
+            match &id {
+                b"PROP" => {
+                    let prop_type: [u8; 4] = read_array(&mut reader)?;
+                    if &prop_type == b"SND " {
+                        while reader.stream_position()? < chunk_start + size {
+                            let sub_id: [u8; 4] = read_array(&mut reader)?;
+                            let sub_size = read_u64_be(&mut reader)?;
+                            let sub_start = reader.stream_position()?;
+                            match &sub_id {
+                                b"FS  " => dsd_rate = read_u32_be(&mut reader)?,
+                                b"CHNL" => channels = read_u16_be(&mut reader)? as usize,
+                                b"CMPR" => {
+                                    let compression: [u8; 4] = read_array(&mut reader)?;
+                                    if &compression != b"DSD " {
+                                        return Err(invalid(
+                                            "DSDIFF: сжатый DST не поддерживается",
+                                        ));
+                                    }
+                                }
+                                _ => {}
+                            }
+                            reader.seek(SeekFrom::Start(sub_start + sub_size + (sub_size & 1)))?;
+                        }
+                    }
+                }
+                b"DSD " => {
+                    let sample_frames = if channels > 0 {
+                        size / channels as u64 * 8
+                    } else {
+                        0
+                    };
+                    return Ok(DsdStream {
+                        reader,
+                        layout: Layout::Dff,
+                        channels,
+                        dsd_rate,
+                        data_start: chunk_start,
+                        sample_frames,
+                        pos: 0,
+                    });
+                }
+                b"DST " => return Err(invalid("DSDIFF: сжатый DST не поддерживается")),
+                _ => {}
+            }
+            reader.seek(SeekFrom::Start(chunk_end))?;
+        }
+    }
+
+    fn bytes_per_channel(&self) -> u64 {
+        self.sample_frames.div_ceil(8)
+    }
+
+    fn stream_info(&self, pcm_rate: u32) -> StreamInfo {
+        StreamInfo {
+            codec: stream_info::dsd_label(self.dsd_rate).to_string(),
+            sample_rate: self.dsd_rate,
+            bits_per_sample: Some(1),
+            channels: self.channels as u16,
+            bitrate: Some(self.dsd_rate as u64 * self.channels as u64),
+            n_frames: Some(self.sample_frames),
+            pcm_rate,
+        }
+    }
+
+    fn duration(&self) -> Duration {
+        let nanos = self.sample_frames as u128 * 1_000_000_000 / self.dsd_rate as u128;
+        Duration::from_nanos(nanos as u64)
+    }
+
+    fn rewind(&mut self) -> io::Result<()> {
+        self.reader.seek(SeekFrom::Start(self.data_start))?;
+        self.pos = 0;
+        Ok(())
+    }
+
+    // Встает как можно ближе к байту `byte` каждого канала (не дальше него).
+    // Возвращает, сколько байт осталось пропустить до точной позиции.
+    fn seek_to_byte(&mut self, byte: u64) -> io::Result<u64> {
+        let byte = byte.min(self.bytes_per_channel());
+        let aligned = match self.layout {
+            Layout::Dsf { block_size, .. } => byte - byte % block_size as u64,
+            Layout::Dff => byte,
+        };
+        let offset = self.data_start + aligned * self.channels as u64;
+        self.reader.seek(SeekFrom::Start(offset))?;
+        self.pos = aligned;
+        Ok(byte - aligned)
+    }
+
+    // Читает следующую порцию: по одному буферу байт на канал.
+    // Возвращает число байт на канал, 0 - конец данных.
+    fn read_chunk(&mut self, out: &mut [Vec<u8>]) -> io::Result<usize> {
+        let remaining = self.bytes_per_channel().saturating_sub(self.pos) as usize;
+        if remaining == 0 {
+            return Ok(0);
+        }
+
+        match self.layout {
+            Layout::Dsf {
+                block_size,
+                lsb_first,
+            } => {
+                // Последний блок дополнен нулями до полного размера
+                let mut block = vec![0u8; block_size * self.channels];
+                read_fully(&mut self.reader, &mut block)?;
+                let valid = remaining.min(block_size);
+                for (ch, channel_out) in out.iter_mut().enumerate() {
+                    channel_out.clear();
+                    let start = ch * block_size;
+                    channel_out.extend(block[start..start + valid].iter().map(|&b| {
+                        if lsb_first {
+                            b.reverse_bits()
+                        } else {
+                            b
+                        }
+                    }));
+                }
+                self.pos += valid as u64;
+                Ok(valid)
+            }
+            Layout::Dff => {
+                let count = remaining.min(DFF_READ_BYTES);
+                let mut interleaved = vec![0u8; count * self.channels];
+                let read = read_fully(&mut self.reader, &mut interleaved)?;
+                let count = read / self.channels;
+                for (ch, channel_out) in out.iter_mut().enumerate() {
+                    channel_out.clear();
+                    channel_out.extend(
+                        interleaved
+                            .iter()
+                            .skip(ch)
+                            .step_by(self.channels)
+                            .take(count),
+                    );
+                }
+                self.pos += count as u64;
+                Ok(count)
+            }
+        }
+    }
+}
+
+// read_exact, который не считает ошибкой обрезанный в конце файл
+fn read_fully(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+// Децимирующий FIR фильтр для одного канала. Как в dsd2pcm: вклад каждого
+// байта истории заранее посчитан для всех 256 значений, так что на выходной
+// сэмпл нужно по одному сложению на байт фильтра.
+struct Decimator {
+    tables: Arc<Vec<[f32; 256]>>,
+    history: Vec<u8>,
+    // Индекс самого нового байта в кольцевой истории
+    head: usize,
+    // Через сколько байт входа выдается один выходной сэмпл
+    step: usize,
+    phase: usize,
+}
+
+impl Decimator {
+    fn new(tables: Arc<Vec<[f32; 256]>>, step: usize) -> Self {
+        let len = tables.len();
+        Decimator {
+            tables,
+            history: vec![DSD_SILENCE; len],
+            head: 0,
+            step,
+            phase: 0,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.history.fill(DSD_SILENCE);
+        self.head = 0;
+        self.phase = 0;
+    }
+
+    fn push(&mut self, byte: u8) -> Option<f32> {
+        let len = self.history.len();
+        self.head = (self.head + 1) % len;
+        self.history[self.head] = byte;
+
+        self.phase += 1;
+        if self.phase < self.step {
+            return None;
+        }
+        self.phase = 0;
+
+        // tables[k] - вклад байта, пришедшего k байт назад
+        let mut sum = 0.0f32;
+        for (age, table) in self.tables.iter().enumerate() {
+            let index = (self.head + len - age) % len;
+            sum += table[self.history[index] as usize];
+        }
+        Some(sum.clamp(-1.0, 1.0))
+    }
+}
+
+// Таблицы фильтра нижних частот (окно Блэкмана) для децимации в `factor` раз
+fn build_tables(dsd_rate: u32, pcm_rate: u32, factor: usize) -> Vec<[f32; 256]> {
+    // Длина фильтра растет с коэффициентом децимации, округляем до байта
+    let table_count = (factor * 16).div_ceil(8);
+    let taps = table_count * 8;
+
+    // Срез оставляет ультразвук, но давит шум DSD до частоты Найквиста PCM
+    let cutoff = (pcm_rate as f64 * 0.3).min(50_000.0) / dsd_rate as f64;
+    let center = (taps - 1) as f64 / 2.0;
+    let mut coeffs: Vec<f64> = (0..taps)
+        .map(|n| {
+            let x = n as f64 - center;
+            let sinc = if x == 0.0 {
+                2.0 * cutoff
+            } else {
+                (2.0 * std::f64::consts::PI * cutoff * x).sin() / (std::f64::consts::PI * x)
+            };
+            let phase = 2.0 * std::f64::consts::PI * n as f64 / (taps - 1) as f64;
+            let window = 0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos();
+            sinc * window
+        })
+        .collect();
+
+    // Единичное усиление на постоянном токе
+    let sum: f64 = coeffs.iter().sum();
+    coeffs.iter_mut().for_each(|c| *c /= sum);
+
+    // Отвод 0 - самый свежий бит (младший бит самого нового байта)
+    (0..table_count)
+        .map(|age| {
+            let mut table = [0.0f32; 256];
+            for (value, slot) in table.iter_mut().enumerate() {
+                let mut acc = 0.0;
+                for bit in 0..8 {
+                    let tap = age * 8 + bit;
+                    let sign = if (value >> bit) & 1 == 1 { 1.0 } else { -1.0 };
+                    acc += coeffs[tap] * sign;
+                }
+                *slot = acc as f32;
+            }
+            table
+        })
+        .collect()
+}
+
+/// Источник для rodio: DSF/DFF с преобразованием в PCM на лету.
+/// Перемотка и позиция работают через тот же `SourceHandle`, что и у `SymphoniaSource`.
+pub struct DsdSource {
+    stream: DsdStream,
+    decimators: Vec<Decimator>,
+    channel_bytes: Vec<Vec<u8>>,
+    // Готовые PCM сэмплы, чередование по каналам
+    output: Vec<f32>,
+    output_pos: usize,
+    samples_played: u64,
+    pcm_rate: u32,
+    channels: u16,
+    duration: Duration,
+    shared: Arc<SourceShared>,
+}
+
+impl DsdSource {
+    pub fn new(path: &Path, rate: DsdRate) -> Result<Self, Box<dyn std::error::Error>> {
+        let stream = DsdStream::open(path)?;
+
+        // Коэффициент децимации в битах; DSD64 -> 352.8 кГц дает 8 (байт на сэмпл)
+        let pcm_rate = rate.hz().min(stream.dsd_rate / 8);
+        let factor = (stream.dsd_rate / pcm_rate) as usize;
+        let step = (factor / 8).max(1);
+        let pcm_rate = stream.dsd_rate / (step as u32 * 8);
+
+        let tables = Arc::new(build_tables(stream.dsd_rate, pcm_rate, factor));
+        let decimators = (0..stream.channels)
+            .map(|_| Decimator::new(Arc::clone(&tables), step))
+            .collect();
+
+        let channels = stream.channels as u16;
+        let duration = stream.duration();
+        let mut source = DsdSource {
+            channel_bytes: vec![Vec::new(); stream.channels],
+            stream,
+            decimators,
+            output: Vec::new(),
+            output_pos: 0,
+            samples_played: 0,
+            pcm_rate,
+            channels,
+            duration,
+            shared: SourceShared::new(pcm_rate, channels),
+        };
+
+        // Первая порция готова заранее, как и у SymphoniaSource
+        source.fill_output()?;
+        Ok(source)
+    }
+
+    pub fn handle(&self) -> SourceHandle {
+        SourceHandle::new(&self.shared)
+    }
+
+    /// Начать с заданной позиции, как `SymphoniaSource::start_at`
+    pub fn start_at(&mut self, time: Duration) {
+        self.handle().seek(time);
+        self.apply_pending_seek();
+        if self.output_pos >= self.output.len() {
+            self.refill();
+        }
+    }
+
+    pub fn duration(&self) -> Option<Duration> {
+        Some(self.duration)
+    }
+
+    pub fn stream_info(&self) -> StreamInfo {
+        self.stream.stream_info(self.pcm_rate)
+    }
+
+    // Декодирует следующую порцию DSD в PCM. false - конец потока.
+    fn fill_output(&mut self) -> io::Result<bool> {
+        self.output.clear();
+        self.output_pos = 0;
+
+        while self.output.is_empty() {
+            let count = self.stream.read_chunk(&mut self.channel_bytes)?;
+            if count == 0 {
+                return Ok(false);
+            }
+            // Все дециматоры идут в одной фазе, поэтому выдают сэмплы одновременно
+            for i in 0..count {
+                for (decimator, bytes) in self.decimators.iter_mut().zip(&self.channel_bytes) {
+                    if let Some(sample) = decimator.push(bytes[i]) {
+                        self.output.push(sample);
+                    }
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    // Ошибку чтения запоминаем в shared, чтобы движок мог ее показать
+    fn refill(&mut self) -> bool {
+        if self.shared.has_failed() {
+            return false;
+        }
+        match self.fill_output() {
+            Ok(more) => more,
+            Err(e) => {
+                self.shared.fail(e);
+                false
+            }
+        }
+    }
+
+    fn apply_pending_seek(&mut self) {
+        let Some(time) = self.shared.take_seek() else {
+            return;
+        };
+
+        // Байт, с которого начинается выходной сэмпл
+        let step = self.decimators[0].step as u64;
+        let dsd_frame = (time.as_nanos() * self.stream.dsd_rate as u128 / 1_000_000_000) as u64;
+        let target = dsd_frame / 8 / step * step;
+
+        // Историю фильтра заполняем байтами перед целью - с тишиной в истории
+        // после перемотки был бы щелчок
+        let history = self.decimators[0].history.len() as u64;
+        let warm_up = target.saturating_sub(history) / step * step;
+        let Ok(skip_bytes) = self.stream.seek_to_byte(warm_up) else {
+            return;
+        };
+
+        self.output.clear();
+        self.output_pos = 0;
+        self.decimators.iter_mut().for_each(Decimator::reset);
+
+        // Байты до цели только прогревают фильтр, их сэмплы отбрасываем
+        let mut to_skip = (skip_bytes + target - warm_up) as usize;
+        while to_skip > 0 {
+            let Ok(count) = self.stream.read_chunk(&mut self.channel_bytes) else {
+                break;
+            };
+            if count == 0 {
+                break;
+            }
+            let used = count.min(to_skip);
+            for i in 0..count {
+                for (decimator, bytes) in self.decimators.iter_mut().zip(&self.channel_bytes) {
+                    let sample = decimator.push(bytes[i]);
+                    if i >= used {
+                        if let Some(sample) = sample {
+                            self.output.push(sample);
+                        }
+                    }
+                }
+            }
+            to_skip -= used;
+        }
+
+        self.samples_played = self.shared.frames_at(time) * self.channels as u64;
+    }
+}
+
+impl Iterator for DsdSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.output_pos.is_multiple_of(self.channels as usize) {
+            if self.shared.is_cancelled() {
+                return None;
+            }
+            self.apply_pending_seek();
+            self.shared.publish(self.samples_played);
+        }
+
+        // Конец отрезка (трек CUE) или файла
+        if self.shared.samples_left(self.samples_played) == Some(0)
+            || (self.output_pos >= self.output.len() && !self.refill())
+        {
+            return None;
+        }
+
+        let sample = self.output[self.output_pos];
+        self.output_pos += 1;
+        self.samples_played += 1;
+
+        // Готовим следующую порцию, чтобы current_frame_len видел ее длину
+        if self.output_pos >= self.output.len() {
+            self.refill();
+        }
+        Some(sample)
+    }
+}
+
+impl Source for DsdSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        if self.shared.is_cancelled() {
+            return Some(0);
+        }
+        let remaining = self.output.len() - self.output_pos;
+        match self.shared.samples_left(self.samples_played) {
+            Some(left) => Some(remaining.min(left as usize)),
+            None => Some(remaining),
+        }
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.pcm_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(self.duration)
+    }
+}
+
+/// Длительность и параметры DSD файла по заголовку, без построения фильтра.
+/// Частота PCM зависит от --dsd-rate, поэтому здесь она равна частоте DSD
+pub fn probe_dsd(path: &Path) -> Option<(Duration, StreamInfo)> {
+    let stream = DsdStream::open(path).ok()?;
+    Some((stream.duration(), stream.stream_info(stream.dsd_rate)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Cursor;
+
+    const DSD64: u32 = 64 * 44_100;
+
+    fn dsf(bits_per_sample: u32, block_size: u32, sample_frames: u64, data: &[u8]) -> Vec<u8> {
+        let mut file = Vec::new();
+        file.extend(b"DSD ");
+        file.extend(28u64.to_le_bytes());
+        file.extend((28 + 52 + 12 + data.len() as u64).to_le_bytes());
+        file.extend(0u64.to_le_bytes());
+
+        file.extend(b"fmt ");
+        file.extend(52u64.to_le_bytes());
+        // версия, DSD raw, стерео: тип и число каналов
+        for value in [1, 0, 2, 2, DSD64, bits_per_sample] {
+            file.extend(value.to_le_bytes());
+        }
+        file.extend(sample_frames.to_le_bytes());
+        file.extend(block_size.to_le_bytes());
+        file.extend(0u32.to_le_bytes());
+
+        file.extend(b"data");
+        file.extend((12 + data.len() as u64).to_le_bytes());
+        file.extend(data);
+        file
+    }
+
+    fn chunk(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend((body.len() as u64).to_be_bytes());
+        chunk.extend(body);
+        chunk
+    }
+
+    // Стерео DSDIFF, data - байты каналов вперемешку
+    fn dff(data: &[u8]) -> Vec<u8> {
+        let mut channels = 2u16.to_be_bytes().to_vec();
+        channels.extend(b"SLFTSRGT");
+        let mut snd = b"SND ".to_vec();
+        snd.extend(chunk(b"FS  ", &DSD64.to_be_bytes()));
+        snd.extend(chunk(b"CHNL", &channels));
+        snd.extend(chunk(b"CMPR", b"DSD \0\0"));
+
+        let mut form = b"DSD ".to_vec();
+        form.extend(chunk(b"FVER", &[1, 5, 0, 0]));
+        form.extend(chunk(b"PROP", &snd));
+        form.extend(chunk(b"DSD ", data));
+        chunk(b"FRM8", &form)
+    }
+
+    fn open(file: Vec<u8>) -> DsdStream<Cursor<Vec<u8>>> {
+        DsdStream::from_reader(Cursor::new(file)).unwrap()
+    }
+
+    #[test]
+    fn dsf_header_is_parsed() {
+        let stream = open(dsf(1, 4, 48, &[0; 16]));
+        assert_eq!(stream.channels, 2);
+        assert_eq!(stream.dsd_rate, DSD64);
+        assert_eq!(stream.sample_frames, 48);
+        assert_eq!(stream.bytes_per_channel(), 6);
+        assert_eq!(stream.data_start, 28 + 52 + 12);
+        assert!(matches!(
+            stream.layout,
+            Layout::Dsf {
+                block_size: 4,
+                lsb_first: true
+            }
+        ));
+    }
+
+    #[test]
+    fn dsf_blocks_are_deinterleaved_and_bit_reversed() {
+        // Блоки по 4 байта: левый, правый, левый, правый; последние дополнены нулями
+        let data = [
+            0x01, 0x02, 0x03, 0x04, 0x10, 0x20, 0x30, 0x40, //
+            0x0F, 0xAA, 0x00, 0x00, 0xF0, 0x55, 0x00, 0x00,
+        ];
+        let mut out = vec![Vec::new(); 2];
+
+        let mut stream = open(dsf(1, 4, 48, &data));
+        assert_eq!(stream.read_chunk(&mut out).unwrap(), 4);
+        assert_eq!(out, [[0x80, 0x40, 0xC0, 0x20], [0x08, 0x04, 0x0C, 0x02]]);
+        assert_eq!(stream.read_chunk(&mut out).unwrap(), 2);
+        assert_eq!(out, [[0xF0, 0x55], [0x0F, 0xAA]]);
+        assert_eq!(stream.read_chunk(&mut out).unwrap(), 0);
+
+        // 8 бит на сэмпл - старший бит первый, байты как есть
+        let mut stream = open(dsf(8, 4, 48, &data));
+        stream.read_chunk(&mut out).unwrap();
+        assert_eq!(out, [[0x01, 0x02, 0x03, 0x04], [0x10, 0x20, 0x30, 0x40]]);
+    }
+
+    #[test]
+    fn dff_header_is_parsed_and_bytes_deinterleaved() {
+        let mut stream = open(dff(&[0x01, 0x10, 0x02, 0x20, 0x03, 0x30]));
+        assert_eq!(stream.channels, 2);
+        assert_eq!(stream.dsd_rate, DSD64);
+        assert_eq!(stream.sample_frames, 24);
+        assert!(matches!(stream.layout, Layout::Dff));
+
+        let mut out = vec![Vec::new(); 2];
+        assert_eq!(stream.read_chunk(&mut out).unwrap(), 3);
+        assert_eq!(out, [[0x01, 0x02, 0x03], [0x10, 0x20, 0x30]]);
+        assert_eq!(stream.read_chunk(&mut out).unwrap(), 0);
+    }
+
+    // После перемотки звук должен совпадать с непрерывным воспроизведением,
+    // в том числе когда цель попадает ровно на границу блока DSF
+    #[test]
+    fn seek_matches_continuous_playback() {
+        let mut seed = 1u32;
+        let noise: Vec<u8> = (0..2 * 3 * 1764)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (seed >> 16) as u8
+            })
+            .collect();
+        let dir = std::env::temp_dir().join(format!("hi-res-player-dsd-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        // 10 мс при 88.2 кГц - 882 сэмпла, то есть 3528 байт: ровно два блока DSF
+        let target = Duration::from_millis(10);
+        let files = [
+            ("seek.dsf", dsf(8, 1764, 3 * 1764 * 8, &noise)),
+            ("seek.dff", dff(&noise)),
+        ];
+        for (name, file) in files {
+            let path = dir.join(name);
+            fs::write(&path, file).unwrap();
+
+            let continuous: Vec<f32> = DsdSource::new(&path, DsdRate::Hz88200).unwrap().collect();
+            let mut seeked = DsdSource::new(&path, DsdRate::Hz88200).unwrap();
+            seeked.start_at(target);
+            let seeked: Vec<f32> = seeked.collect();
+
+            assert_eq!(seeked, continuous[882 * 2..], "{}", name);
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,77 @@
+// Технические параметры потока: что на самом деле играет
+use std::fmt;
+
+#[derive(Clone, Debug)]
+pub struct StreamInfo {
+    pub codec: String,
+    /// Частота потока в файле (для DSD - частота DSD)
+    pub sample_rate: u32,
+    pub bits_per_sample: Option<u32>,
+    pub channels: u16,
+    /// Средний битрейт, бит/с
+    pub bitrate: Option<u64>,
+    pub n_frames: Option<u64>,
+    /// Частота PCM, которую источник отдает в Sink (у DSD отличается)
+    pub pcm_rate: u32,
+}
+
+impl fmt::Display for StreamInfo {
+    // Например: "FLAC 24/192 2ch 4.6 Mbps"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.codec)?;
+        match self.bits_per_sample {
+            // У DSD частоту видно по названию (DSD64, DSD128...)
+            Some(1) => {}
+            Some(bits) => write!(f, " {}/{}", bits, khz(self.sample_rate))?,
+            None => write!(f, " {} kHz", khz(self.sample_rate))?,
+        }
+        write!(f, " {}ch", self.channels)?;
+        if let Some(bitrate) = self.bitrate {
+            write!(f, " {}", format_bitrate(bitrate))?;
+        }
+        if self.pcm_rate != self.sample_rate {
+            write!(f, " → PCM {} kHz", khz(self.pcm_rate))?;
+        }
+        Ok(())
+    }
+}
+
+/// 44100 -> "44.1", 192000 -> "192"
+pub fn khz(rate: u32) -> String {
+    format!("{}", rate as f64 / 1000.0)
+}
+
+pub fn format_bitrate(bitrate: u64) -> String {
+    if bitrate >= 1_000_000 {
+        format!("{:.1} Mbps", bitrate as f64 / 1_000_000.0)
+    } else {
+        format!("{} kbps", (bitrate + 500) / 1000)
+    }
+}
+
+// Короткое имя кодека для строки состояния
+pub(super) fn codec_label(short_name: &'static str) -> &'static str {
+    match short_name {
+        "flac" => "FLAC",
+        "alac" => "ALAC",
+        "mp1" => "MP1",
+        "mp2" => "MP2",
+        "mp3" => "MP3",
+        "aac" => "AAC",
+        "vorbis" => "Vorbis",
+        "opus" => "Opus",
+        name if name.starts_with("pcm") => "PCM",
+        name if name.starts_with("adpcm") => "ADPCM",
+        name => name,
+    }
+}
+
+pub(super) fn dsd_label(dsd_rate: u32) -> &'static str {
+    match dsd_rate / 44_100 {
+        64 => "DSD64",
+        128 => "DSD128",
+        256 => "DSD256",
+        512 => "DSD512",
+        _ => "DSD",
+    }
+}
@@ -0,0 +1,90 @@
+// Теги трека. symphonia сводит Vorbis comments, ID3v2, MP4 atoms и RIFF INFO
+// к общим StandardTagKey, поэтому разбор один на все форматы.
+use std::time::Duration;
+use symphonia::core::meta::{MetadataRevision, StandardTagKey};
+
+#[derive(Clone, Debug, Default)]
+pub struct TrackInfo {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub composer: Option<String>,
+    pub genre: Option<String>,
+    pub date: Option<String>,
+    pub track_number: Option<u32>,
+    pub track_total: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub disc_total: Option<u32>,
+    pub duration: Option<Duration>,
+}
+
+impl TrackInfo {
+    /// Дополняет пустые поля тегами из ревизии: что прочитано раньше, то и главнее
+    pub(super) fn merge(&mut self, revision: &MetadataRevision) {
+        for tag in revision.tags() {
+            let Some(key) = tag.std_key else {
+                continue;
+            };
+            // RIFF INFO добивает строки нулями
+            let value = tag.value.to_string();
+            let value = value.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+            if value.is_empty() {
+                continue;
+            }
+
+            match key {
+                StandardTagKey::TrackTitle => set_text(&mut self.title, value),
+                StandardTagKey::Artist => set_text(&mut self.artist, value),
+                StandardTagKey::Album => set_text(&mut self.album, value),
+                StandardTagKey::AlbumArtist => set_text(&mut self.album_artist, value),
+                StandardTagKey::Composer => set_text(&mut self.composer, value),
+                StandardTagKey::Genre => set_text(&mut self.genre, value),
+                StandardTagKey::Date
+                | StandardTagKey::ReleaseDate
+                | StandardTagKey::OriginalDate => set_text(&mut self.date, value),
+                // ID3v2 хранит номер и общее число вместе: "3/12"
+                StandardTagKey::TrackNumber => {
+                    set_pair(&mut self.track_number, &mut self.track_total, value)
+                }
+                StandardTagKey::TrackTotal => set_number(&mut self.track_total, value),
+                StandardTagKey::DiscNumber => {
+                    set_pair(&mut self.disc_number, &mut self.disc_total, value)
+                }
+                StandardTagKey::DiscTotal => set_number(&mut self.disc_total, value),
+                _ => {}
+            }
+        }
+    }
+
+    /// "Исполнитель - Название" для списков; None, если названия в тегах нет
+    pub fn display_title(&self) -> Option<String> {
+        let title = self.title.as_deref()?;
+        match self.artist.as_deref().or(self.album_artist.as_deref()) {
+            Some(artist) => Some(format!("{} - {}", artist, title)),
+            None => Some(title.to_string()),
+        }
+    }
+}
+
+fn set_text(field: &mut Option<String>, value: &str) {
+    if field.is_none() {
+        *field = Some(value.to_string());
+    }
+}
+
+fn set_number(field: &mut Option<u32>, value: &str) {
+    if field.is_none() {
+        *field = value.trim().parse().ok();
+    }
+}
+
+fn set_pair(number: &mut Option<u32>, total: &mut Option<u32>, value: &str) {
+    match value.split_once('/') {
+        Some((n, t)) => {
+            set_number(number, n);
+            set_number(total, t);
+        }
+        None => set_number(number, value),
+    }
+}
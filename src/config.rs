@@ -0,0 +1,138 @@
+// Настройки из $XDG_CONFIG_HOME/hi-res-player/config.toml.
+// Все поля необязательны: чего нет в файле, берется по умолчанию
+use crate::keymap::Keymap;
+use crate::paths;
+use crate::player::PlaybackMode;
+use crate::playlist_file::PathStyle;
+use crate::theme::Palette;
+use ratatui::style::Color;
+use serde::de::{self, Deserializer};
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+const CONFIG_FILE: &str = "config.toml";
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub general: General,
+    pub audio: Audio,
+    pub playlist: Playlist,
+    pub theme: Palette,
+    pub keys: Keymap,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct General {
+    /// Папка при запуске без аргумента и без сохраненной сессии
+    pub start_dir: Option<PathBuf>,
+    #[serde(deserialize_with = "volume_step")]
+    pub volume_step: f32,
+    #[serde(deserialize_with = "playback_mode")]
+    pub playback_mode: Option<PlaybackMode>,
+    pub show_hidden: bool,
+    /// Куда дописывать журнал сообщений; по умолчанию он только в памяти
+    pub log_file: Option<PathBuf>,
+}
+
+impl Default for General {
+    fn default() -> Self {
+        General {
+            start_dir: None,
+            volume_step: 0.1,
+            playback_mode: None,
+            show_hidden: false,
+            log_file: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Audio {
+    /// Размер буфера вывода в кадрах: больше - меньше щелчков при нагрузке
+    #[serde(deserialize_with = "buffer_size")]
+    pub buffer_size: u32,
+}
+
+impl Default for Audio {
+    fn default() -> Self {
+        Audio { buffer_size: 16384 }
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Playlist {
+    /// Пути при сохранении по умолчанию: absolute, relative или library
+    #[serde(deserialize_with = "path_style")]
+    pub paths: PathStyle,
+    /// Кроме папок библиотеки: от них считаются пути library
+    /// и в них ищутся пропавшие файлы
+    pub roots: Vec<PathBuf>,
+}
+
+impl Config {
+    /// Нет файла - настройки по умолчанию. Ошибка разбора указывает строку и ключ
+    pub fn load() -> Result<Self, Box<dyn std::error::Error>> {
+        let Some(path) = paths::config_dir().map(|dir| dir.join(CONFIG_FILE)) else {
+            return Ok(Config::default());
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(format!("{}: {}", path.display(), e).into()),
+        };
+        toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e).into())
+    }
+}
+
+/// Цвет темы: "#rrggbb", имя ("light-blue") или номер 0-255
+pub fn color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    value.parse().map_err(|_| {
+        de::Error::custom(format!(
+            "неверный цвет: {} (нужен #rrggbb, имя или номер 0-255)",
+            value
+        ))
+    })
+}
+
+fn volume_step<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    let step = f32::deserialize(deserializer)?;
+    if step > 0.0 && step <= 1.0 {
+        Ok(step)
+    } else {
+        Err(de::Error::custom(format!(
+            "шаг громкости {} вне диапазона (0, 1]",
+            step
+        )))
+    }
+}
+
+fn playback_mode<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<PlaybackMode>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    value.parse().map(Some).map_err(de::Error::custom)
+}
+
+fn path_style<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathStyle, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    value.parse().map_err(de::Error::custom)
+}
+
+fn buffer_size<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    let size = u32::deserialize(deserializer)?;
+    if (256..=1 << 20).contains(&size) {
+        Ok(size)
+    } else {
+        Err(de::Error::custom(format!(
+            "размер буфера {} вне диапазона 256..1048576",
+            size
+        )))
+    }
+}
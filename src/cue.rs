@@ -0,0 +1,388 @@
+// Разбор CUE: альбом одним файлом (или несколькими) и разметка треков в нем.
+// Время в CUE - mm:ss:ff, где ff - кадры CD, 75 в секунде
+use crate::audio_engine::{EmbeddedCue, Span, TrackInfo, SUPPORTED_EXTENSIONS};
+use crate::playlist_file;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const FRAMES_PER_SECOND: u64 = 75;
+
+#[derive(Default)]
+pub struct CueSheet {
+    pub performer: Option<String>,
+    pub title: Option<String>,
+    /// REM GENRE, REM DATE и т.п.
+    pub genre: Option<String>,
+    pub date: Option<String>,
+    pub disc_number: Option<u32>,
+    pub disc_total: Option<u32>,
+    pub files: Vec<CueFile>,
+}
+
+/// FILE: путь как записан в CUE, обычно относительно самого CUE
+pub struct CueFile {
+    pub path: String,
+    pub tracks: Vec<CueTrack>,
+}
+
+pub struct CueTrack {
+    pub number: u32,
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    /// REM GENRE и REM DATE внутри TRACK - важнее альбомных
+    pub genre: Option<String>,
+    pub date: Option<String>,
+    /// INDEX 01 - начало трека в файле
+    pub start: Option<Duration>,
+}
+
+/// Трек CUE, готовый к воспроизведению: часть файла и его теги
+#[derive(Clone)]
+pub struct VirtualTrack {
+    pub path: PathBuf,
+    /// None - трек занимает весь файл
+    pub span: Option<Span>,
+    pub info: TrackInfo,
+}
+
+/// Неизвестные команды и строки пропускаются: CUE пишут очень разные программы
+pub fn parse(text: &str) -> CueSheet {
+    let mut sheet = CueSheet::default();
+    for line in text.lines() {
+        let fields = fields(line);
+        let Some((command, args)) = fields.split_first() else {
+            continue;
+        };
+        let arg = args.first().cloned();
+        let track = sheet
+            .files
+            .last_mut()
+            .and_then(|file| file.tracks.last_mut());
+
+        match command.to_uppercase().as_str() {
+            "FILE" => {
+                if let Some(path) = arg {
+                    sheet.files.push(CueFile {
+                        path,
+                        tracks: Vec::new(),
+                    });
+                }
+            }
+            "TRACK" => {
+                let number = arg.and_then(|n| n.parse::<u32>().ok());
+                if let (Some(file), Some(number)) = (sheet.files.last_mut(), number) {
+                    file.tracks.push(CueTrack {
+                        number,
+                        title: None,
+                        performer: None,
+                        genre: None,
+                        date: None,
+                        start: None,
+                    });
+                }
+            }
+            // До первого TRACK - альбом, после - трек
+            "TITLE" => match track {
+                Some(track) => track.title = arg,
+                None => sheet.title = arg,
+            },
+            "PERFORMER" => match track {
+                Some(track) => track.performer = arg,
+                None => sheet.performer = arg,
+            },
+            "INDEX" => match (track, args) {
+                (Some(track), [number, time, ..]) if number == "01" => {
+                    track.start = parse_time(time);
+                }
+                _ => {}
+            },
+            "REM" => {
+                let value = args.get(1).cloned();
+                match (arg.map(|key| key.to_uppercase()).as_deref(), track) {
+                    (Some("GENRE"), Some(track)) => track.genre = value,
+                    (Some("DATE"), Some(track)) => track.date = value,
+                    (Some("GENRE"), None) => sheet.genre = value,
+                    (Some("DATE"), None) => sheet.date = value,
+                    // Номер диска у трека не имеет смысла
+                    (Some("DISCNUMBER"), None) => {
+                        sheet.disc_number = value.and_then(|n| n.parse().ok())
+                    }
+                    (Some("TOTALDISCS"), None) => {
+                        sheet.disc_total = value.and_then(|n| n.parse().ok())
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    sheet
+}
+
+impl CueSheet {
+    /// Треки всех FILE по порядку; base - папка, от которой считаются пути FILE.
+    /// Трек кончается там, где начинается следующий в том же файле, последний - с файлом
+    pub fn tracks(&self, base: &Path) -> Vec<VirtualTrack> {
+        let total = self
+            .files
+            .iter()
+            .map(|file| file.tracks.len())
+            .sum::<usize>() as u32;
+        let mut tracks = Vec::new();
+        for file in &self.files {
+            let path = find_file(base, &file.path);
+            // Трек без INDEX 01 сыграть нельзя - не знаем, где он начинается
+            let starts: Vec<(&CueTrack, Duration)> = file
+                .tracks
+                .iter()
+                .filter_map(|track| Some((track, track.start?)))
+                .collect();
+            for (i, &(track, start)) in starts.iter().enumerate() {
+                let end = starts.get(i + 1).map(|&(_, next)| next);
+                // Файл на трек (CUE на несколько FILE) - играем файл целиком
+                let span = if starts.len() == 1 && start.is_zero() {
+                    None
+                } else {
+                    Some(Span { start, end })
+                };
+                tracks.push(VirtualTrack {
+                    path: path.clone(),
+                    span,
+                    info: TrackInfo {
+                        title: track.title.clone(),
+                        artist: track.performer.clone().or_else(|| self.performer.clone()),
+                        album: self.title.clone(),
+                        album_artist: self.performer.clone(),
+                        genre: track.genre.clone().or_else(|| self.genre.clone()),
+                        date: track.date.clone().or_else(|| self.date.clone()),
+                        track_number: Some(track.number),
+                        track_total: Some(total),
+                        disc_number: self.disc_number,
+                        disc_total: self.disc_total,
+                        duration: end.map(|end| end.saturating_sub(start)),
+                        ..TrackInfo::default()
+                    },
+                });
+            }
+        }
+        tracks
+    }
+}
+
+/// Треки из файла .cue
+pub fn load(path: &Path) -> Result<Vec<VirtualTrack>, Box<dyn std::error::Error>> {
+    let text = playlist_file::decode(&fs::read(path)?);
+    let base = path.parent().unwrap_or(Path::new("."));
+    Ok(parse(&text).tracks(base))
+}
+
+/// Разметка образа альбома: CUE рядом (album.cue или album.flac.cue)
+/// или встроенный во FLAC, который нашла проба. None - файл обычный, один трек
+pub fn for_image(path: &Path, embedded: Option<EmbeddedCue>) -> Option<Vec<VirtualTrack>> {
+    let mut with_suffix = path.as_os_str().to_owned();
+    with_suffix.push(".cue");
+    let sibling = [path.with_extension("cue"), PathBuf::from(with_suffix)]
+        .into_iter()
+        .find(|candidate| candidate.is_file());
+    let tracks = match sibling {
+        Some(sibling) => {
+            let sheet = parse(&playlist_file::decode(&fs::read(sibling).ok()?));
+            image_tracks(sheet, path)
+        }
+        None => embedded_tracks(path, embedded?),
+    };
+    (tracks.len() > 1).then_some(tracks)
+}
+
+// Треки CUE, которые лежат в образе. CUE с одним FILE описывает именно его,
+// как бы файл ни назывался внутри CUE
+fn image_tracks(sheet: CueSheet, image: &Path) -> Vec<VirtualTrack> {
+    let base = image.parent().unwrap_or(Path::new("."));
+    let mut tracks = sheet.tracks(base);
+    if sheet.files.len() == 1 {
+        for track in &mut tracks {
+            track.path = image.to_path_buf();
+        }
+    } else {
+        tracks.retain(|track| track.path == image);
+    }
+    tracks
+}
+
+// Встроенный CUE: текст из тега или одни начала треков из блока CUESHEET
+fn embedded_tracks(path: &Path, cue: EmbeddedCue) -> Vec<VirtualTrack> {
+    match cue {
+        EmbeddedCue::Text(text) => {
+            // Все FILE встроенного CUE - это сам образ
+            let mut sheet = parse(&text);
+            let tracks = sheet.files.drain(..).flat_map(|file| file.tracks).collect();
+            sheet.files.push(CueFile {
+                path: String::new(),
+                tracks,
+            });
+            image_tracks(sheet, path)
+        }
+        EmbeddedCue::Starts(starts, lead_out) => {
+            let total = starts.len() as u32;
+            starts
+                .iter()
+                .enumerate()
+                .map(|(i, &start)| {
+                    let end = starts.get(i + 1).copied().or(lead_out);
+                    VirtualTrack {
+                        path: path.to_path_buf(),
+                        span: Some(Span { start, end }),
+                        info: TrackInfo {
+                            track_number: Some(i as u32 + 1),
+                            track_total: Some(total),
+                            duration: end.map(|end| end.saturating_sub(start)),
+                            ..TrackInfo::default()
+                        },
+                    }
+                })
+                .collect()
+        }
+    }
+}
+
+// Файл из FILE. Образ часто пережимают (wav -> flac), а CUE не правят -
+// тогда ищем то же имя с другим поддерживаемым расширением
+fn find_file(base: &Path, location: &str) -> PathBuf {
+    // CUE из Windows пишут пути через обратную косую черту
+    let path = playlist_file::resolve(base, &location.replace('\\', "/"));
+    if path.exists() {
+        return path;
+    }
+    SUPPORTED_EXTENSIONS
+        .iter()
+        .map(|ext| path.with_extension(ext))
+        .find(|candidate| candidate.is_file())
+        .unwrap_or(path)
+}
+
+// Команда и аргументы; аргумент в кавычках может содержать пробелы
+fn fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut chars = line.trim().chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            fields.push(chars.by_ref().take_while(|&c| c != '"').collect());
+        } else {
+            let mut field = String::new();
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                field.push(c);
+            }
+            fields.push(field);
+        }
+    }
+    fields
+}
+
+fn parse_time(time: &str) -> Option<Duration> {
+    let mut parts = time.split(':').map(|part| part.parse::<u64>().ok());
+    let (minutes, seconds, frames) = (parts.next()??, parts.next()??, parts.next()??);
+    let frames = (minutes * 60 + seconds) * FRAMES_PER_SECOND + frames;
+    Some(Duration::from_nanos(
+        frames * 1_000_000_000 / FRAMES_PER_SECOND,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHEET: &str = r#"REM GENRE "Rock"
+REM DATE 1988
+REM DISCNUMBER 1
+PERFORMER "Кино"
+TITLE "Группа крови"
+FILE "CD1\Кино - Группа крови.flac" WAVE
+  TRACK 01 AUDIO
+    TITLE "Группа крови"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "Закрой за мной дверь, я ухожу"
+    PERFORMER "Виктор Цой"
+    REM DATE 1987
+    REM DISCNUMBER 2
+    INDEX 00 04:44:50
+    INDEX 01 04:46:00
+FILE "bonus.wav" WAVE
+  TRACK 03 AUDIO
+    TITLE "Бонус"
+    INDEX 01 00:00:00
+  TRACK 04 AUDIO
+    TITLE "Без начала"
+"#;
+
+    #[test]
+    fn parse_reads_quoted_fields_and_per_track_rem() {
+        let sheet = parse(SHEET);
+        assert_eq!(sheet.performer.as_deref(), Some("Кино"));
+        assert_eq!(sheet.title.as_deref(), Some("Группа крови"));
+        assert_eq!(sheet.genre.as_deref(), Some("Rock"));
+        assert_eq!(sheet.date.as_deref(), Some("1988"));
+        assert_eq!(sheet.disc_number, Some(1));
+        assert_eq!(sheet.files.len(), 2);
+        assert_eq!(sheet.files[0].path, "CD1\\Кино - Группа крови.flac");
+
+        let second = &sheet.files[0].tracks[1];
+        assert_eq!(second.number, 2);
+        assert_eq!(
+            second.title.as_deref(),
+            Some("Закрой за мной дверь, я ухожу")
+        );
+        assert_eq!(second.performer.as_deref(), Some("Виктор Цой"));
+        assert_eq!(second.date.as_deref(), Some("1987"));
+        // INDEX 00 - пауза перед треком, начало - INDEX 01
+        assert_eq!(second.start, parse_time("04:46:00"));
+    }
+
+    #[test]
+    fn parse_time_counts_cd_frames() {
+        assert_eq!(parse_time("00:00:00"), Some(Duration::ZERO));
+        assert_eq!(parse_time("01:02:15"), Some(Duration::from_millis(62_200)));
+        assert_eq!(parse_time("01:02"), None);
+        assert_eq!(parse_time("01:xx:00"), None);
+    }
+
+    #[test]
+    fn tracks_end_where_the_next_one_starts() {
+        let tracks = parse(SHEET).tracks(Path::new("/нет/такой/папки"));
+        // Трек без INDEX 01 пропускается
+        assert_eq!(tracks.len(), 3);
+
+        let paths: Vec<&Path> = tracks.iter().map(|t| t.path.as_path()).collect();
+        assert_eq!(
+            paths,
+            [
+                Path::new("/нет/такой/папки/CD1/Кино - Группа крови.flac"),
+                Path::new("/нет/такой/папки/CD1/Кино - Группа крови.flac"),
+                Path::new("/нет/такой/папки/bonus.wav"),
+            ]
+        );
+
+        let second_start = Duration::from_secs(4 * 60 + 46);
+        let first = tracks[0].span.unwrap();
+        assert_eq!(
+            (first.start, first.end),
+            (Duration::ZERO, Some(second_start))
+        );
+        // Последний трек файла играет до конца файла
+        let second = tracks[1].span.unwrap();
+        assert_eq!((second.start, second.end), (second_start, None));
+        assert_eq!(tracks[1].info.duration, None);
+        // Единственный трек с начала файла - это весь файл
+        assert!(tracks[2].span.is_none());
+
+        assert_eq!(tracks[0].info.artist.as_deref(), Some("Кино"));
+        assert_eq!(tracks[1].info.artist.as_deref(), Some("Виктор Цой"));
+        assert_eq!(tracks[0].info.date.as_deref(), Some("1988"));
+        assert_eq!(tracks[1].info.date.as_deref(), Some("1987"));
+        assert_eq!(tracks[1].info.disc_number, Some(1));
+    }
+}
@@ -0,0 +1,689 @@
+// Действия по клавишам: раскладка по умолчанию и переназначения из конфига
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::de::{self, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Action {
+    Help,
+    Play,
+    Pause,
+    Stop,
+    PreviousTrack,
+    NextTrack,
+    SeekBackward,
+    SeekForward,
+    SavePlaylist,
+    TrackInfo,
+    Messages,
+    SwitchPanel,
+    RescanLibrary,
+    CycleMode,
+    PlayNext,
+    Enqueue,
+    ClearUpNext,
+    Relink,
+    Search,
+    SearchNext,
+    SearchPrevious,
+    Cancel,
+    Down,
+    Up,
+    First,
+    Last,
+    MarkDown,
+    MarkUp,
+    Right,
+    Left,
+    Add,
+    Remove,
+    PurgeBroken,
+    VolumeUp,
+    VolumeDown,
+    Quit,
+}
+
+/// Раздел справки
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Group {
+    Playback,
+    Navigation,
+    Volume,
+    Application,
+}
+
+impl Group {
+    pub fn title(self) -> &'static str {
+        match self {
+            Group::Playback => "Основное управление:",
+            Group::Navigation => "Навигация:",
+            Group::Volume => "Громкость:",
+            Group::Application => "Закрытие приложения:",
+        }
+    }
+}
+
+struct ActionInfo {
+    action: Action,
+    // Имя в конфиге
+    name: &'static str,
+    group: Group,
+    description: &'static str,
+    // Клавиши по умолчанию; последовательность - через пробел
+    keys: &'static [&'static str],
+}
+
+const fn info(
+    action: Action,
+    name: &'static str,
+    group: Group,
+    description: &'static str,
+    keys: &'static [&'static str],
+) -> ActionInfo {
+    ActionInfo {
+        action,
+        name,
+        group,
+        description,
+        keys,
+    }
+}
+
+// В порядке справки
+const ACTIONS: &[ActionInfo] = &[
+    info(Action::Help, "help", Group::Playback, "Справка", &["F1"]),
+    info(Action::Play, "play", Group::Playback, "Воспроизведение", &["F2"]),
+    info(Action::Pause, "pause", Group::Playback, "Пауза", &["F3"]),
+    info(Action::Stop, "stop", Group::Playback, "Стоп", &["F4"]),
+    info(
+        Action::PreviousTrack,
+        "previous-track",
+        Group::Playback,
+        "Предыдущий трек",
+        &["F5"],
+    ),
+    info(
+        Action::NextTrack,
+        "next-track",
+        Group::Playback,
+        "Следующий трек",
+        &["F6"],
+    ),
+    info(
+        Action::SeekBackward,
+        "seek-backward",
+        Group::Playback,
+        "Перемотка на 10 сек назад",
+        &["F7"],
+    ),
+    info(
+        Action::SeekForward,
+        "seek-forward",
+        Group::Playback,
+        "Перемотка на 10 сек вперед",
+        &["F8"],
+    ),
+    info(
+        Action::SavePlaylist,
+        "save-playlist",
+        Group::Playback,
+        "Сохранить плейлист",
+        &["F9"],
+    ),
+    info(
+        Action::TrackInfo,
+        "track-info",
+        Group::Playback,
+        "Свойства трека: теги, кодек, формат вывода",
+        &["i"],
+    ),
+    info(
+        Action::Messages,
+        "messages",
+        Group::Playback,
+        "Журнал сообщений",
+        &["L"],
+    ),
+    info(
+        Action::SwitchPanel,
+        "switch-panel",
+        Group::Navigation,
+        "Файлы → Плейлист → Библиотека",
+        &["tab"],
+    ),
+    info(
+        Action::RescanLibrary,
+        "rescan-library",
+        Group::Navigation,
+        "Пересканировать библиотеку",
+        &["r"],
+    ),
+    info(
+        Action::CycleMode,
+        "cycle-mode",
+        Group::Navigation,
+        "Режим: по порядку / по кругу / повтор трека / перемешать",
+        &["m"],
+    ),
+    info(
+        Action::PlayNext,
+        "play-next",
+        Group::Navigation,
+        "Сыграть выделенное следующим",
+        &["A"],
+    ),
+    info(
+        Action::Enqueue,
+        "enqueue",
+        Group::Navigation,
+        "Добавить в конец очереди UP NEXT",
+        &["a"],
+    ),
+    info(
+        Action::ClearUpNext,
+        "clear-up-next",
+        Group::Navigation,
+        "Очистить очередь UP NEXT",
+        &["X"],
+    ),
+    info(
+        Action::Relink,
+        "relink",
+        Group::Navigation,
+        "Найти пропавшие файлы плейлиста в папках библиотеки",
+        &["R"],
+    ),
+    info(
+        Action::Search,
+        "search",
+        Group::Navigation,
+        "Поиск по имени и тегам (Enter - готово)",
+        &["/"],
+    ),
+    info(
+        Action::SearchNext,
+        "search-next",
+        Group::Navigation,
+        "Следующее совпадение",
+        &["n"],
+    ),
+    info(
+        Action::SearchPrevious,
+        "search-previous",
+        Group::Navigation,
+        "Предыдущее совпадение",
+        &["N"],
+    ),
+    info(Action::Cancel, "cancel", Group::Navigation, "Сбросить поиск", &["esc"]),
+    info(Action::Down, "down", Group::Navigation, "Вниз по списку", &["down"]),
+    info(Action::Up, "up", Group::Navigation, "Вверх по списку", &["up"]),
+    info(Action::First, "first", Group::Navigation, "В начало списка", &["home"]),
+    info(Action::Last, "last", Group::Navigation, "В конец списка", &["end"]),
+    info(
+        Action::MarkDown,
+        "mark-down",
+        Group::Navigation,
+        "Отметить файл и вниз",
+        &["ctrl+down"],
+    ),
+    info(
+        Action::MarkUp,
+        "mark-up",
+        Group::Navigation,
+        "Отметить файл и вверх",
+        &["ctrl+up"],
+    ),
+    info(
+        Action::Right,
+        "right",
+        Group::Navigation,
+        "Войти в папку / Добавить в плейлист",
+        &["right"],
+    ),
+    info(Action::Left, "left", Group::Navigation, "Выйти из папки", &["left"]),
+    info(Action::Add, "add", Group::Navigation, "Добавить в плейлист", &["enter"]),
+    info(
+        Action::Remove,
+        "remove",
+        Group::Navigation,
+        "Удалить из плейлиста",
+        &["delete"],
+    ),
+    info(
+        Action::PurgeBroken,
+        "purge-broken",
+        Group::Navigation,
+        "Убрать из плейлиста недоступные файлы",
+        &["shift+delete"],
+    ),
+    info(
+        Action::VolumeUp,
+        "volume-up",
+        Group::Volume,
+        "Увеличить громкость",
+        &["+"],
+    ),
+    info(
+        Action::VolumeDown,
+        "volume-down",
+        Group::Volume,
+        "Уменьшить громкость",
+        &["-"],
+    ),
+    info(
+        Action::Quit,
+        "quit",
+        Group::Application,
+        "Выйти из приложения",
+        &["ctrl+q"],
+    ),
+];
+
+// Добавки раскладки vim поверх стандартной
+const VIM_KEYS: &[(Action, &str)] = &[
+    (Action::Down, "j"),
+    (Action::Up, "k"),
+    (Action::Left, "h"),
+    (Action::Right, "l"),
+    (Action::First, "g g"),
+    (Action::Last, "G"),
+];
+
+/// Готовая раскладка: [keys] preset = "vim"
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(try_from = "String")]
+enum Preset {
+    #[default]
+    Default,
+    Vim,
+}
+
+impl TryFrom<String> for Preset {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "default" => Ok(Preset::Default),
+            "vim" => Ok(Preset::Vim),
+            other => Err(format!("неизвестная раскладка: {} (default, vim)", other)),
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ACTIONS
+            .iter()
+            .find(|info| info.name == s)
+            .map(|info| info.action)
+            .ok_or_else(|| format!("неизвестное действие: {}", s))
+    }
+}
+
+impl TryFrom<String> for Action {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = ACTIONS
+            .iter()
+            .find(|info| info.action == *self)
+            .map_or("?", |info| info.name);
+        write!(f, "{}", name)
+    }
+}
+
+/// Клавиша с модификаторами: "F2", "ctrl+q", "shift+tab", "N", "+"
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct KeyChord {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    // Регистр буквы уже говорит о Shift - его флаг у разных терминалов разный
+    fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let mut modifiers =
+            modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        if let KeyCode::Char(_) = code {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        KeyChord { code, modifiers }
+    }
+
+    pub fn from_event(key: &KeyEvent) -> Self {
+        KeyChord::new(key.code, key.modifiers)
+    }
+}
+
+impl FromStr for KeyChord {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // "+" и "ctrl++" - сама клавиша плюс
+        let (prefix, key) = if s == "+" || s.ends_with("++") {
+            (&s[..s.len() - 1], "+")
+        } else {
+            s.rsplit_once('+').unwrap_or(("", s))
+        };
+
+        let mut modifiers = KeyModifiers::NONE;
+        for modifier in prefix.split('+').filter(|m| !m.is_empty()) {
+            modifiers |= match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                other => return Err(format!("неизвестный модификатор: {}", other)),
+            };
+        }
+
+        let mut chars = key.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) if modifiers.contains(KeyModifiers::SHIFT) => {
+                KeyCode::Char(c.to_ascii_uppercase())
+            }
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match key.to_lowercase().as_str() {
+                "enter" | "return" => KeyCode::Enter,
+                "esc" | "escape" => KeyCode::Esc,
+                "tab" => KeyCode::Tab,
+                "backspace" => KeyCode::Backspace,
+                "delete" | "del" => KeyCode::Delete,
+                "insert" | "ins" => KeyCode::Insert,
+                "space" => KeyCode::Char(' '),
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                lower => match lower.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                    Some(n @ 1..=24) => KeyCode::F(n),
+                    _ => return Err(format!("неизвестная клавиша: {}", s)),
+                },
+            },
+        };
+        Ok(KeyChord::new(code, modifiers))
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, name) in [
+            (KeyModifiers::CONTROL, "Ctrl+"),
+            (KeyModifiers::ALT, "Alt+"),
+            (KeyModifiers::SHIFT, "Shift+"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{}", name)?;
+            }
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::F(n) => write!(f, "F{}", n),
+            KeyCode::Up => write!(f, "↑"),
+            KeyCode::Down => write!(f, "↓"),
+            KeyCode::Left => write!(f, "←"),
+            KeyCode::Right => write!(f, "→"),
+            KeyCode::PageUp => write!(f, "PageUp"),
+            KeyCode::PageDown => write!(f, "PageDown"),
+            other => write!(f, "{:?}", other),
+        }
+    }
+}
+
+/// Последовательность клавиш через пробел: "g g"
+fn parse_sequence(s: &str) -> Result<Vec<KeyChord>, String> {
+    let sequence = s
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<Vec<_>, _>>()?;
+    if sequence.is_empty() {
+        return Err("пустое назначение клавиши".to_string());
+    }
+    Ok(sequence)
+}
+
+fn format_sequence(sequence: &[KeyChord]) -> String {
+    sequence
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Клавиши действия в конфиге: одна строкой или списком
+struct Chords(Vec<Vec<KeyChord>>);
+
+impl<'de> Deserialize<'de> for Chords {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ChordsVisitor;
+
+        impl<'de> Visitor<'de> for ChordsVisitor {
+            type Value = Chords;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "клавиша или список клавиш")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Chords, E> {
+                parse_sequence(value)
+                    .map(|sequence| Chords(vec![sequence]))
+                    .map_err(E::custom)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Chords, A::Error> {
+                let mut sequences = Vec::new();
+                while let Some(value) = seq.next_element::<String>()? {
+                    sequences.push(parse_sequence(&value).map_err(de::Error::custom)?);
+                }
+                Ok(Chords(sequences))
+            }
+        }
+
+        deserializer.deserialize_any(ChordsVisitor)
+    }
+}
+
+// Ключ таблицы [keys]: выбор раскладки или действие
+#[derive(Deserialize)]
+#[serde(try_from = "String")]
+enum KeysEntry {
+    Preset,
+    Action(Action),
+}
+
+impl TryFrom<String> for KeysEntry {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        if value == "preset" {
+            return Ok(KeysEntry::Preset);
+        }
+        value.parse().map(KeysEntry::Action)
+    }
+}
+
+/// Раздел справки: описание действия и назначенные ему клавиши
+pub struct HelpEntry {
+    pub keys: String,
+    pub description: &'static str,
+}
+
+pub struct Keymap {
+    // В порядке назначения: так же идут клавиши в справке
+    bindings: Vec<(Vec<KeyChord>, Action)>,
+    // Начало последовательности, набранное до сих пор
+    pending: Vec<KeyChord>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap::new(Preset::Default, &[]).expect("раскладка по умолчанию")
+    }
+}
+
+// [keys] конфига: preset и действие = клавиша или список клавиш
+impl<'de> Deserialize<'de> for Keymap {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct KeymapVisitor;
+
+        impl<'de> Visitor<'de> for KeymapVisitor {
+            type Value = Keymap;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "таблица действие = клавиши")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Keymap, A::Error> {
+                let mut preset = Preset::Default;
+                let mut overrides = Vec::new();
+                while let Some(entry) = map.next_key::<KeysEntry>()? {
+                    match entry {
+                        KeysEntry::Preset => preset = map.next_value()?,
+                        KeysEntry::Action(action) => {
+                            let chords: Chords = map.next_value()?;
+                            overrides.push((action, chords.0));
+                        }
+                    }
+                }
+                Keymap::new(preset, &overrides).map_err(de::Error::custom)
+            }
+        }
+
+        deserializer.deserialize_map(KeymapVisitor)
+    }
+}
+
+impl Keymap {
+    // Стандартная раскладка и добавки пресета; назначение из конфига заменяет все
+    // клавиши действия и отбирает эти клавиши у других действий
+    fn new(preset: Preset, overrides: &[(Action, Vec<Vec<KeyChord>>)]) -> Result<Self, String> {
+        let mut bindings = Vec::new();
+        let vim: &[(Action, &str)] = match preset {
+            Preset::Default => &[],
+            Preset::Vim => VIM_KEYS,
+        };
+        let defaults = ACTIONS
+            .iter()
+            .flat_map(|info| info.keys.iter().map(|keys| (info.action, *keys)))
+            .chain(vim.iter().copied());
+        for (action, keys) in defaults {
+            if !overrides.iter().any(|(overridden, _)| *overridden == action) {
+                bindings.push((parse_sequence(keys)?, action));
+            }
+        }
+
+        for (action, sequences) in overrides {
+            for sequence in sequences {
+                if let Some((_, other)) = bindings
+                    .iter()
+                    .find(|(bound, other)| bound == sequence && other != action)
+                {
+                    if overrides.iter().any(|(overridden, _)| overridden == other) {
+                        return Err(format!(
+                            "keys.{} и keys.{}: клавиша {} назначена дважды",
+                            other,
+                            action,
+                            format_sequence(sequence)
+                        ));
+                    }
+                }
+                bindings.retain(|(bound, _)| bound != sequence);
+                bindings.push((sequence.clone(), *action));
+            }
+        }
+
+        // "g" сработала бы раньше, чем успели набрать "g g"
+        for (sequence, action) in &bindings {
+            if let Some((longer, other)) = bindings
+                .iter()
+                .find(|(longer, _)| longer.len() > sequence.len() && longer.starts_with(sequence))
+            {
+                return Err(format!(
+                    "клавиша {} ({}) закрывает последовательность {} ({})",
+                    format_sequence(sequence),
+                    action,
+                    format_sequence(longer),
+                    other
+                ));
+            }
+        }
+        Ok(Keymap {
+            bindings,
+            pending: Vec::new(),
+        })
+    }
+
+    /// Очередное нажатие. None - клавиша ничего не делает или начата последовательность
+    pub fn feed(&mut self, key: &KeyEvent) -> Option<Action> {
+        self.pending.push(KeyChord::from_event(key));
+        if let Some((_, action)) = self.bindings.iter().find(|(seq, _)| *seq == self.pending) {
+            self.pending.clear();
+            return Some(*action);
+        }
+        if self
+            .bindings
+            .iter()
+            .any(|(seq, _)| seq.starts_with(&self.pending))
+        {
+            return None;
+        }
+        // Последовательность оборвалась - пробуем нажатие само по себе
+        let interrupted = self.pending.len() > 1;
+        self.pending.clear();
+        if interrupted {
+            self.feed(key)
+        } else {
+            None
+        }
+    }
+
+    /// Набрана часть последовательности - для строки состояния
+    pub fn pending(&self) -> Option<String> {
+        (!self.pending.is_empty()).then(|| format_sequence(&self.pending))
+    }
+
+    /// Клавиши действия для подсказок, через запятую. None - действие не назначено
+    pub fn keys(&self, action: Action) -> Option<String> {
+        let keys: Vec<String> = self
+            .bindings
+            .iter()
+            .filter(|(_, bound)| *bound == action)
+            .map(|(sequence, _)| format_sequence(sequence))
+            .collect();
+        (!keys.is_empty()).then(|| keys.join(", "))
+    }
+
+    /// Справка по текущей раскладке: разделы по порядку, действия без клавиш пропущены
+    pub fn help(&self) -> Vec<(Group, Vec<HelpEntry>)> {
+        let mut groups: Vec<(Group, Vec<HelpEntry>)> = Vec::new();
+        for info in ACTIONS {
+            let Some(keys) = self.keys(info.action) else {
+                continue;
+            };
+            let entry = HelpEntry {
+                keys,
+                description: info.description,
+            };
+            match groups.last_mut() {
+                Some((group, entries)) if *group == info.group => entries.push(entry),
+                _ => groups.push((info.group, vec![entry])),
+            }
+        }
+        groups
+    }
+}
@@ -0,0 +1,348 @@
+// Музыкальная библиотека: индекс всех треков из корневых папок.
+// Индекс хранится на диске, пересканирование трогает только измененные файлы.
+mod scan;
+
+use crate::audio_engine::{StreamInfo, TrackInfo};
+use crate::paths;
+use crate::probe::FileStamp;
+use crate::tsv;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+const INDEX_FILE: &str = "library.tsv";
+const HEADER: &str = "# hi-res-player library v1";
+
+const UNKNOWN_ARTIST: &str = "Неизвестный исполнитель";
+const UNKNOWN_ALBUM: &str = "Без альбома";
+
+#[derive(Clone)]
+pub struct LibraryTrack {
+    pub path: PathBuf,
+    stamp: FileStamp,
+    pub info: TrackInfo,
+    /// None - файл не удалось прочитать
+    pub stream: Option<StreamInfo>,
+}
+
+impl LibraryTrack {
+    /// Название для списка: тег или имя файла
+    pub fn title(&self) -> String {
+        self.info.title.clone().unwrap_or_else(|| {
+            self.path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        })
+    }
+}
+
+pub struct AlbumNode {
+    pub title: String,
+    pub date: Option<String>,
+    /// Индексы в Library::tracks, по порядку диска и номера трека
+    pub tracks: Vec<usize>,
+}
+
+pub struct ArtistNode {
+    pub name: String,
+    pub albums: Vec<AlbumNode>,
+}
+
+/// Ход фонового сканирования
+#[derive(Clone, Copy, Default)]
+pub struct ScanProgress {
+    /// Найдено аудиофайлов
+    pub found: usize,
+    /// Сколько из них новых или измененных
+    pub to_probe: usize,
+    pub probed: usize,
+}
+
+/// Итог сканирования для строки состояния
+#[derive(Clone, Copy, Default)]
+pub struct ScanSummary {
+    pub total: usize,
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+}
+
+enum ScanEvent {
+    Progress(ScanProgress),
+    Finished {
+        tracks: Vec<LibraryTrack>,
+        summary: ScanSummary,
+    },
+}
+
+pub struct Library {
+    roots: Vec<PathBuf>,
+    tracks: Vec<LibraryTrack>,
+    artists: Vec<ArtistNode>,
+    index_path: Option<PathBuf>,
+    scan: Option<Receiver<ScanEvent>>,
+    progress: ScanProgress,
+}
+
+impl Library {
+    /// Загружает индекс. Явно заданные корни заменяют сохраненные;
+    /// если корней нет совсем - берем ~/Music
+    pub fn open(roots: Vec<PathBuf>) -> Self {
+        let index_path = paths::data_dir().map(|dir| dir.join(INDEX_FILE));
+        let (saved_roots, tracks) = index_path.as_deref().map(load_index).unwrap_or_default();
+
+        let mut roots = if roots.is_empty() { saved_roots } else { roots };
+        if roots.is_empty() {
+            if let Some(music) = std::env::var_os("HOME")
+                .map(|home| PathBuf::from(home).join("Music"))
+                .filter(|dir| dir.is_dir())
+            {
+                roots.push(music);
+            }
+        }
+        let roots = roots
+            .into_iter()
+            .map(|root| root.canonicalize().unwrap_or(root))
+            .collect();
+
+        let mut library = Library {
+            roots,
+            tracks,
+            artists: Vec::new(),
+            index_path,
+            scan: None,
+            progress: ScanProgress::default(),
+        };
+        library.rebuild_tree();
+        library
+    }
+
+    pub fn roots(&self) -> &[PathBuf] {
+        &self.roots
+    }
+
+    pub fn artists(&self) -> &[ArtistNode] {
+        &self.artists
+    }
+
+    pub fn track(&self, index: usize) -> &LibraryTrack {
+        &self.tracks[index]
+    }
+
+    pub fn track_count(&self) -> usize {
+        self.tracks.len()
+    }
+
+    /// Ход сканирования, если оно идет
+    pub fn scan_progress(&self) -> Option<ScanProgress> {
+        self.scan.as_ref().map(|_| self.progress)
+    }
+
+    /// Запускает инкрементальное сканирование в фоне; повторный вызов во время
+    /// сканирования ничего не делает
+    pub fn rescan(&mut self) {
+        if self.scan.is_some() || self.roots.is_empty() {
+            return;
+        }
+        let (event_tx, event_rx) = mpsc::channel();
+        let roots = self.roots.clone();
+        let previous = self
+            .tracks
+            .iter()
+            .map(|track| (track.path.clone(), track.clone()))
+            .collect();
+
+        thread::Builder::new()
+            .name("library-scan".into())
+            .spawn(move || scan::run(roots, previous, event_tx))
+            .expect("не удалось запустить поток сканирования");
+
+        self.scan = Some(event_rx);
+        self.progress = ScanProgress::default();
+    }
+
+    /// Забирает события сканирования; Some - сканирование закончилось,
+    /// индекс обновлен и сохранен
+    pub fn poll(&mut self) -> Option<io::Result<ScanSummary>> {
+        let scan = self.scan.as_ref()?;
+        let mut finished = None;
+        loop {
+            match scan.try_recv() {
+                Ok(ScanEvent::Progress(progress)) => self.progress = progress,
+                Ok(ScanEvent::Finished { tracks, summary }) => {
+                    finished = Some((tracks, summary));
+                    break;
+                }
+                Err(mpsc::TryRecvError::Empty) => break,
+                // Поток сканирования упал - индекс остается прежним
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.scan = None;
+                    return Some(Err(io::Error::other("сканирование библиотеки прервано")));
+                }
+            }
+        }
+
+        let (tracks, summary) = finished?;
+        self.scan = None;
+        self.tracks = tracks;
+        self.rebuild_tree();
+        Some(self.save().map(|_| summary))
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.index_path else {
+            return Ok(());
+        };
+        let roots = self
+            .roots
+            .iter()
+            .filter_map(|root| Some(format!("root\t{}", tsv::escape(root.to_str()?))));
+        let tracks = self.tracks.iter().filter_map(format_track);
+        tsv::write_file(path, HEADER, roots.chain(tracks))
+    }
+
+    // Группировка Исполнитель альбома -> Альбом -> Трек, без учета регистра
+    fn rebuild_tree(&mut self) {
+        let mut artists: HashMap<String, ArtistNode> = HashMap::new();
+        let mut albums: HashMap<(String, String), usize> = HashMap::new();
+
+        for (index, track) in self.tracks.iter().enumerate() {
+            let info = &track.info;
+            let artist = info
+                .album_artist
+                .as_deref()
+                .or(info.artist.as_deref())
+                .unwrap_or(UNKNOWN_ARTIST);
+            let album = info.album.as_deref().unwrap_or(UNKNOWN_ALBUM);
+            let artist_key = artist.to_lowercase();
+
+            let node = artists
+                .entry(artist_key.clone())
+                .or_insert_with(|| ArtistNode {
+                    name: artist.to_string(),
+                    albums: Vec::new(),
+                });
+            let album_index = *albums
+                .entry((artist_key, album.to_lowercase()))
+                .or_insert_with(|| {
+                    node.albums.push(AlbumNode {
+                        title: album.to_string(),
+                        date: info.date.clone(),
+                        tracks: Vec::new(),
+                    });
+                    node.albums.len() - 1
+                });
+            node.albums[album_index].tracks.push(index);
+        }
+
+        let tracks = &self.tracks;
+        let mut artists: Vec<ArtistNode> = artists.into_values().collect();
+        for artist in &mut artists {
+            artist
+                .albums
+                .sort_by_cached_key(|album| album.title.to_lowercase());
+            for album in &mut artist.albums {
+                album.tracks.sort_by(|&a, &b| {
+                    let (a, b) = (&tracks[a], &tracks[b]);
+                    (a.info.disc_number, a.info.track_number, &a.path).cmp(&(
+                        b.info.disc_number,
+                        b.info.track_number,
+                        &b.path,
+                    ))
+                });
+            }
+        }
+        artists.sort_by_cached_key(|artist| artist.name.to_lowercase());
+        self.artists = artists;
+    }
+}
+
+/// Все аудиофайлы папки и подпапок - тем же обходом, что и при сканировании
+pub fn audio_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    scan::walk(dir, &mut files);
+    files
+}
+
+fn load_index(path: &Path) -> (Vec<PathBuf>, Vec<LibraryTrack>) {
+    let mut roots = Vec::new();
+    let mut tracks = Vec::new();
+    let Ok(content) = fs::read_to_string(path) else {
+        return (roots, tracks);
+    };
+    let mut lines = content.lines();
+    if lines.next() != Some(HEADER) {
+        return (roots, tracks);
+    }
+    for line in lines {
+        match line.split_once('\t') {
+            Some(("root", root)) => roots.push(PathBuf::from(tsv::unescape(root))),
+            Some(("track", rest)) => tracks.extend(parse_track(rest)),
+            _ => {}
+        }
+    }
+    (roots, tracks)
+}
+
+// путь, mtime, размер, теги, затем кодек, частота, разрядность, каналы, битрейт, кадры
+const STREAM_FIELDS: usize = 6;
+
+fn format_track(track: &LibraryTrack) -> Option<String> {
+    let mut fields = vec![
+        "track".to_string(),
+        tsv::escape(track.path.to_str()?),
+        track.stamp.mtime_ns.to_string(),
+        track.stamp.size.to_string(),
+    ];
+    tsv::push_info(&mut fields, &track.info);
+    match &track.stream {
+        Some(stream) => fields.extend([
+            tsv::escape(&stream.codec),
+            stream.sample_rate.to_string(),
+            tsv::number(stream.bits_per_sample),
+            stream.channels.to_string(),
+            tsv::number(stream.bitrate),
+            tsv::number(stream.n_frames),
+        ]),
+        None => fields.extend(std::iter::repeat_n(String::new(), STREAM_FIELDS)),
+    }
+    Some(fields.join("\t"))
+}
+
+fn parse_track(line: &str) -> Option<LibraryTrack> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    if fields.len() != 3 + tsv::INFO_FIELDS + STREAM_FIELDS {
+        return None;
+    }
+    let stamp = FileStamp {
+        mtime_ns: fields[1].parse().ok()?,
+        size: fields[2].parse().ok()?,
+    };
+    let info = tsv::parse_info(&fields[3..3 + tsv::INFO_FIELDS]);
+
+    let s = &fields[3 + tsv::INFO_FIELDS..];
+    let stream = (!s[0].is_empty()).then(|| {
+        let sample_rate = s[1].parse().unwrap_or(0);
+        StreamInfo {
+            codec: tsv::unescape(s[0]),
+            sample_rate,
+            bits_per_sample: s[2].parse().ok(),
+            channels: s[3].parse().unwrap_or(0),
+            bitrate: s[4].parse().ok(),
+            n_frames: s[5].parse().ok(),
+            // Частоту PCM знает только открытый источник
+            pcm_rate: sample_rate,
+        }
+    });
+
+    Some(LibraryTrack {
+        path: PathBuf::from(tsv::unescape(fields[0])),
+        stamp,
+        info,
+        stream,
+    })
+}
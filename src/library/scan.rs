@@ -0,0 +1,143 @@
+// Обход корневых папок и проба новых или измененных файлов
+use super::{LibraryTrack, ScanEvent, ScanProgress, ScanSummary};
+use crate::audio_engine;
+use crate::probe::FileStamp;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Mutex;
+use std::thread;
+
+const MAX_WORKERS: usize = 4;
+// Как часто сообщать о ходе пробы
+const PROGRESS_EVERY: usize = 64;
+
+pub(super) fn run(
+    roots: Vec<PathBuf>,
+    mut previous: HashMap<PathBuf, LibraryTrack>,
+    events: Sender<ScanEvent>,
+) {
+    let mut progress = ScanProgress::default();
+    let mut tracks = Vec::new();
+    let mut to_probe = Vec::new();
+    let mut summary = ScanSummary::default();
+    let mut seen = HashSet::new();
+
+    for root in &roots {
+        // Отключенный диск - не повод выбрасывать его треки из индекса
+        if !root.is_dir() {
+            let (kept, rest): (HashMap<_, _>, HashMap<_, _>) = previous
+                .into_iter()
+                .partition(|(path, _)| path.starts_with(root));
+            tracks.extend(kept.into_values());
+            previous = rest;
+            continue;
+        }
+
+        let mut files = Vec::new();
+        walk(root, &mut files);
+        for path in files {
+            // Папки могут пересекаться - один файл индексируем один раз
+            if !seen.insert(path.clone()) {
+                continue;
+            }
+            progress.found += 1;
+            let Some(stamp) = FileStamp::of(&path) else {
+                continue;
+            };
+            match previous.remove(&path) {
+                Some(track) if track.stamp == stamp => tracks.push(track),
+                Some(_) => {
+                    summary.updated += 1;
+                    to_probe.push((path, stamp));
+                }
+                None => {
+                    summary.added += 1;
+                    to_probe.push((path, stamp));
+                }
+            }
+            if progress.found.is_multiple_of(PROGRESS_EVERY) {
+                let _ = events.send(ScanEvent::Progress(progress));
+            }
+        }
+    }
+    // Все, что осталось от старого индекса, на диске больше не найдено
+    summary.removed = previous.len();
+
+    progress.to_probe = to_probe.len();
+    let _ = events.send(ScanEvent::Progress(progress));
+    tracks.extend(probe_all(&to_probe, progress, &events));
+
+    summary.total = tracks.len();
+    let _ = events.send(ScanEvent::Finished { tracks, summary });
+}
+
+// Рекурсивный обход без перехода по ссылкам на папки - так не зациклимся
+pub(super) fn walk(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            walk(&path, files);
+        } else if audio_engine::is_supported_file(&path) {
+            files.push(path);
+        }
+    }
+}
+
+// Проба на нескольких потоках: упираемся в диск, а не в процессор
+fn probe_all(
+    to_probe: &[(PathBuf, FileStamp)],
+    progress: ScanProgress,
+    events: &Sender<ScanEvent>,
+) -> Vec<LibraryTrack> {
+    let next = AtomicUsize::new(0);
+    let done = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(to_probe.len()));
+    let workers = thread::available_parallelism()
+        .map(|n| n.get().min(MAX_WORKERS))
+        .unwrap_or(2);
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                let mut progress = progress;
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some((path, stamp)) = to_probe.get(i) else {
+                        return;
+                    };
+                    let probed = audio_engine::probe_track(path);
+                    let track = LibraryTrack {
+                        path: path.clone(),
+                        stamp: *stamp,
+                        info: probed
+                            .as_ref()
+                            .map(|(info, _)| info.clone())
+                            .unwrap_or_default(),
+                        stream: probed.map(|(_, stream)| stream),
+                    };
+                    results.lock().unwrap().push(track);
+
+                    let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+                    if done.is_multiple_of(PROGRESS_EVERY) {
+                        progress.probed = done;
+                        let _ = events.send(ScanEvent::Progress(progress));
+                    }
+                }
+            });
+        }
+    });
+
+    results.into_inner().unwrap()
+}
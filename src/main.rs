@@ -1,53 +1,122 @@
 mod audio_engine;
-use audio_engine::SymphoniaSource;
+mod config;
+mod cue;
+mod keymap;
+mod library;
+mod messages;
+mod paths;
+mod player;
+mod playlist_file;
+mod probe;
+mod relink;
+mod search;
+mod session;
+mod tsv;
+use audio_engine::{DsdRate, OpenOptions, StreamInfo, TrackInfo};
 use clap::Parser;
+use config::Config;
 use crossterm::{
-    event::{self, Event, KeyCode, KeyModifiers},
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyModifiers, MouseButton,
+        MouseEvent, MouseEventKind,
+    },
     // event::{self, Event, KeyCode},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
+use keymap::{Action, Group, Keymap};
+use library::{Library, ScanSummary};
+use messages::{Level, Messages};
+use player::{Command, OutputFormat, PlaybackMode, Player, PlayerState, Track};
+use playlist_file::PathStyle;
+use probe::Prober;
 use ratatui::{
     backend::CrosstermBackend,
     layout::{Constraint, Direction, Layout, Rect},
     style::{Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Clear, ListState, Paragraph},
+    widgets::{Block, Clear, ListState, Paragraph, Wrap},
     Terminal,
 };
-use rodio::{Decoder, OutputStream, Sink};
-use std::env;
+use search::Matcher;
+use session::{Session, SessionTrack};
+use std::collections::HashMap;
 use std::fs;
-use std::fs::File;
 use std::io;
-use std::io::BufReader;
 use std::path::{Path, PathBuf};
-use std::time::Duration;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::time::{Duration, Instant};
 
 // -------- цвета -------
 // Цветовая палитра приложения
 mod theme {
     use ratatui::style::Color;
+    use serde::Deserialize;
+    use std::sync::OnceLock;
+
+    static PALETTE: OnceLock<Palette> = OnceLock::new();
+
+    // Палитра задается в [theme] конфига, чего нет - берется отсюда
+    #[derive(Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct Palette {
+        // Основные цвета
+        #[serde(deserialize_with = "crate::config::color")]
+        pub background: Color, // глубокий темный
+        #[serde(deserialize_with = "crate::config::color")]
+        pub surface: Color, // поверхность
+
+        // Акцентные цвета
+        #[serde(deserialize_with = "crate::config::color")]
+        pub primary: Color, // рамки
+        #[serde(deserialize_with = "crate::config::color")]
+        pub secondary: Color, // папки
+        #[serde(deserialize_with = "crate::config::color")]
+        pub success: Color, // маркированные файлы
+        #[serde(deserialize_with = "crate::config::color")]
+        pub warning: Color, // текст файла под курсором
+        #[serde(deserialize_with = "crate::config::color")]
+        pub error: Color, // сообщения об ошибках
+
+        // Текст
+        #[serde(deserialize_with = "crate::config::color")]
+        pub text_primary: Color, // основной текст
+        #[serde(deserialize_with = "crate::config::color")]
+        pub text_secondary: Color, // второстепенный
+        #[serde(deserialize_with = "crate::config::color")]
+        pub text_disabled: Color, // отключенный
+
+        // Состояния
+        #[serde(deserialize_with = "crate::config::color")]
+        pub selected: Color, // выделенный
+    }
+
+    impl Default for Palette {
+        fn default() -> Self {
+            Palette {
+                background: Color::Rgb(53, 52, 54),
+                surface: Color::Rgb(53, 52, 54),
+                primary: Color::Rgb(190, 116, 190),
+                secondary: Color::Rgb(142, 89, 178),
+                success: Color::Rgb(252, 105, 153),
+                warning: Color::Rgb(190, 116, 190),
+                error: Color::Rgb(230, 92, 92),
+                text_primary: Color::Rgb(240, 240, 240),
+                text_secondary: Color::Rgb(160, 160, 160),
+                text_disabled: Color::Rgb(80, 80, 80),
+                selected: Color::Rgb(63, 62, 64),
+            }
+        }
+    }
+
+    /// Палитра задается один раз при запуске, до первой отрисовки
+    pub fn set(palette: Palette) {
+        let _ = PALETTE.set(palette);
+    }
 
-    // Основные цвета
-    pub const BACKGROUND: Color = Color::Rgb(53, 52, 54); // #0A0C0F - глубокий темный
-    pub const SURFACE: Color = Color::Rgb(53, 52, 54); // #14161C - поверхность
-
-    // Акцентные цвета
-    pub const PRIMARY: Color = Color::Rgb(190, 116, 190); // #00B8D4 - рамки
-    pub const SECONDARY: Color = Color::Rgb(142, 89, 178); // #6496FF - папки
-    pub const SUCCESS: Color = Color::Rgb(252, 105, 153); // #4CAF50 - маркированные файлы
-    pub const WARNING: Color = Color::Rgb(190, 116, 190); // #FFC107 - текст файла под курсором
-
-    // Текст
-    pub const TEXT_PRIMARY: Color = Color::Rgb(240, 240, 240); // #F0F0F0 - основной текст
-    pub const TEXT_SECONDARY: Color = Color::Rgb(160, 160, 160); // #B4B4BE - второстепенный
-    pub const TEXT_DISABLED: Color = Color::Rgb(80, 80, 80); // #64646E - отключенный
-
-    // Состояния
-    // pub const HOVER: Color = Color::Rgb(40, 42, 50);             // #282A32 - при наведении
-    pub const SELECTED: Color = Color::Rgb(63, 62, 64); // #1E2028 - выделенный
-                                                        // pub const ACTIVE: Color = Color::Rgb(0, 150, 200);           // #0096C8 - активный
+    pub fn palette() -> &'static Palette {
+        PALETTE.get_or_init(Palette::default)
+    }
 }
 
 // Стили для конкретных элементов
@@ -57,51 +126,51 @@ mod styles {
 
     // Панели
     pub fn active_panel() -> Style {
-        Style::default().fg(theme::PRIMARY)
+        Style::default().fg(theme::palette().primary)
     }
 
     pub fn inactive_panel() -> Style {
-        Style::default().fg(theme::TEXT_DISABLED)
+        Style::default().fg(theme::palette().text_disabled)
     }
 
     // Выделение
     pub fn highlight_active() -> Style {
-        Style::default().fg(theme::WARNING).bg(theme::SELECTED)
+        Style::default().fg(theme::palette().warning).bg(theme::palette().selected)
     }
 
     pub fn highlight_inactive() -> Style {
         Style::default()
-            .fg(theme::TEXT_DISABLED)
-            .bg(theme::BACKGROUND)
+            .fg(theme::palette().text_disabled)
+            .bg(theme::palette().background)
     }
 
     // Элементы
     pub fn folder() -> Style {
-        Style::default().fg(theme::SECONDARY)
+        Style::default().fg(theme::palette().secondary)
     }
 
     pub fn selected_file() -> Style {
-        Style::default().fg(theme::SUCCESS)
+        Style::default().fg(theme::palette().success)
     }
 
     pub fn playing_track() -> Style {
-        Style::default().fg(theme::SUCCESS)
+        Style::default().fg(theme::palette().success)
     }
 
     pub fn normal_file() -> Style {
-        Style::default().fg(theme::TEXT_SECONDARY)
+        Style::default().fg(theme::palette().text_secondary)
     }
     pub fn inactive_text() -> Style {
-        Style::default().fg(theme::TEXT_DISABLED) // Более тусклый цвет
+        Style::default().fg(theme::palette().text_disabled) // Более тусклый цвет
     }
 
     // Фоны
     pub fn background() -> Style {
-        Style::default().bg(theme::BACKGROUND)
+        Style::default().bg(theme::palette().background)
     }
 
     pub fn surface() -> Style {
-        Style::default().bg(theme::SURFACE)
+        Style::default().bg(theme::palette().surface)
     }
 }
 // ------------------------------------
@@ -112,6 +181,35 @@ mod styles {
 struct Cli {
     #[arg(help = "Начальная папка (опционально)")]
     folder: Option<String>,
+
+    #[arg(
+        long,
+        default_value_t = DsdRate::default(),
+        help = "Частота PCM для DSD: 88.2, 176.4 или 352.8 кГц"
+    )]
+    dsd_rate: DsdRate,
+
+    #[arg(
+        long,
+        help = "Строгий режим: остановиться на поврежденном пакете вместо пропуска"
+    )]
+    strict: bool,
+
+    #[arg(
+        long = "library",
+        value_name = "DIR",
+        help = "Корневая папка библиотеки, можно указать несколько раз (по умолчанию - сохраненные или ~/Music)"
+    )]
+    library_roots: Vec<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Режим воспроизведения: sequential, repeat-all, repeat-one или shuffle"
+    )]
+    mode: Option<PlaybackMode>,
+
+    #[arg(long, help = "Не восстанавливать прошлую сессию")]
+    no_restore: bool,
 }
 
 #[derive(Clone)]
@@ -120,26 +218,174 @@ struct FileEntry {
     is_dir: bool,
     name: String,
     selected: bool,
-    duration: Option<std::time::Duration>,
+    info: TrackInfo, // Теги и длительность
+}
+
+// Уровень просмотра библиотеки: Исполнитель альбома -> Альбом -> Трек
+#[derive(Clone, Copy)]
+enum LibraryLevel {
+    Artists,
+    Albums(usize),
+    Tracks(usize, usize),
 }
 
+// Фильтр панели по / : пока editing, нажатия идут в строку запроса
+struct Search {
+    panel: usize,
+    query: String,
+    editing: bool,
+}
+
+// Шаг перемотки F7/F8
+const SEEK_STEP: Duration = Duration::from_secs(10);
+// Как часто сохранять сессию, кроме выхода
+const SESSION_SAVE_EVERY: Duration = Duration::from_secs(30);
+// Два щелчка по одной строке быстрее этого - двойной щелчок
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+// На сколько строк сдвигает выделение один шаг колеса
+const WHEEL_STEP: usize = 3;
+
 struct PlaylistEntry {
     path: PathBuf,
+    // Трек CUE: часть образа альбома. None - файл целиком
+    span: Option<audio_engine::Span>,
     name: String,
-    playing: bool,                         // Добавляем флаг воспроизведения
-    duration: Option<std::time::Duration>, // Добавляем длительность
+    playing: bool,        // Добавляем флаг воспроизведения
+    info: TrackInfo,      // Теги и длительность
+    skipped_packets: u64, // Битые пакеты, пропущенные при проигрывании
+    // Файл не сыграть - строка остается серой, R ищет пропавшие в корневых папках
+    broken: Option<Broken>,
+    // Размер из плейлиста (#EXTBYT): по нему находится переехавший файл
+    size: Option<u64>,
+}
+
+// Почему строку плейлиста не сыграть
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Broken {
+    NotFound,
+    NoAccess,
+    Unsupported,
 }
-fn get_audio_duration(path: &Path) -> Option<std::time::Duration> {
-    match SymphoniaSource::new(path) {
-        Ok(source) => source.duration(),
-        Err(_) => None,
+
+impl Broken {
+    // Кодек известен только после пробы - до нее файл считается исправным
+    fn check(path: &Path, prober: &Prober) -> Option<Broken> {
+        match fs::File::open(path) {
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => Some(Broken::NoAccess),
+            Err(_) => Some(Broken::NotFound),
+            // Плейлист в плейлисте не играет, но и не сломан
+            Ok(_) if playlist_file::is_playlist(path) => None,
+            Ok(_) if !audio_engine::is_supported_file(path) || prober.is_unreadable(path) => {
+                Some(Broken::Unsupported)
+            }
+            Ok(_) => None,
+        }
+    }
+
+    fn reason(self) -> &'static str {
+        match self {
+            Broken::NotFound => "файл не найден",
+            Broken::NoAccess => "нет прав на чтение",
+            Broken::Unsupported => "формат или кодек не поддерживается",
+        }
+    }
+
+    // Вместо длительности в строке плейлиста
+    fn tag(self) -> &'static str {
+        match self {
+            Broken::NotFound => "[ нет ]",
+            Broken::NoAccess => "[права]",
+            Broken::Unsupported => "[кодек]",
+        }
     }
 }
 
+impl PlaylistEntry {
+    fn track(&self) -> Track {
+        Track {
+            path: self.path.clone(),
+            span: self.span,
+        }
+    }
+}
+
+// Строки плейлиста для аудиофайла: образ альбома с CUE раскрывается в треки.
+// Встроенный CUE берется из пробы; еще не пробованный образ раскроется,
+// когда придет ее результат (см. process_probe_results)
+fn file_entries(
+    path: &Path,
+    name: &str,
+    info: TrackInfo,
+    prober: &mut Prober,
+) -> Vec<PlaylistEntry> {
+    let embedded = prober.embedded_cue(path);
+    if let Some(tracks) = cue::for_image(path, embedded) {
+        return cue_entries(tracks, prober);
+    }
+    vec![PlaylistEntry {
+        path: path.to_path_buf(),
+        span: None,
+        name: name.to_string(),
+        playing: false,
+        info,
+        skipped_packets: 0,
+        broken: None,
+        size: None,
+    }]
+}
+
+// Треки CUE. Длительность последнего трека образа известна только по длине файла
+fn cue_entries(tracks: Vec<cue::VirtualTrack>, prober: &mut Prober) -> Vec<PlaylistEntry> {
+    tracks
+        .into_iter()
+        .map(|track| {
+            let file_name = track
+                .path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or("Unknown");
+            let name = match track.info.track_number {
+                Some(number) => format!("{} #{:02}", file_name, number),
+                None => file_name.to_string(),
+            };
+            let broken = Broken::check(&track.path, prober);
+            let mut info = track.info;
+            if let Some(file_info) = Some(&track.path)
+                .filter(|_| broken.is_none())
+                .and_then(|path| prober.lookup(path))
+            {
+                fill_duration(&mut info, track.span, &file_info);
+            }
+            PlaylistEntry {
+                path: track.path,
+                span: track.span,
+                name,
+                playing: false,
+                info,
+                skipped_packets: 0,
+                broken,
+                size: None,
+            }
+        })
+        .collect()
+}
+
+// Длительность трека CUE без конца - от его начала до конца файла
+fn fill_duration(info: &mut TrackInfo, span: Option<audio_engine::Span>, file_info: &TrackInfo) {
+    if info.duration.is_none() {
+        let start = span.map_or(Duration::ZERO, |span| span.start);
+        info.duration = file_info.duration.map(|total| total.saturating_sub(start));
+    }
+}
+// Название для списков: теги, если есть, иначе имя файла
+fn display_name(info: &TrackInfo, name: &str) -> String {
+    info.display_title().unwrap_or_else(|| name.to_string())
+}
+
 fn suppress_alsa_warnings() {
     unsafe {
         // Открываем /dev/null
-        let null_fd = libc::open("/dev/null\0".as_ptr() as *const i8, libc::O_WRONLY);
+        let null_fd = libc::open(c"/dev/null".as_ptr(), libc::O_WRONLY);
         if null_fd >= 0 {
             // Перенаправляем stderr в /dev/null
             libc::dup2(null_fd, 2); // 2 = stderr
@@ -169,23 +415,103 @@ struct App {
     current_dir: PathBuf,
     files: Vec<FileEntry>,
     playlist: Vec<PlaylistEntry>,
+    // Список "дальше" - копия очереди движка, играет раньше плейлиста
+    up_next: Vec<PlaylistEntry>,
     files_list_state: ListState,
     playlist_list_state: ListState,
     active_panel: usize,
-
-    // ЗАМЕНЯЕМ rodio поля на symphonia
-    sink: Option<rodio::Sink>,
-    _stream: Option<OutputStream>,
-
-    current_playlist_index: usize,
-    is_playing: bool,
-    current_playing_path: Option<PathBuf>,
+    // Левая колонка: файловый менеджер или библиотека
+    library_shown: bool,
+
+    library: Library,
+    library_level: LibraryLevel,
+    library_list_state: ListState,
+    // Выделение на верхних уровнях, чтобы Left возвращал на то же место
+    library_parents: Vec<usize>,
+    library_summary: Option<ScanSummary>,
+    search: Option<Search>,
+
+    // Воспроизведением управляет поток движка, здесь - только его отражение для UI
+    player: Player,
+    player_state: PlayerState,
+    volume: f32,
+    playback_mode: PlaybackMode,
+    // Теги и длительность читаются в фоне, см. process_probe_results
+    prober: Prober,
+    current_track_duration: Option<std::time::Duration>,
+    current_track_info: TrackInfo,
+    current_stream_info: Option<StreamInfo>,
+    output_format: Option<OutputFormat>,
+    current_playlist_index: Option<usize>,
+    current_track: Option<Track>,
+    // Сколько битых пакетов пропущено в текущем треке
+    current_skipped_packets: u64,
     current_playback_position: std::time::Duration,
-    playback_start_time: Option<std::time::Instant>,
     save_dialog: Option<SaveDialog>,
     show_help: bool,
     help_scroll: u16,
+    show_track_info: bool,
+    load_report: Option<LoadReport>,
+    // Журнал сообщений: свежие всплывают, L открывает весь
+    messages: Messages,
+    show_messages: bool,
+    messages_scroll: u16,
+    keymap: Keymap,
+    // Пути в сохраняемом плейлисте по умолчанию и дополнительные корневые папки
+    playlist_paths: PathStyle,
+    playlist_roots: Vec<PathBuf>,
+    // Идет поиск пропавших файлов - результат заберет process_relink
+    relink: Option<Receiver<relink::Relinked>>,
+    volume_step: f32,
+    show_hidden: bool,
+    // Последняя записанная сессия - неизменную не пишем повторно
+    saved_session: Option<Session>,
+    session_saved_at: Instant,
+    // Где что нарисовано в последнем кадре - для мыши
+    screen: ScreenAreas,
+    // Панель и строка прошлого щелчка: второй щелчок рядом по времени - двойной
+    last_click: Option<(Instant, usize, usize)>,
+    // Кнопка нажата на полосе прогресса - перетаскивание перематывает
+    seek_drag: bool,
 }
+
+/// Видимая часть списка: rows[y] - индекс строки в полном списке
+#[derive(Clone, Default)]
+struct ListArea {
+    area: Rect,
+    rows: Vec<usize>,
+}
+
+impl ListArea {
+    fn row_at(&self, x: u16, y: u16) -> Option<usize> {
+        if !contains(self.area, x, y) {
+            return None;
+        }
+        self.rows.get((y - self.area.y) as usize).copied()
+    }
+}
+
+#[derive(Clone, Default)]
+struct ScreenAreas {
+    // Файловый менеджер или библиотека - смотря что показано
+    left: ListArea,
+    playlist: ListArea,
+    status_icon: Rect,
+    progress: Rect,
+}
+
+fn contains(area: Rect, x: u16, y: u16) -> bool {
+    x >= area.x && x < area.x + area.width && y >= area.y && y < area.y + area.height
+}
+
+// Итог загрузки плейлиста из файлового менеджера или прошлой сессии - окно до первой клавиши.
+// Строки берутся из плейлиста при отрисовке: проба дописывает битые кодеки позже
+struct LoadReport {
+    name: String,
+    added: std::ops::Range<usize>,
+    error: Option<String>,
+}
+
 #[derive(Default)]
 struct SaveDialog {
     visible: bool,
@@ -194,92 +520,67 @@ struct SaveDialog {
     current_dir: PathBuf,
     files: Vec<FileEntry>, // Добавляем список файлов для навигации
     list_state: ListState,
+    path_style: PathStyle, // Tab - как записать пути
 }
 
-fn parse_m3u_file(path: &Path) -> Result<Vec<PlaylistEntry>, Box<dyn std::error::Error>> {
-    let content = fs::read_to_string(path)?;
+// Плейлист любого поддерживаемого формата. CUE и образы альбомов в плейлисте
+// раскрываются в треки; недоступные файлы остаются строками с причиной в broken.
+// roots - где еще искать файлы с путями от библиотеки
+fn parse_playlist_file(
+    path: &Path,
+    roots: &[PathBuf],
+    prober: &mut Prober,
+) -> Result<Vec<PlaylistEntry>, Box<dyn std::error::Error>> {
+    if playlist_file::Format::from_path(path) == Some(playlist_file::Format::Cue) {
+        return Ok(cue_entries(cue::load(path)?, prober));
+    }
     let mut entries = Vec::new();
-    let mut current_extinf: Option<String> = None;
-    let base_dir = path.parent().unwrap_or(Path::new("."));
-
-    for line in content.lines() {
-        let line = line.trim();
-
-        if line.is_empty() {
-            continue;
-        }
-
-        if line.starts_with("#EXTM3U") {
-            continue; // Пропускаем заголовок
-        }
-
-        if line.starts_with("#EXTINF:") {
-            current_extinf = Some(line.to_string());
-            continue;
-        }
-
-        // Это строка с путем к файлу
-        if !line.starts_with("#") {
-            let file_path = if Path::new(line).is_absolute() {
-                PathBuf::from(line)
-            } else {
-                base_dir.join(line)
-            };
-
-            if file_path.exists() {
-                let name = if let Some(extinf) = &current_extinf {
-                    // Парсим название из #EXTINF
-                    if let Some(comma_pos) = extinf.rfind(',') {
-                        extinf[comma_pos + 1..].to_string()
-                    } else {
-                        file_path
-                            .file_name()
-                            .and_then(|n| n.to_str())
-                            .unwrap_or("Unknown")
-                            .to_string()
-                    }
-                } else {
-                    file_path
-                        .file_name()
-                        .and_then(|n| n.to_str())
-                        .unwrap_or("Unknown")
-                        .to_string()
-                };
-
-                let duration = if file_path
-                    .extension()
-                    .map_or(false, |ext| is_audio_extension(ext))
-                {
-                    get_audio_duration(&file_path)
-                } else {
-                    None
-                };
-
-                entries.push(PlaylistEntry {
-                    path: file_path,
-                    name,
-                    playing: false,
-                    duration,
-                });
+    for item in playlist_file::read(path, roots)? {
+        let name = item.title.unwrap_or_else(|| {
+            item.path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or("Unknown")
+                .to_string()
+        });
+        let broken = Broken::check(&item.path, prober);
+        if broken.is_none() && audio_engine::is_supported_file(&item.path) {
+            let info = prober.lookup(&item.path).unwrap_or_default();
+            let mut file = file_entries(&item.path, &name, info, prober);
+            if let [entry] = &mut file[..] {
+                entry.size = item.size;
             }
-
-            current_extinf = None;
+            entries.extend(file);
+        } else {
+            entries.push(PlaylistEntry {
+                path: item.path,
+                span: None,
+                name,
+                playing: false,
+                info: TrackInfo {
+                    duration: item.duration,
+                    ..TrackInfo::default()
+                },
+                skipped_packets: 0,
+                broken,
+                size: item.size,
+            });
         }
     }
-
     Ok(entries)
 }
 
-// Вспомогательная функция для проверки аудио расширений
-fn is_audio_extension(ext: &std::ffi::OsStr) -> bool {
-    let audio_extensions = ["wav", "flac", "ogg", "aiff", "aif", "aifc", "m4a", "caf"];
-    ext.to_str()
-        .map(|ext| audio_extensions.contains(&ext.to_lowercase().as_str()))
-        .unwrap_or(false)
-}
-
 impl App {
-    fn new(start_dir: Option<String>) -> Result<Self, Box<dyn std::error::Error>> {
+    fn new(
+        start_dir: Option<String>,
+        options: OpenOptions,
+        mode: PlaybackMode,
+        library_roots: Vec<PathBuf>,
+        restore: bool,
+        config: Config,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let session = if restore { Session::load() } else { None };
+        let explicit_dir = start_dir.is_some();
         let (current_dir, initial_file) = if let Some(dir) = start_dir {
             let path = PathBuf::from(&dir);
 
@@ -306,6 +607,8 @@ impl App {
             } else {
                 return Err(format!("Путь не существует: {}", absolute_path.display()).into());
             }
+        } else if let Some(dir) = config.general.start_dir.filter(|dir| dir.is_dir()) {
+            (dir, None)
         } else {
             // По умолчанию - домашняя директория
             let home_dir = std::env::var("HOME")
@@ -316,121 +619,707 @@ impl App {
 
         // Канонизируем путь (убираем ../ и ./)
         let current_dir = current_dir.canonicalize().unwrap_or(current_dir);
+        // Папка из командной строки главнее сохраненной
+        let current_dir = session
+            .as_ref()
+            .and_then(|session| session.dir.clone())
+            .filter(|dir| !explicit_dir && dir.is_dir())
+            .unwrap_or(current_dir);
 
         let mut app = App {
             current_dir,
             files: Vec::new(),
             playlist: Vec::new(),
+            up_next: Vec::new(),
             files_list_state: ListState::default(),
             playlist_list_state: ListState::default(),
             active_panel: 0,
-            // current_source: None,  // ← НОВОЕ
-            sink: None,
-            _stream: None,
-            current_playlist_index: 0,
-            is_playing: false,
-            current_playing_path: None,
+            library_shown: false,
+            library: Library::open(library_roots),
+            library_level: LibraryLevel::Artists,
+            library_list_state: ListState::default(),
+            library_parents: Vec::new(),
+            library_summary: None,
+            search: None,
+            player: Player::spawn(options, mode, config.audio.buffer_size),
+            player_state: PlayerState::Stopped,
+            volume: 1.0,
+            playback_mode: mode,
+            prober: Prober::spawn(),
+            current_track_duration: None,
+            current_track_info: TrackInfo::default(),
+            current_stream_info: None,
+            output_format: None,
+            current_playlist_index: None,
+            current_track: None,
+            current_skipped_packets: 0,
             current_playback_position: std::time::Duration::ZERO,
-            playback_start_time: None,
             save_dialog: None,
             show_help: false,
             help_scroll: 0,
+            show_track_info: false,
+            load_report: None,
+            messages: Messages::new(config.general.log_file.as_deref()),
+            show_messages: false,
+            messages_scroll: 0,
+            keymap: config.keys,
+            playlist_paths: config.playlist.paths,
+            playlist_roots: config.playlist.roots,
+            relink: None,
+            volume_step: config.general.volume_step,
+            show_hidden: config.general.show_hidden,
+            saved_session: None,
+            session_saved_at: Instant::now(),
+            screen: ScreenAreas::default(),
+            last_click: None,
+            seek_drag: false,
         };
         app.load_directory()?;
+        if let Some(session) = session {
+            // Файл из командной строки заиграет сам - прошлый трек не открываем
+            app.restore_session(session, initial_file.is_none());
+        }
+        // Индекс уже загружен с диска, сканирование только догоняет изменения
+        app.library.rescan();
+        if !app.library.artists().is_empty() {
+            app.library_list_state.select(Some(0));
+        }
 
         // Если был передан файл - добавляем его в плейлист и начинаем воспроизведение
         // В методе new(), где добавляем начальный файл в плейлист:
         if let Some(file_path) = initial_file {
             if let Some(file_name) = file_path.file_name().and_then(|n| n.to_str()) {
-                let duration = get_audio_duration(&file_path);
-                app.playlist.push(PlaylistEntry {
-                    path: file_path.clone(),
-                    name: file_name.to_string(),
-                    playing: false,
-                    duration, // Добавляем длительность
-                });
-
-                // Начинаем воспроизведение
-                app.play()?;
+                let info = app.prober.lookup(&file_path).unwrap_or_default();
+                let first = app.playlist.len();
+                let entries = file_entries(&file_path, file_name, info, &mut app.prober);
+                app.playlist.extend(entries);
+
+                // Начинаем воспроизведение именно этой записи плейлиста
+                app.sync_queue();
+                app.player.send(Command::PlayIndex(first));
             }
         }
 
         Ok(app)
     }
 
-    // fn rewind_backward(&mut self) {
-    //     // ТОЛЬКО ВИЗУАЛЬНАЯ ПЕРЕМОТКА - не влияет на реальное воспроизведение
-    //     if self.is_playing {
-    //         let new_pos = self.current_playback_position.saturating_sub(Duration::from_secs(10));
-    //         self.current_playback_position = new_pos;
-    //         // ОБНОВЛЯЕМ ВРЕМЯ СТАРТА ДЛЯ КОРРЕКТНОГО ПРОДОЛЖЕНИЯ
-    //         if let Some(start_time) = self.playback_start_time {
-    //             self.playback_start_time = Some(start_time - (self.current_playback_position - new_pos));
-    //         }
-    //     }
-    // }
-    //
-    // fn rewind_forward(&mut self) {
-    //     // ТОЛЬКО ВИЗУАЛЬНАЯ ПЕРЕМОТКА
-    //     if self.is_playing {
-    //         if let Some(total) = self.get_current_track_duration() {
-    //             let new_pos = (self.current_playback_position + Duration::from_secs(10)).min(total);
-    //             self.current_playback_position = new_pos;
-    //             // ОБНОВЛЯЕМ ВРЕМЯ СТАРТА
-    //             if let Some(start_time) = self.playback_start_time {
-    //                 self.playback_start_time = Some(start_time - (self.current_playback_position - new_pos));
-    //             }
-    //         }
-    //     }
-    // }
-
-    // fn restart_playback_from_seek(&mut self, seek_time: Duration, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
-    //     // Останавливаем текущее воспроизведение
-    //     self.stop();
-    //
-    //     // СОЗДАЕМ ОДИН ИСТОЧНИК И ДЕЛАЕМ SEEK
-    //     let mut source = SymphoniaSource::new(path)?;
-    //     source.seek(seek_time)?;
-    //
-    //     // СОЗДАЕМ RODIO SINK
-    //     let (stream, stream_handle) = OutputStream::try_default()?;
-    //     let sink = Sink::try_new(&stream_handle)?;
-    //
-    //     // ИСПОЛЬЗУЕМ ТОТ ЖЕ ИСТОЧНИК ДЛЯ ВОСПРОИЗВЕДЕНИЯ
-    //     sink.append(source);
-    //     sink.play();
-    //
-    //     // ОБНОВЛЯЕМ СОСТОЯНИЕ - НЕ СОХРАНЯЕМ source отдельно!
-    //     self.current_source = None; // Больше не храним отдельно
-    //     self.sink = Some(sink);
-    //     self._stream = Some(stream);
-    //     self.current_playing_path = Some(path.to_path_buf());
-    //     self.is_playing = true;
-    //     self.current_playback_position = seek_time;
-    //     self.playback_start_time = Some(std::time::Instant::now());
-    //
-    //     self.update_playing_status();
-    //     Ok(())
-    // }
-    //
-    // ОБНОВЛЯЕМ update_playback_progress - ВОЗВРАЩАЕМ РАСЧЕТНОЕ ВРЕМЯ
-    fn update_playback_progress(&mut self) {
-        if self.is_playing {
-            if let Some(start_time) = self.playback_start_time {
-                // РАСЧЕТНОЕ ВРЕМЯ ОТ СТАРТА ВОСПРОИЗВЕДЕНИЯ
-                self.current_playback_position = start_time.elapsed();
-            }
-        }
-    }
-    //
-    // // УПРОЩАЕМ get_current_track_duration
-    // fn get_current_track_duration(&self) -> Option<Duration> {
-    //     if let Some(current_path) = &self.current_playing_path {
-    //         SymphoniaSource::new(current_path).ok()?.duration()
-    //     } else {
-    //         None
-    //     }
-    // }
+    // Плейлист, громкость и трек с позицией из прошлого запуска.
+    // resume - открыть прошлый трек на паузе
+    fn restore_session(&mut self, session: Session, resume: bool) {
+        self.volume = session.volume;
+        self.player.send(Command::SetVolume(self.volume));
+
+        // Теги треков CUE берем из разметки образа - по разу на образ
+        let mut images: HashMap<PathBuf, Vec<cue::VirtualTrack>> = HashMap::new();
+        for track in session.playlist {
+            // Пропавшие с диска файлы остаются строками с причиной, как в плейлисте
+            let broken = Broken::check(&track.path, &self.prober);
+            let mut info = if broken.is_none() && audio_engine::is_supported_file(&track.path) {
+                self.prober.lookup(&track.path).unwrap_or_default()
+            } else {
+                TrackInfo::default()
+            };
+            let mut span = track.span;
+            if let Some(saved) = track.span.filter(|_| broken.is_none()) {
+                // В сессии время округлено до миллисекунд - ищем трек по началу
+                let image_track = images
+                    .entry(track.path.clone())
+                    .or_insert_with(|| {
+                        let embedded = self.prober.embedded_cue(&track.path);
+                        cue::for_image(&track.path, embedded).unwrap_or_default()
+                    })
+                    .iter()
+                    .find(|image_track| {
+                        image_track
+                            .span
+                            .is_some_and(|s| s.start.as_millis() == saved.start.as_millis())
+                    });
+                let file_info = std::mem::take(&mut info);
+                if let Some(image_track) = image_track {
+                    span = image_track.span;
+                    info = image_track.info.clone();
+                }
+                fill_duration(&mut info, span, &file_info);
+            }
+            self.playlist.push(PlaylistEntry {
+                path: track.path,
+                span,
+                name: track.name,
+                playing: false,
+                info,
+                skipped_packets: 0,
+                broken,
+                size: None,
+            });
+        }
+        if self.playlist.is_empty() {
+            return;
+        }
+        let current = session
+            .current
+            .filter(|(index, _)| *index < self.playlist.len());
+        self.playlist_list_state
+            .select(Some(current.map_or(0, |(index, _)| index)));
+        self.sync_queue();
+
+        let broken = self.playlist.iter().filter(|e| e.broken.is_some()).count();
+        if broken > 0 {
+            self.messages.warning(format!(
+                "Прошлая сессия: недоступно {} из {}",
+                broken,
+                self.playlist.len()
+            ));
+            self.load_report = Some(LoadReport {
+                name: "прошлой сессии".to_string(),
+                added: 0..self.playlist.len(),
+                error: None,
+            });
+        }
+
+        // Пропавший трек открыть не выйдет - сессия начнется с остановки
+        if let (true, Some((index, position))) = (resume, current) {
+            if self.playlist[index].broken.is_none() {
+                self.player.send(Command::Cue(index, position));
+            }
+        }
+    }
+
+    fn session(&self) -> Session {
+        Session {
+            dir: Some(self.current_dir.clone()),
+            volume: self.volume,
+            playlist: self
+                .playlist
+                .iter()
+                .map(|entry| SessionTrack {
+                    path: entry.path.clone(),
+                    name: entry.name.clone(),
+                    span: entry.span,
+                })
+                .collect(),
+            current: self
+                .current_playlist_index
+                .map(|index| (index, self.current_playback_position)),
+        }
+    }
+
+    // При выходе и раз в SESSION_SAVE_EVERY - на случай, если процесс убьют
+    fn save_session(&mut self) -> io::Result<()> {
+        self.session_saved_at = Instant::now();
+        let session = self.session();
+        if self.saved_session.as_ref() == Some(&session) {
+            return Ok(());
+        }
+        session.save()?;
+        self.saved_session = Some(session);
+        Ok(())
+    }
+
+    // Действие по клавише из раскладки; выход обрабатывает главный цикл
+    fn perform(&mut self, action: Action) {
+        match action {
+            // Группа 1: Основное управление (F1-F4)
+            Action::Help => self.show_help(),
+            Action::Play => {
+                if let Err(e) = self.play() {
+                    self.messages
+                        .error(format!("Ошибка воспроизведения: {}", e));
+                }
+            }
+            Action::Pause => self.pause(),
+            Action::Stop => self.stop(),
+
+            // Группа 2: Навигация по трекам (F5-F8)
+            Action::PreviousTrack => {
+                if let Err(e) = self.previous_track() {
+                    self.messages
+                        .error(format!("Ошибка переключения трека: {}", e));
+                }
+            }
+            Action::NextTrack => {
+                if let Err(e) = self.next_track() {
+                    self.messages
+                        .error(format!("Ошибка переключения трека: {}", e));
+                }
+            }
+            Action::SeekBackward => self.rewind_backward(),
+            Action::SeekForward => self.rewind_forward(),
+            Action::SavePlaylist => {
+                if self.save_dialog.is_none() {
+                    if let Err(e) = self.show_save_dialog() {
+                        self.messages
+                            .error(format!("Ошибка открытия диалога сохранения: {}", e));
+                    }
+                } else {
+                    self.hide_save_dialog();
+                }
+            }
+            Action::Quit => {}
+            Action::SwitchPanel => self.switch_panel(),
+            Action::TrackInfo => self.toggle_track_info(),
+            Action::Messages => self.toggle_messages(),
+            Action::RescanLibrary => self.library.rescan(),
+            Action::CycleMode => self.cycle_playback_mode(),
+            Action::Search => self.start_search(),
+            Action::SearchNext => self.search_jump(true),
+            Action::SearchPrevious => self.search_jump(false),
+            Action::PlayNext | Action::Enqueue => {
+                if let Err(e) = self.queue_selection(action == Action::PlayNext) {
+                    self.messages
+                        .error(format!("Ошибка добавления в очередь: {}", e));
+                }
+            }
+            Action::ClearUpNext => self.clear_up_next(),
+            Action::Relink => self.relink_playlist(),
+            Action::Cancel => self.cancel_search(),
+
+            // Громкость
+            Action::VolumeUp => self.volume_up(),
+            Action::VolumeDown => self.volume_down(),
+
+            // Навигация и выделение
+            Action::Down => self.next_item(),
+            Action::Up => self.previous_item(),
+            Action::First => self.select_edge(false),
+            Action::Last => self.select_edge(true),
+            Action::MarkDown => {
+                self.toggle_current_selection();
+                self.next_item();
+            }
+            Action::MarkUp => {
+                self.toggle_current_selection();
+                self.previous_item();
+            }
+            Action::Right => {
+                if let Err(e) = self.handle_right_key() {
+                    self.messages.error(format!("Ошибка: {}", e));
+                }
+            }
+            Action::Left => {
+                if let Err(e) = self.leave_directory() {
+                    self.messages.error(format!("Ошибка: {}", e));
+                }
+            }
+
+            // Действия
+            Action::Add => {
+                if let Err(e) = self.add_to_playlist() {
+                    self.messages
+                        .error(format!("Ошибка добавления в плейлист: {}", e));
+                }
+            }
+            Action::Remove => self.remove_from_playlist(),
+            Action::PurgeBroken => self.purge_broken(),
+        }
+    }
+
+    // F7 - Перемотка назад
+    fn rewind_backward(&mut self) {
+        let target = self.current_playback_position.saturating_sub(SEEK_STEP);
+        self.seek_to(target);
+    }
+
+    // F8 - Перемотка вперед
+    fn rewind_forward(&mut self) {
+        let target = self.current_playback_position + SEEK_STEP;
+        self.seek_to(target);
+    }
+
+    // 0-9 - Переход к 0%..90% трека
+    fn seek_percent(&mut self, percent: f64) {
+        if let Some(total) = self.current_track_duration {
+            self.seek_to(total.mul_f64(percent.clamp(0.0, 1.0)));
+        }
+    }
+
+    // Перемотка на абсолютную позицию без пересоздания OutputStream
+    fn seek_to(&mut self, target: Duration) {
+        if self.current_track.is_none() {
+            return;
+        }
+
+        let target = match self.current_track_duration {
+            Some(total) => target.min(total),
+            None => target,
+        };
+        self.player.send(Command::Seek(target));
+        self.current_playback_position = target;
+    }
+
+    // Забираем события движка: смена трека, позиция, ошибки
+    fn process_player_events(&mut self) {
+        while let Some(event) = self.player.try_recv() {
+            match event {
+                player::Event::TrackStarted {
+                    index,
+                    track,
+                    mut info,
+                    stream,
+                } => {
+                    // У трека CUE теги образа - берем теги трека из плейлиста
+                    if track.span.is_some() {
+                        let known = index
+                            .and_then(|index| self.playlist.get(index))
+                            .into_iter()
+                            .chain(&self.up_next)
+                            .chain(&self.playlist)
+                            .find(|entry| entry.track() == track);
+                        if let Some(entry) = known {
+                            *info = TrackInfo {
+                                duration: info.duration,
+                                ..entry.info.clone()
+                            };
+                        }
+                    }
+                    self.current_playlist_index = index;
+                    self.current_track = Some(track);
+                    self.current_track_duration = info.duration;
+                    self.current_track_info = *info;
+                    self.current_stream_info = Some(stream);
+                    self.current_skipped_packets = 0;
+                    self.current_playback_position = Duration::ZERO;
+                    self.update_playing_status();
+                }
+                player::Event::OutputOpened(format) => {
+                    self.output_format = Some(format);
+                }
+                player::Event::Position(position) => {
+                    self.current_playback_position = position;
+                }
+                player::Event::PacketsSkipped(count) => {
+                    self.current_skipped_packets = count;
+                    if let Some(entry) = self
+                        .current_playlist_index
+                        .and_then(|index| self.playlist.get_mut(index))
+                    {
+                        entry.skipped_packets = count;
+                    }
+                }
+                player::Event::TrackEnded(track) => {
+                    // Последние кадры могли не успеть попасть в Position
+                    if self.current_track.as_ref() == Some(&track) {
+                        self.current_playback_position =
+                            self.current_track_duration.unwrap_or_default();
+                    }
+                }
+                player::Event::UpNextChanged(tracks) => self.set_up_next(tracks),
+                player::Event::StateChanged(state) => {
+                    self.player_state = state;
+                    if state == PlayerState::Stopped {
+                        self.current_playlist_index = None;
+                        self.current_track = None;
+                        self.current_track_duration = None;
+                        self.current_track_info = TrackInfo::default();
+                        self.current_stream_info = None;
+                        self.current_playback_position = Duration::ZERO;
+                        self.update_playing_status();
+                    }
+                }
+                player::Event::Error(e) => {
+                    self.messages
+                        .error(format!("Ошибка воспроизведения: {}", e));
+                }
+            }
+        }
+    }
+
+    // Раздаем готовые результаты пробы всем строкам с этим файлом
+    fn process_probe_results(&mut self) {
+        while let Some((path, probed)) = self.prober.try_recv() {
+            // Файл не разобрался - в плейлисте он серый, с причиной
+            let unreadable = probed.info.is_none();
+            let info = probed.info.unwrap_or_default();
+            for entry in self.files.iter_mut().filter(|e| e.path == path) {
+                entry.info = info.clone();
+            }
+            // У треков CUE свои теги - от файла им нужна только длительность
+            for entry in self
+                .playlist
+                .iter_mut()
+                .chain(&mut self.up_next)
+                .filter(|e| e.path == path)
+            {
+                if unreadable {
+                    entry.broken = Some(Broken::Unsupported);
+                }
+                match entry.span {
+                    Some(_) => fill_duration(&mut entry.info, entry.span, &info),
+                    None => entry.info = info.clone(),
+                }
+            }
+            if let Some(tracks) = probed
+                .cue
+                .and_then(|embedded| cue::for_image(&path, Some(embedded)))
+            {
+                self.expand_image(&path, tracks);
+            }
+        }
+    }
+
+    // Проба нашла в файле встроенный CUE: строки плейлиста с образом целиком
+    // становятся треками, а восстановленные треки образа получают его теги.
+    // Играющую строку не трогаем - движок ищет ее в очереди по пути и отрезку
+    fn expand_image(&mut self, path: &Path, tracks: Vec<cue::VirtualTrack>) {
+        let file_info = self.prober.lookup(path).unwrap_or_default();
+        let mut expanded = false;
+        // Новый индекс каждой старой строки: индексы за раскрытым образом сдвигаются
+        let mut moved = Vec::with_capacity(self.playlist.len() + 1);
+        for entry in std::mem::take(&mut self.playlist) {
+            moved.push(self.playlist.len());
+            if entry.path != path || entry.playing {
+                self.playlist.push(entry);
+                continue;
+            }
+            match entry.span {
+                None => {
+                    let entries = cue_entries(tracks.clone(), &mut self.prober);
+                    self.playlist.extend(entries);
+                    expanded = true;
+                }
+                Some(span) => {
+                    let mut entry = entry;
+                    let image_track = tracks.iter().find(|track| {
+                        track
+                            .span
+                            .is_some_and(|s| s.start.as_millis() == span.start.as_millis())
+                    });
+                    if let Some(image_track) = image_track {
+                        entry.info = image_track.info.clone();
+                        fill_duration(&mut entry.info, entry.span, &file_info);
+                    }
+                    self.playlist.push(entry);
+                }
+            }
+        }
+        if !expanded {
+            return;
+        }
+        let end = self.playlist.len();
+        moved.push(end);
+        let moved = |index: usize| moved.get(index).copied().unwrap_or(end);
+        self.current_playlist_index = self.current_playlist_index.map(moved);
+        let selection = self.playlist_list_state.selected().map(moved);
+        self.playlist_list_state.select(selection);
+        if let Some(report) = &mut self.load_report {
+            report.added = moved(report.added.start)..moved(report.added.end);
+        }
+        self.sync_queue();
+        self.sync_search_selection();
+    }
+
+    // Движок прислал новый список "дальше" - теги берем у уже известных записей
+    fn set_up_next(&mut self, tracks: Vec<Track>) {
+        let old = std::mem::take(&mut self.up_next);
+        for track in tracks {
+            let known = old
+                .iter()
+                .chain(&self.playlist)
+                .find(|entry| entry.track() == track)
+                .map(|entry| (entry.name.clone(), entry.info.clone()));
+            let (name, info) = known.unwrap_or_else(|| {
+                let name = track
+                    .path
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .unwrap_or("Unknown")
+                    .to_string();
+                (name, self.prober.lookup(&track.path).unwrap_or_default())
+            });
+            self.up_next.push(PlaylistEntry {
+                path: track.path,
+                span: track.span,
+                name,
+                playing: false,
+                info,
+                skipped_packets: 0,
+                broken: None,
+                size: None,
+            });
+        }
+    }
+
+    // Файлы под курсором активной панели: отмеченные или текущий файл,
+    // строка плейлиста, выделенное в библиотеке
+    fn selection_tracks(&mut self) -> Result<Vec<Track>, Box<dyn std::error::Error>> {
+        let mut entries = Vec::new();
+        match self.active_panel {
+            0 => {
+                let mut marked: Vec<FileEntry> = self
+                    .files
+                    .iter()
+                    .filter(|entry| entry.selected && !entry.is_dir)
+                    .cloned()
+                    .collect();
+                if marked.is_empty() {
+                    marked.extend(
+                        self.files_list_state
+                            .selected()
+                            .and_then(|selected| self.files.get(selected))
+                            .filter(|entry| !entry.is_dir)
+                            .cloned(),
+                    );
+                }
+                for entry in &mut self.files {
+                    entry.selected = false;
+                }
+                for file in marked {
+                    if playlist_file::is_playlist(&file.path) {
+                        let roots = self.search_roots();
+                        entries.extend(parse_playlist_file(&file.path, &roots, &mut self.prober)?);
+                    } else {
+                        entries.extend(file_entries(
+                            &file.path,
+                            &file.name,
+                            file.info,
+                            &mut self.prober,
+                        ));
+                    }
+                }
+            }
+            1 => {
+                return Ok(self
+                    .playlist_list_state
+                    .selected()
+                    .and_then(|selected| self.playlist.get(selected))
+                    .map(|entry| entry.track())
+                    .into_iter()
+                    .collect())
+            }
+            _ => {
+                for index in self.library_selected_tracks() {
+                    let track = self.library.track(index);
+                    let (path, info) = (track.path.clone(), track.info.clone());
+                    entries.extend(file_entries(&path, "", info, &mut self.prober));
+                }
+            }
+        }
+        Ok(entries
+            .iter()
+            .filter(|entry| entry.broken.is_none())
+            .map(PlaylistEntry::track)
+            .collect())
+    }
+
+    // A - сыграть выделенное следующим, a - добавить в конец списка "дальше"
+    fn queue_selection(&mut self, next: bool) -> Result<(), Box<dyn std::error::Error>> {
+        let tracks = self.selection_tracks()?;
+        if tracks.is_empty() {
+            return Ok(());
+        }
+        self.player.send(if next {
+            Command::QueueNext(tracks)
+        } else {
+            Command::QueueAppend(tracks)
+        });
+        Ok(())
+    }
+
+    // X - очистить список "дальше"
+    fn clear_up_next(&mut self) {
+        self.player.send(Command::ClearUpNext);
+    }
+
+    // Корневые папки: из [playlist] конфига и папки библиотеки
+    fn search_roots(&self) -> Vec<PathBuf> {
+        self.playlist_roots
+            .iter()
+            .chain(self.library.roots())
+            .cloned()
+            .collect()
+    }
+
+    // R - найти пропавшие файлы плейлиста в корневых папках по имени и размеру.
+    // Папки обходит отдельный поток, результат забирает process_relink
+    fn relink_playlist(&mut self) {
+        if self.relink.is_some() {
+            return;
+        }
+        let wanted: Vec<(PathBuf, Option<u64>)> = self
+            .playlist
+            .iter()
+            .filter(|entry| entry.broken == Some(Broken::NotFound))
+            .map(|entry| (entry.path.clone(), entry.size))
+            .collect();
+        if wanted.is_empty() {
+            return;
+        }
+        self.messages
+            .info(format!("Поиск пропавших файлов: {}", wanted.len()));
+        self.relink = Some(relink::spawn(wanted, self.search_roots()));
+    }
+
+    // Пока шел поиск, плейлист могли изменить - строки находим заново по старому пути
+    fn process_relink(&mut self) {
+        let Some(relink) = &self.relink else {
+            return;
+        };
+        let found = match relink.try_recv() {
+            Ok(found) => found,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => Vec::new(),
+        };
+        self.relink = None;
+
+        let wanted = found.len();
+        let mut relinked = 0;
+        for (old, path) in found {
+            let Some(path) = path else {
+                continue;
+            };
+            for entry in self
+                .playlist
+                .iter_mut()
+                .filter(|entry| entry.path == old && entry.broken == Some(Broken::NotFound))
+            {
+                if let Some(info) = self.prober.lookup(&path) {
+                    entry.info = info;
+                }
+                entry.broken = Broken::check(&path, &self.prober);
+                entry.path = path.clone();
+            }
+            relinked += 1;
+        }
+        if relinked > 0 {
+            self.sync_queue();
+            self.messages.info(format!(
+                "Найдено пропавших файлов: {} из {}",
+                relinked, wanted
+            ));
+        } else {
+            self.messages
+                .warning("Пропавшие файлы не найдены в папках библиотеки");
+        }
+    }
+
+    // Движок должен знать плейлист для автоперехода и gapless
+    fn sync_queue(&self) {
+        let queue = self.playlist.iter().map(PlaylistEntry::track).collect();
+        self.player.send(Command::LoadQueue(queue));
+    }
+
+    // i - Свойства текущего трека
+    fn toggle_track_info(&mut self) {
+        self.show_track_info = !self.show_track_info;
+    }
+
+    // L - Журнал сообщений, открывается на самых новых
+    fn toggle_messages(&mut self) {
+        self.show_messages = !self.show_messages;
+        self.messages_scroll = 0;
+    }
+
+    // Строка формата для статуса: поток файла и формат устройства вывода
+    fn stream_status(&self) -> Option<String> {
+        let stream = self.current_stream_info.as_ref()?;
+        let mut text = stream.to_string();
+        if let Some(output) = &self.output_format {
+            text.push_str(&format!(" | вывод: {}", output));
+            if output.sample_rate != stream.pcm_rate {
+                text.push_str(" (ресемплинг)");
+            }
+        }
+        Some(text)
+    }
 
     // F1 - Показать справку (заглушка)
     fn show_help(&mut self) {
@@ -447,6 +1336,7 @@ impl App {
             current_dir: current_dir.clone(),
             files: Vec::new(),
             list_state: ListState::default(),
+            path_style: self.playlist_paths,
         };
 
         // Загружаем файлы текущей директории
@@ -459,7 +1349,7 @@ impl App {
         self.save_dialog = None;
     }
 
-    fn save_playlist(&self) -> Result<(), Box<dyn std::error::Error>> {
+    fn save_playlist(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         if let Some(dialog) = &self.save_dialog {
             let path = if dialog.filename.starts_with('/')
                 || dialog.filename.starts_with('\\')
@@ -477,21 +1367,41 @@ impl App {
                 std::fs::create_dir_all(parent)?;
             }
 
-            let mut content = String::new();
-            content.push_str("#EXTM3U\n");
-
-            for entry in &self.playlist {
-                if let Some(duration) = entry.duration {
-                    let seconds = duration.as_secs();
-                    content.push_str(&format!("#EXTINF:{},{}\n", seconds, entry.name));
+            // Формат - по расширению имени. Треки CUE, идущие подряд в одном образе,
+            // сохраняются одной записью образа - при загрузке он раскроется снова
+            let mut items: Vec<playlist_file::Item> = Vec::new();
+            for (i, entry) in self.playlist.iter().enumerate() {
+                if let Some(span) = entry.span {
+                    let previous = i.checked_sub(1).map(|i| &self.playlist[i]);
+                    let continues = previous.is_some_and(|previous| {
+                        previous.path == entry.path
+                            && previous.span.is_some_and(|p| p.end == Some(span.start))
+                    });
+                    if continues {
+                        continue;
+                    }
+                    items.push(playlist_file::Item {
+                        path: entry.path.clone(),
+                        title: entry.info.album.clone(),
+                        duration: None,
+                        size: fs::metadata(&entry.path).ok().map(|m| m.len()),
+                    });
                 } else {
-                    content.push_str(&format!("#EXTINF:-1,{}\n", entry.name));
+                    items.push(playlist_file::Item {
+                        path: entry.path.clone(),
+                        title: Some(display_name(&entry.info, &entry.name)),
+                        duration: entry.info.duration,
+                        // Пропавший файл сохраняет размер, записанный раньше
+                        size: fs::metadata(&entry.path)
+                            .ok()
+                            .map(|m| m.len())
+                            .or(entry.size),
+                    });
                 }
-                content.push_str(&format!("{}\n", entry.path.display()));
             }
-
-            std::fs::write(&path, content)?;
-            // println!("✅ Плейлист сохранен: {}", path.display());
+            playlist_file::write(&path, &items, dialog.path_style, &self.search_roots())?;
+            self.messages
+                .info(format!("Плейлист сохранен: {}", path.display()));
         }
         Ok(())
     }
@@ -505,23 +1415,26 @@ impl App {
             match key.code {
                 KeyCode::Enter => {
                     if let Err(e) = self.save_playlist() {
-                        eprintln!("Ошибка сохранения: {}", e);
+                        self.messages.error(format!("Ошибка сохранения: {}", e));
                     }
                     self.hide_save_dialog();
                 }
                 KeyCode::Esc => {
                     self.hide_save_dialog();
                 }
+                KeyCode::Tab => {
+                    dialog.path_style = dialog.path_style.cycle();
+                }
                 KeyCode::Char(c) => {
                     // РАЗРЕШАЕМ ВСЕ СИМВОЛЫ - кириллица будет работать
-                    dialog.filename.insert(dialog.cursor_position, c);
+                    let at = dialog.cursor_byte();
+                    dialog.filename.insert(at, c);
                     dialog.cursor_position += 1;
                 }
-                KeyCode::Backspace => {
-                    if dialog.cursor_position > 0 {
-                        dialog.cursor_position -= 1;
-                        dialog.filename.remove(dialog.cursor_position);
-                    }
+                KeyCode::Backspace if dialog.cursor_position > 0 => {
+                    dialog.cursor_position -= 1;
+                    let at = dialog.cursor_byte();
+                    dialog.filename.remove(at);
                 }
                 KeyCode::Left => {
                     if key.modifiers.contains(KeyModifiers::CONTROL) {
@@ -543,7 +1456,7 @@ impl App {
                         dialog.enter_directory()?;
                     } else {
                         // Обычный Right
-                        if dialog.cursor_position < dialog.filename.len() {
+                        if dialog.cursor_position < dialog.filename.chars().count() {
                             dialog.cursor_position += 1;
                         }
                     }
@@ -553,7 +1466,7 @@ impl App {
                     dialog.cursor_position = 0;
                 }
                 KeyCode::End => {
-                    dialog.cursor_position = dialog.filename.len();
+                    dialog.cursor_position = dialog.filename.chars().count();
                 }
                 KeyCode::Down => {
                     // Навигация по файлам
@@ -582,112 +1495,82 @@ impl App {
     // F2 - Play
     fn play(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         // Если на паузе - продолжаем
-        if let Some(sink) = &self.sink {
-            if sink.is_paused() {
-                sink.play();
-                self.is_playing = true;
-                // println!("▶ Продолжено воспроизведение");
-                return Ok(());
-            }
+        if self.player_state == PlayerState::Paused {
+            self.player.send(Command::Play);
+            return Ok(());
         }
 
         // Иначе начинаем новое воспроизведение
-        // println!("▶ Запуск воспроизведения");
         self.start_playback()?;
         Ok(())
     }
 
     // F3 - Pause/Unpause
-    // ОБНОВЛЯЕМ ПРОГРЕСС В pause()
     fn pause(&mut self) {
-        if let Some(sink) = &self.sink {
-            if sink.is_paused() {
-                sink.play();
-                self.is_playing = true;
-                // ВОССТАНАВЛИВАЕМ ВРЕМЯ ПРИ СНЯТИИ ПАУЗЫ
-                if self.playback_start_time.is_none() {
-                    self.playback_start_time =
-                        Some(std::time::Instant::now() - self.current_playback_position);
-                }
-            } else {
-                sink.pause();
-                self.is_playing = false;
-                // СОХРАНЯЕМ ПОЗИЦИЮ ПРИ ПАУЗЕ
-                if let Some(_start_time) = self.playback_start_time {
-                    // ← добавляем _
-                    self.current_playback_position = _start_time.elapsed();
-                    self.playback_start_time = None;
-                }
-            }
+        match self.player_state {
+            PlayerState::Paused => self.player.send(Command::Play),
+            PlayerState::Playing => self.player.send(Command::Pause),
+            PlayerState::Stopped => {}
         }
     }
 
     // F4 - Stop
-    // ОБНОВЛЯЕМ ПРОГРЕСС В stop()
     fn stop(&mut self) {
-        if let Some(sink) = &self.sink {
-            sink.stop();
-        }
-        self.sink = None;
-        self._stream = None;
-        self.is_playing = false;
-        self.current_playing_path = None;
-        self.current_playback_position = std::time::Duration::ZERO;
-        self.playback_start_time = None;
-        self.update_playing_status();
+        self.player.send(Command::Stop);
     }
 
     fn load_directory(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         self.files.clear();
+        // Фильтр относился к прошлой папке
+        if self.search.as_ref().is_some_and(|search| search.panel == 0) {
+            self.search = None;
+        }
 
         let entries = fs::read_dir(&self.current_dir)?;
         let mut dirs = Vec::new();
         let mut audio_files = Vec::new();
 
-        for entry in entries {
-            if let Ok(entry) = entry {
-                let path = entry.path();
+        for entry in entries.flatten() {
+            let path = entry.path();
 
-                // Пропускаем скрытые файлы/папки
-                if let Some(file_name) = path.file_name().and_then(|n| n.to_str()) {
-                    if file_name.starts_with('.') {
-                        continue;
-                    }
+            // Пропускаем скрытые файлы/папки, если конфиг не просит их показать
+            if let Some(file_name) = path.file_name().and_then(|n| n.to_str()) {
+                if file_name.starts_with('.') && !self.show_hidden {
+                    continue;
                 }
+            }
 
-                let is_dir = path.is_dir();
+            let is_dir = path.is_dir();
 
-                if is_dir {
-                    dirs.push(FileEntry {
-                        path: path.clone(),
-                        is_dir: true,
-                        name: path
-                            .file_name()
-                            .and_then(|n| n.to_str())
-                            .map(|s| format!("{}/", s))
-                            .unwrap_or_else(|| "Unknown/".to_string()),
-                        selected: false,
-                        duration: None,
-                    });
-                } else if is_audio_file(&path) || path.extension().map_or(false, |ext| ext == "m3u")
-                {
-                    let duration = if path.extension().map_or(false, |ext| ext == "m3u") {
-                        None // У m3u файлов нет длительности
-                    } else {
-                        get_audio_duration(&path)
-                    };
-                    audio_files.push(FileEntry {
-                        path: path.clone(),
-                        is_dir: false,
-                        name: path
-                            .file_name()
-                            .and_then(|n| n.to_str())
-                            .unwrap_or("Unknown")
-                            .to_string(),
-                        selected: false,
-                        duration,
-                    });
-                }
+            if is_dir {
+                dirs.push(FileEntry {
+                    path: path.clone(),
+                    is_dir: true,
+                    name: path
+                        .file_name()
+                        .and_then(|n| n.to_str())
+                        .map(|s| format!("{}/", s))
+                        .unwrap_or_else(|| "Unknown/".to_string()),
+                    selected: false,
+                    info: TrackInfo::default(),
+                });
+            } else if audio_engine::is_supported_file(&path) || playlist_file::is_playlist(&path) {
+                let info = if playlist_file::is_playlist(&path) {
+                    TrackInfo::default() // У плейлистов нет тегов и длительности
+                } else {
+                    self.prober.lookup(&path).unwrap_or_default()
+                };
+                audio_files.push(FileEntry {
+                    path: path.clone(),
+                    is_dir: false,
+                    name: path
+                        .file_name()
+                        .and_then(|n| n.to_str())
+                        .unwrap_or("Unknown")
+                        .to_string(),
+                    selected: false,
+                    info,
+                });
             }
         }
 
@@ -707,6 +1590,10 @@ impl App {
     }
 
     fn next_item(&mut self) {
+        if let Some(rows) = self.search_rows(self.active_panel) {
+            self.step_search(&rows, true, false);
+            return;
+        }
         match self.active_panel {
             0 => {
                 if let Some(selected) = self.files_list_state.selected() {
@@ -726,11 +1613,44 @@ impl App {
                     self.playlist_list_state.select(Some(0));
                 }
             }
+            2 => {
+                let len = self.library_len();
+                if let Some(selected) = self.library_list_state.selected() {
+                    if selected + 1 < len {
+                        self.library_list_state.select(Some(selected + 1));
+                    }
+                } else if len > 0 {
+                    self.library_list_state.select(Some(0));
+                }
+            }
             _ => {}
         }
     }
 
+    // Первая или последняя видимая строка активной панели
+    fn select_edge(&mut self, last: bool) {
+        let panel = self.active_panel;
+        let rows = if panel == 2 {
+            (0..self.library_len()).collect()
+        } else {
+            self.visible_rows(panel)
+        };
+        let row = if last { rows.last() } else { rows.first() }.copied();
+        if row.is_none() {
+            return;
+        }
+        if panel == 2 {
+            self.library_list_state.select(row);
+        } else {
+            self.panel_list_state(panel).select(row);
+        }
+    }
+
     fn previous_item(&mut self) {
+        if let Some(rows) = self.search_rows(self.active_panel) {
+            self.step_search(&rows, false, false);
+            return;
+        }
         match self.active_panel {
             0 => {
                 if let Some(selected) = self.files_list_state.selected() {
@@ -746,11 +1666,21 @@ impl App {
                     }
                 }
             }
+            2 => {
+                if let Some(selected) = self.library_list_state.selected() {
+                    if selected > 0 {
+                        self.library_list_state.select(Some(selected - 1));
+                    }
+                }
+            }
             _ => {}
         }
     }
 
     fn leave_directory(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.active_panel == 2 {
+            self.library_leave();
+        }
         if self.active_panel == 0 {
             if let Some(parent) = self.current_dir.parent() {
                 self.current_dir = parent.to_path_buf();
@@ -782,20 +1712,18 @@ impl App {
                 .collect();
 
             for file in selected_files {
-                if file.path.extension().map_or(false, |ext| ext == "m3u") {
-                    // Если это M3U файл - парсим его
-                    let m3u_entries = parse_m3u_file(&file.path)?;
-                    for m3u_entry in m3u_entries {
-                        self.playlist.push(m3u_entry);
+                if playlist_file::is_playlist(&file.path) {
+                    // Если это плейлист - парсим его
+                    let roots = self.search_roots();
+                    let playlist_entries =
+                        parse_playlist_file(&file.path, &roots, &mut self.prober)?;
+                    for playlist_entry in playlist_entries {
+                        self.playlist.push(playlist_entry);
                     }
                 } else {
-                    // Обычный аудиофайл
-                    self.playlist.push(PlaylistEntry {
-                        path: file.path.clone(),
-                        name: file.name.clone(),
-                        playing: false,
-                        duration: file.duration,
-                    });
+                    // Обычный аудиофайл или образ альбома
+                    let entries = file_entries(&file.path, &file.name, file.info, &mut self.prober);
+                    self.playlist.extend(entries);
                 }
             }
 
@@ -803,11 +1731,15 @@ impl App {
             for entry in &mut self.files {
                 entry.selected = false;
             }
+            self.sync_queue();
         }
         Ok(())
     }
 
     fn handle_right_key(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.active_panel == 2 {
+            self.library_enter();
+        }
         if self.active_panel == 0 {
             if let Some(selected) = self.files_list_state.selected() {
                 if let Some(entry) = self.files.get(selected) {
@@ -815,16 +1747,15 @@ impl App {
                         // Вход в папку
                         self.current_dir = entry.path.clone();
                         self.load_directory()?;
-                    } else if entry.path.extension().map_or(false, |ext| ext == "m3u") {
-                        // Если это M3U файл - добавляем все треки из плейлиста
-                        let m3u_entries = parse_m3u_file(&entry.path)?;
-                        for m3u_entry in m3u_entries {
-                            self.playlist.push(m3u_entry);
-                        }
+                    } else if playlist_file::is_playlist(&entry.path) {
+                        // Если это плейлист - добавляем все треки из плейлиста
+                        let path = entry.path.clone();
+                        self.load_playlist_file(&path);
                     } else {
                         // Перемещение выделенных файлов в плейлист
                         if let Err(e) = self.move_selected_to_playlist() {
-                            eprintln!("Ошибка при добавлении в плейлист: {}", e);
+                            self.messages
+                                .error(format!("Ошибка при добавлении в плейлист: {}", e));
                         }
                     }
                 }
@@ -834,24 +1765,24 @@ impl App {
     }
 
     fn add_to_playlist(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.active_panel == 2 {
+            self.add_library_selection();
+        }
         if self.active_panel == 0 {
             if let Some(selected) = self.files_list_state.selected() {
                 if let Some(entry) = self.files.get(selected) {
                     if !entry.is_dir {
-                        if entry.path.extension().map_or(false, |ext| ext == "m3u") {
-                            // Если это M3U файл - парсим его
-                            let m3u_entries = parse_m3u_file(&entry.path)?;
-                            for m3u_entry in m3u_entries {
-                                self.playlist.push(m3u_entry);
-                            }
+                        if playlist_file::is_playlist(&entry.path) {
+                            // Если это плейлист - парсим его
+                            let path = entry.path.clone();
+                            self.load_playlist_file(&path);
                         } else {
-                            // Обычный аудиофайл
-                            self.playlist.push(PlaylistEntry {
-                                path: entry.path.clone(),
-                                name: entry.name.clone(),
-                                playing: false,
-                                duration: entry.duration,
-                            });
+                            // Обычный аудиофайл или образ альбома
+                            let (path, name, info) =
+                                (entry.path.clone(), entry.name.clone(), entry.info.clone());
+                            let entries = file_entries(&path, &name, info, &mut self.prober);
+                            self.playlist.extend(entries);
+                            self.sync_queue();
                         }
                     }
                 }
@@ -860,11 +1791,91 @@ impl App {
         Ok(())
     }
 
+    // Треки плейлиста-файла - в конец плейлиста; что добавилось и что нет - в окне итога
+    fn load_playlist_file(&mut self, path: &Path) {
+        let roots = self.search_roots();
+        let start = self.playlist.len();
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let error = match parse_playlist_file(path, &roots, &mut self.prober) {
+            Ok(entries) => {
+                let broken = entries.iter().filter(|e| e.broken.is_some()).count();
+                if broken > 0 {
+                    self.messages.warning(format!(
+                        "{}: недоступно {} из {}",
+                        name,
+                        broken,
+                        entries.len()
+                    ));
+                }
+                self.playlist.extend(entries);
+                self.sync_queue();
+                None
+            }
+            Err(e) => {
+                self.messages
+                    .error(format!("Плейлист {} не прочитан: {}", name, e));
+                Some(e.to_string())
+            }
+        };
+        self.load_report = Some(LoadReport {
+            name,
+            added: start..self.playlist.len(),
+            error,
+        });
+    }
+
+    // Shift+Delete - убрать из плейлиста все строки, которые не сыграть
+    fn purge_broken(&mut self) {
+        let before = self.playlist.len();
+        let selected = self.playlist_list_state.selected();
+        let mut kept = 0;
+        // Индексы текущего трека и выделения сдвигаются на число убранных перед ними
+        let mut current = None;
+        let mut selection = None;
+        for (i, entry) in std::mem::take(&mut self.playlist).into_iter().enumerate() {
+            if Some(i) == selected {
+                selection = Some(kept);
+            }
+            if entry.broken.is_some() {
+                continue;
+            }
+            if Some(i) == self.current_playlist_index {
+                current = Some(kept);
+            }
+            self.playlist.push(entry);
+            kept += 1;
+        }
+        if kept == before {
+            return;
+        }
+        self.messages
+            .info(format!("Убрано недоступных строк: {}", before - kept));
+        self.current_playlist_index = current;
+        self.update_playing_status();
+        self.sync_queue();
+        let last = self.playlist.len().checked_sub(1);
+        self.playlist_list_state
+            .select(selection.zip(last).map(|(index, last)| index.min(last)));
+        self.sync_search_selection();
+    }
+
     fn remove_from_playlist(&mut self) {
         if self.active_panel == 1 {
             if let Some(selected) = self.playlist_list_state.selected() {
                 if selected < self.playlist.len() {
                     let _removed = self.playlist.remove(selected);
+                    self.sync_queue();
+
+                    // Движок сам найдет текущий трек в новой очереди, здесь - только подсветка
+                    self.current_playlist_index = match self.current_playlist_index {
+                        Some(index) if index == selected => None,
+                        Some(index) if index > selected => Some(index - 1),
+                        other => other,
+                    };
+                    self.update_playing_status();
 
                     // Обновляем выделение
                     if self.playlist.is_empty() {
@@ -873,6 +1884,7 @@ impl App {
                         self.playlist_list_state
                             .select(Some(self.playlist.len() - 1));
                     }
+                    self.sync_search_selection();
                 }
             }
         }
@@ -880,120 +1892,522 @@ impl App {
 
     // Увеличение громкости
     fn volume_up(&mut self) {
-        if let Some(sink) = &self.sink {
-            let new_volume = (sink.volume() + 0.1).min(1.0);
-            sink.set_volume(new_volume);
-            // println!("🔊 Громкость: {:.0}%", new_volume * 100.0);
-        }
+        self.volume = (self.volume + self.volume_step).min(1.0);
+        self.player.send(Command::SetVolume(self.volume));
     }
 
     // Уменьшение громкости
     fn volume_down(&mut self) {
-        if let Some(sink) = &self.sink {
-            let new_volume = (sink.volume() - 0.1).max(0.0);
-            sink.set_volume(new_volume);
-            // println!("🔈 Громкость: {:.0}%", new_volume * 100.0);
+        self.volume = (self.volume - self.volume_step).max(0.0);
+        self.player.send(Command::SetVolume(self.volume));
+    }
+    // m - следующий режим воспроизведения
+    fn cycle_playback_mode(&mut self) {
+        self.playback_mode = self.playback_mode.cycle();
+        self.player.send(Command::SetMode(self.playback_mode));
+    }
+
+    fn handle_mouse(&mut self, mouse: MouseEvent) {
+        let (x, y) = (mouse.column, mouse.row);
+        // Поверх панелей открыто окно: колесо листает справку или журнал, остальное не нужно
+        let scroll = if self.show_help {
+            Some(&mut self.help_scroll)
+        } else if self.show_messages {
+            Some(&mut self.messages_scroll)
+        } else {
+            None
+        };
+        if let Some(scroll) = scroll {
+            match mouse.kind {
+                MouseEventKind::ScrollDown => *scroll = scroll.saturating_add(3),
+                MouseEventKind::ScrollUp => *scroll = scroll.saturating_sub(3),
+                _ => {}
+            }
+            return;
+        }
+        if self.show_track_info || self.load_report.is_some() || self.is_search_editing() {
+            return;
+        }
+
+        match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                if contains(self.screen.status_icon, x, y) {
+                    self.pause();
+                } else if contains(self.screen.progress, x, y) {
+                    self.seek_drag = true;
+                    self.seek_to_column(x);
+                } else if let Some((panel, row)) = self.row_at(x, y) {
+                    self.click_row(panel, row);
+                }
+            }
+            MouseEventKind::Drag(MouseButton::Left) if self.seek_drag => self.seek_to_column(x),
+            MouseEventKind::Up(MouseButton::Left) => self.seek_drag = false,
+            MouseEventKind::ScrollDown | MouseEventKind::ScrollUp => {
+                if let Some(panel) = self.panel_at(x, y) {
+                    self.scroll_panel(panel, mouse.kind == MouseEventKind::ScrollDown);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Левая колонка - файловый менеджер (0) или библиотека (2)
+    fn panel_at(&self, x: u16, y: u16) -> Option<usize> {
+        if contains(self.screen.left.area, x, y) {
+            Some(if self.library_shown { 2 } else { 0 })
+        } else if contains(self.screen.playlist.area, x, y) {
+            Some(1)
+        } else {
+            None
+        }
+    }
+
+    fn row_at(&self, x: u16, y: u16) -> Option<(usize, usize)> {
+        let panel = self.panel_at(x, y)?;
+        let list = if panel == 1 {
+            &self.screen.playlist
+        } else {
+            &self.screen.left
+        };
+        Some((panel, list.row_at(x, y)?))
+    }
+
+    // Щелчок выделяет строку, второй щелчок по ней же - как Enter/Right
+    fn click_row(&mut self, panel: usize, row: usize) {
+        let double = self.last_click.is_some_and(|(at, last_panel, last_row)| {
+            last_panel == panel && last_row == row && at.elapsed() < DOUBLE_CLICK
+        });
+        self.last_click = (!double).then(|| (Instant::now(), panel, row));
+
+        self.active_panel = panel;
+        if panel == 2 {
+            self.library_list_state.select(Some(row));
+        } else {
+            self.panel_list_state(panel).select(Some(row));
+        }
+        if double {
+            if let Err(e) = self.activate_selection() {
+                self.messages
+                    .error(format!("Ошибка воспроизведения: {}", e));
+            }
+        }
+    }
+
+    // Двойной щелчок: папку или узел библиотеки открыть, трек запустить
+    fn activate_selection(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        match self.active_panel {
+            0 => {
+                let is_dir = self
+                    .files_list_state
+                    .selected()
+                    .and_then(|selected| self.files.get(selected))
+                    .is_some_and(|entry| entry.is_dir);
+                if is_dir {
+                    self.handle_right_key()
+                } else {
+                    self.start_playback()
+                }
+            }
+            2 if !matches!(self.library_level, LibraryLevel::Tracks(..)) => {
+                self.library_enter();
+                Ok(())
+            }
+            _ => self.start_playback(),
         }
     }
+
+    // Колесо двигает выделение панели под курсором, не делая ее активной
+    fn scroll_panel(&mut self, panel: usize, down: bool) {
+        let active = std::mem::replace(&mut self.active_panel, panel);
+        for _ in 0..WHEEL_STEP {
+            if down {
+                self.next_item();
+            } else {
+                self.previous_item();
+            }
+        }
+        self.active_panel = active;
+    }
+
+    // Позиция на полосе прогресса - доля трека; при перетаскивании за край - край
+    fn seek_to_column(&mut self, x: u16) {
+        let bar = self.screen.progress;
+        if bar.width == 0 {
+            return;
+        }
+        let offset = x.saturating_sub(bar.x).min(bar.width - 1);
+        self.seek_percent(offset as f64 / (bar.width - 1).max(1) as f64);
+    }
+
+    // Tab: файлы -> плейлист -> библиотека; библиотека занимает место файлового менеджера
     fn switch_panel(&mut self) {
-        self.active_panel = (self.active_panel + 1) % 2;
+        self.active_panel = (self.active_panel + 1) % 3;
+        match self.active_panel {
+            0 => self.library_shown = false,
+            2 => self.library_shown = true,
+            _ => {}
+        }
     }
 
     // Переименовываем старый метод play в start_playback
     fn start_playback(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        self.stop();
-
-        let file_to_play = match self.active_panel {
+        match self.active_panel {
             0 => {
-                if let Some(selected) = self.files_list_state.selected() {
+                let file_to_play = self.files_list_state.selected().and_then(|selected| {
                     self.files
                         .get(selected)
                         .filter(|entry| !entry.is_dir)
                         .map(|entry| entry.path.clone())
-                } else {
-                    None
+                });
+                if let Some(path) = file_to_play {
+                    self.player.send(Command::PlayFile(Track::file(path)));
                 }
             }
             1 => {
                 if let Some(selected) = self.playlist_list_state.selected() {
-                    self.playlist.get(selected).map(|entry| entry.path.clone())
-                } else {
-                    None
+                    self.player.send(Command::PlayIndex(selected));
                 }
             }
-            _ => None,
+            // Трек играет сразу, альбом или исполнитель сначала попадают в плейлист
+            2 => match self.library_level {
+                LibraryLevel::Tracks(..) => {
+                    if let Some(&track) = self.library_selected_tracks().first() {
+                        let path = self.library.track(track).path.clone();
+                        self.player.send(Command::PlayFile(Track::file(path)));
+                    }
+                }
+                _ => {
+                    if let Some(first) = self.add_library_selection() {
+                        self.player.send(Command::PlayIndex(first));
+                    }
+                }
+            },
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn next_track(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.player.send(Command::Next);
+        Ok(())
+    }
+
+    fn previous_track(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.player.send(Command::Prev);
+        Ok(())
+    }
+
+    // / - фильтр файлов или плейлиста
+    fn start_search(&mut self) {
+        if matches!(self.active_panel, 0 | 1) {
+            self.search = Some(Search {
+                panel: self.active_panel,
+                query: String::new(),
+                editing: true,
+            });
+        }
+    }
+
+    fn is_search_editing(&self) -> bool {
+        self.search.as_ref().is_some_and(|search| search.editing)
+    }
+
+    // Ввод запроса: список фильтруется на каждом нажатии
+    fn handle_search_input(&mut self, key: event::KeyEvent) {
+        let Some(search) = &mut self.search else {
+            return;
+        };
+        match key.code {
+            KeyCode::Char(c) => search.query.push(c),
+            KeyCode::Backspace => {
+                search.query.pop();
+            }
+            KeyCode::Enter => search.editing = false,
+            KeyCode::Esc => {
+                self.cancel_search();
+                return;
+            }
+            KeyCode::Down => self.next_item(),
+            KeyCode::Up => self.previous_item(),
+            _ => {}
+        }
+        self.sync_search_selection();
+    }
+
+    // Esc - полный список; выделение остается на найденном элементе
+    fn cancel_search(&mut self) {
+        self.search = None;
+    }
+
+    // n/N - к следующему/предыдущему совпадению по кругу
+    fn search_jump(&mut self, forward: bool) {
+        let panel = self.search.as_ref().map(|search| search.panel);
+        if panel != Some(self.active_panel) {
+            return;
+        }
+        if let Some(rows) = self.search_rows(self.active_panel) {
+            self.step_search(&rows, forward, true);
+        }
+    }
+
+    // Индексы строк панели, подходящих под запрос; None - фильтра нет
+    fn search_rows(&self, panel: usize) -> Option<Vec<usize>> {
+        let search = self
+            .search
+            .as_ref()
+            .filter(|search| search.panel == panel)?;
+        let matcher = Matcher::new(&search.query);
+        if matcher.is_empty() {
+            return None;
+        }
+        let fields = |name: &str, info: &TrackInfo| {
+            let mut fields = vec![name.to_string()];
+            for tag in [&info.title, &info.artist, &info.album, &info.album_artist] {
+                fields.extend(tag.clone());
+            }
+            fields
+        };
+        let matches = |fields: Vec<String>| {
+            let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+            matcher.matches(&fields)
         };
 
-        if let Some(path) = file_to_play {
-            // СОЗДАЕМ ИСТОЧНИК
-            let source = SymphoniaSource::new(&path)?;
+        let rows = match panel {
+            0 => self
+                .files
+                .iter()
+                .enumerate()
+                .filter(|(_, entry)| matches(fields(&entry.name, &entry.info)))
+                .map(|(i, _)| i)
+                .collect(),
+            _ => self
+                .playlist
+                .iter()
+                .enumerate()
+                .filter(|(_, entry)| matches(fields(&entry.name, &entry.info)))
+                .map(|(i, _)| i)
+                .collect(),
+        };
+        Some(rows)
+    }
 
-            // СОЗДАЕМ RODIO SINK
-            let (stream, stream_handle) = OutputStream::try_default()?;
-            let sink = Sink::try_new(&stream_handle)?;
+    // Строки, которые сейчас видны в панели
+    fn visible_rows(&self, panel: usize) -> Vec<usize> {
+        self.search_rows(panel).unwrap_or_else(|| {
+            let len = if panel == 0 {
+                self.files.len()
+            } else {
+                self.playlist.len()
+            };
+            (0..len).collect()
+        })
+    }
 
-            sink.append(source);
-            sink.play();
+    fn panel_list_state(&mut self, panel: usize) -> &mut ListState {
+        if panel == 0 {
+            &mut self.files_list_state
+        } else {
+            &mut self.playlist_list_state
+        }
+    }
 
-            // ОБНОВЛЯЕМ СОСТОЯНИЕ
-            self.sink = Some(sink);
-            self._stream = Some(stream);
-            self.current_playing_path = Some(path);
-            self.is_playing = true;
-            self.current_playback_position = std::time::Duration::ZERO;
-            self.playback_start_time = Some(std::time::Instant::now());
+    // Шаг по отфильтрованным строкам; выделение хранит индекс в полном списке
+    fn step_search(&mut self, rows: &[usize], forward: bool, wrap: bool) {
+        if rows.is_empty() {
+            return;
+        }
+        let state = self.panel_list_state(self.active_panel);
+        let position = state
+            .selected()
+            .and_then(|selected| rows.iter().position(|&row| row == selected));
+        let last = rows.len() - 1;
+        let next = match position {
+            None => 0,
+            Some(p) if forward && p < last => p + 1,
+            Some(p) if !forward && p > 0 => p - 1,
+            // Край списка: n/N идут по кругу, стрелки упираются
+            Some(p) if wrap => last - p,
+            Some(p) => p,
+        };
+        state.select(Some(rows[next]));
+    }
 
-            self.update_playing_status();
+    // Выделение всегда на видимой строке, если такие есть
+    fn sync_search_selection(&mut self) {
+        let Some(panel) = self.search.as_ref().map(|search| search.panel) else {
+            return;
+        };
+        let Some(rows) = self.search_rows(panel) else {
+            return;
+        };
+        let state = self.panel_list_state(panel);
+        let visible = state
+            .selected()
+            .is_some_and(|selected| rows.contains(&selected));
+        if !visible {
+            if let Some(&first) = rows.first() {
+                state.select(Some(first));
+            }
         }
-
-        Ok(())
     }
 
-    fn next_track(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        // println!("⏭️ Следующий трек");
-        self.play_next() // <-- Использовать правильный метод
+    // Строка запроса для заголовка панели
+    fn search_title(&self, panel: usize) -> Option<String> {
+        let search = self
+            .search
+            .as_ref()
+            .filter(|search| search.panel == panel)?;
+        let cursor = if search.editing { "▏" } else { "" };
+        let found = match self.search_rows(panel) {
+            Some(rows) => format!(" [{}/{}]", rows.len(), self.visible_rows_total(panel)),
+            None => String::new(),
+        };
+        Some(format!(" /{}{}{} ", search.query, cursor, found))
     }
 
-    fn previous_track(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        if self.current_playlist_index > 0 {
-            self.current_playlist_index -= 1;
+    fn visible_rows_total(&self, panel: usize) -> usize {
+        if panel == 0 {
+            self.files.len()
+        } else {
+            self.playlist.len()
+        }
+    }
 
-            if let Some(sink) = &self.sink {
-                sink.stop();
+    // Сканирование библиотеки идет в фоне, здесь забираем его итог
+    fn process_library_scan(&mut self) {
+        match self.library.poll() {
+            Some(Ok(summary)) => {
+                self.library_summary = Some(summary);
+                // Индексы в дереве поменялись - возвращаемся к списку исполнителей
+                self.library_level = LibraryLevel::Artists;
+                self.library_parents.clear();
+                let len = self.library_len();
+                let selected = self.library_list_state.selected().unwrap_or(0);
+                self.library_list_state
+                    .select((len > 0).then(|| selected.min(len - 1)));
             }
+            Some(Err(e)) => self
+                .messages
+                .error(format!("Ошибка сканирования библиотеки: {}", e)),
+            None => {}
+        }
+    }
 
-            let files_to_play: Vec<PathBuf> = self
-                .playlist
-                .iter()
-                .map(|entry| entry.path.clone())
-                .collect();
-
-            if self.current_playlist_index < files_to_play.len() {
-                if let Some(prev_file) = files_to_play.get(self.current_playlist_index) {
-                    self.current_playing_path = Some(prev_file.clone());
-
-                    let file = File::open(prev_file)?;
-                    let source = Decoder::new(BufReader::new(file))?;
+    fn library_len(&self) -> usize {
+        let artists = self.library.artists();
+        match self.library_level {
+            LibraryLevel::Artists => artists.len(),
+            LibraryLevel::Albums(artist) => artists[artist].albums.len(),
+            LibraryLevel::Tracks(artist, album) => artists[artist].albums[album].tracks.len(),
+        }
+    }
 
-                    let (stream, stream_handle) = OutputStream::try_default()?;
-                    let sink = Sink::try_new(&stream_handle)?;
-                    sink.append(source);
-                    sink.play();
+    // Левая и правая часть строки списка библиотеки
+    fn library_row(&self, row: usize) -> (String, String) {
+        let artists = self.library.artists();
+        match self.library_level {
+            LibraryLevel::Artists => {
+                let artist = &artists[row];
+                (
+                    format!("{}/", artist.name),
+                    format!("{} альб.", artist.albums.len()),
+                )
+            }
+            LibraryLevel::Albums(artist) => {
+                let album = &artists[artist].albums[row];
+                let title = match &album.date {
+                    Some(date) => format!("{} ({})/", album.title, date),
+                    None => format!("{}/", album.title),
+                };
+                (title, format!("{} тр.", album.tracks.len()))
+            }
+            LibraryLevel::Tracks(artist, album) => {
+                let track = self
+                    .library
+                    .track(artists[artist].albums[album].tracks[row]);
+                let title = match track.info.track_number {
+                    Some(number) => format!("{:02}. {}", number, track.title()),
+                    None => track.title(),
+                };
+                (title, format_duration(track.info.duration))
+            }
+        }
+    }
 
-                    self._stream = Some(stream);
-                    self.sink = Some(sink);
-                    self.is_playing = true;
+    // Right - на уровень глубже
+    fn library_enter(&mut self) {
+        let Some(selected) = self.library_list_state.selected() else {
+            return;
+        };
+        if selected >= self.library_len() {
+            return;
+        }
+        self.library_level = match self.library_level {
+            LibraryLevel::Artists => LibraryLevel::Albums(selected),
+            LibraryLevel::Albums(artist) => LibraryLevel::Tracks(artist, selected),
+            LibraryLevel::Tracks(..) => {
+                self.add_library_selection();
+                return;
+            }
+        };
+        self.library_parents.push(selected);
+        self.library_list_state.select(Some(0));
+    }
 
-                    // СБРАСЫВАЕМ И ЗАПУСКАЕМ ПРОГРЕСС ДЛЯ ПРЕДЫДУЩЕГО ТРЕКА
-                    self.current_playback_position = std::time::Duration::ZERO;
-                    self.playback_start_time = Some(std::time::Instant::now());
+    // Left - на уровень выше, на прежнее выделение
+    fn library_leave(&mut self) {
+        self.library_level = match self.library_level {
+            LibraryLevel::Artists => return,
+            LibraryLevel::Albums(_) => LibraryLevel::Artists,
+            LibraryLevel::Tracks(artist, _) => LibraryLevel::Albums(artist),
+        };
+        self.library_list_state
+            .select(Some(self.library_parents.pop().unwrap_or(0)));
+    }
 
-                    self.update_playing_status();
-                }
+    // Треки под выделенной строкой: весь исполнитель, альбом или один трек
+    fn library_selected_tracks(&self) -> Vec<usize> {
+        let Some(selected) = self.library_list_state.selected() else {
+            return Vec::new();
+        };
+        if selected >= self.library_len() {
+            return Vec::new();
+        }
+        let artists = self.library.artists();
+        match self.library_level {
+            LibraryLevel::Artists => artists[selected]
+                .albums
+                .iter()
+                .flat_map(|album| album.tracks.iter().copied())
+                .collect(),
+            LibraryLevel::Albums(artist) => artists[artist].albums[selected].tracks.clone(),
+            LibraryLevel::Tracks(artist, album) => {
+                vec![artists[artist].albums[album].tracks[selected]]
             }
         }
+    }
 
-        Ok(())
+    // Enter - выделенное в плейлист; возвращает индекс первой добавленной записи
+    fn add_library_selection(&mut self) -> Option<usize> {
+        let tracks = self.library_selected_tracks();
+        if tracks.is_empty() {
+            return None;
+        }
+        let first = self.playlist.len();
+        for index in tracks {
+            let track = self.library.track(index);
+            let path = track.path.clone();
+            let info = track.info.clone();
+            let name = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or("Unknown")
+                .to_string();
+            let entries = file_entries(&path, &name, info, &mut self.prober);
+            self.playlist.extend(entries);
+        }
+        self.sync_queue();
+        Some(first)
     }
 
     fn update_playing_status(&mut self) {
@@ -1002,96 +2416,48 @@ impl App {
             entry.playing = false;
         }
 
-        // Помечаем текущий играющий трек
-        if let Some(current_path) = &self.current_playing_path {
+        // Помечаем текущий играющий трек: по индексу (в плейлисте бывают дубли),
+        // а для файла вне плейлиста - по пути и части файла
+        if let Some(entry) = self
+            .current_playlist_index
+            .and_then(|index| self.playlist.get_mut(index))
+        {
+            entry.playing = true;
+        } else if let Some(current) = &self.current_track {
             for entry in &mut self.playlist {
-                if &entry.path == current_path {
+                if entry.path == current.path && entry.span == current.span {
                     entry.playing = true;
                     break;
                 }
             }
         }
     }
-
-    fn play_next(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        if let Some(sink) = &self.sink {
-            sink.stop();
-        }
-
-        self.current_playlist_index += 1;
-
-        // Определяем следующий файл для воспроизведения
-        let files_to_play: Vec<PathBuf> = self
-            .playlist
-            .iter()
-            .map(|entry| entry.path.clone())
-            .collect();
-
-        // Проверяем есть ли еще треки
-        if self.current_playlist_index >= files_to_play.len() {
-            self.is_playing = false;
-            self.current_playlist_index = 0;
-            self.current_playing_path = None;
-            // СБРАСЫВАЕМ ПРОГРЕСС
-            self.current_playback_position = std::time::Duration::ZERO;
-            self.playback_start_time = None;
-            self.update_playing_status();
-            return Ok(());
-        }
-
-        // Воспроизводим следующий трек
-        if let Some(next_file) = files_to_play.get(self.current_playlist_index) {
-            self.current_playing_path = Some(next_file.clone());
-
-            let file = File::open(next_file)?;
-            let source = Decoder::new(BufReader::new(file))?;
-
-            let (stream, stream_handle) = OutputStream::try_default()?;
-            let sink = Sink::try_new(&stream_handle)?;
-            sink.append(source);
-            sink.play();
-
-            self._stream = Some(stream);
-            self.sink = Some(sink);
-            self.is_playing = true;
-
-            // СБРАСЫВАЕМ И ЗАПУСКАЕМ ПРОГРЕСС ДЛЯ НОВОГО ТРЕКА
-            self.current_playback_position = std::time::Duration::ZERO;
-            self.playback_start_time = Some(std::time::Instant::now());
-
-            self.update_playing_status();
-        }
-
-        Ok(())
+}
+impl SaveDialog {
+    // Курсор считается в символах, строка индексируется в байтах
+    fn cursor_byte(&self) -> usize {
+        self.filename
+            .char_indices()
+            .nth(self.cursor_position)
+            .map_or(self.filename.len(), |(i, _)| i)
     }
 
-    fn check_playback_finished(&mut self) {
-        if let Some(sink) = &self.sink {
-            if sink.empty() && self.is_playing {
-                self.current_playback_position = std::time::Duration::ZERO;
-                self.playback_start_time = None;
-
-                if let Err(_e) = self.play_next() {
-                    self.is_playing = false;
-                    self.update_playing_status();
-                }
-            }
+    // Формат сохранения по расширению имени - для подсказки
+    fn format_hint(&self) -> String {
+        match playlist_file::Format::from_path(Path::new(&self.filename)) {
+            Some(format) if format.can_write() => format!(" Формат: {} ", format.label()),
+            _ => " Формат: .m3u .m3u8 .pls .xspf ".to_string(),
         }
     }
-}
-impl SaveDialog {
+
     fn load_directory(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         self.files.clear();
 
         let entries = match fs::read_dir(&self.current_dir) {
             Ok(entries) => entries,
             Err(e) => {
-                eprintln!(
-                    "Ошибка чтения директории {}: {}",
-                    self.current_dir.display(),
-                    e
-                );
-                return Ok(()); // Возвращаемся без ошибки, чтобы не падать
+                let dir = self.current_dir.display();
+                return Err(format!("Ошибка чтения директории {}: {}", dir, e).into());
             }
         };
 
@@ -1130,7 +2496,7 @@ impl SaveDialog {
                     is_dir: true,
                     name,
                     selected: false,
-                    duration: None,
+                    info: TrackInfo::default(),
                 });
             } else {
                 files.push(FileEntry {
@@ -1138,7 +2504,7 @@ impl SaveDialog {
                     is_dir: false,
                     name,
                     selected: false,
-                    duration: None,
+                    info: TrackInfo::default(),
                 });
             }
         }
@@ -1164,11 +2530,12 @@ impl SaveDialog {
                 if entry.is_dir {
                     self.current_dir = entry.path.clone();
                     if let Err(e) = self.load_directory() {
-                        eprintln!("Ошибка загрузки директории: {}", e);
                         // Возвращаемся обратно при ошибке
                         if let Some(parent) = self.current_dir.parent() {
                             self.current_dir = parent.to_path_buf();
                         }
+                        let _ = self.load_directory();
+                        return Err(e);
                     }
                 }
             }
@@ -1176,14 +2543,7 @@ impl SaveDialog {
         Ok(())
     }
 } // <-- Закрывающая фигурная скобка для impl SaveDialog
-fn is_audio_file(path: &Path) -> bool {
-    let audio_extensions = ["wav", "flac", "ogg", "m3u", "aiff", "aif", "aifc", "m4a", "caf"];
-    path.extension()
-        .and_then(|ext| ext.to_str())
-        .map(|ext| audio_extensions.contains(&ext.to_lowercase().as_str()))
-        .unwrap_or(false)
-}
-// Добавляем функцию центрирования ПОСЛЕ функции ui
+  // Добавляем функцию центрирования ПОСЛЕ функции ui
 fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
     let popup_layout = Layout::default()
         .direction(Direction::Vertical)
@@ -1203,46 +2563,94 @@ fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
         ])
         .split(popup_layout[1])[1]
 }
+// Прокрутка справки и журнала; остальные клавиши в окне игнорируются
+fn scroll_by_key(scroll: &mut u16, code: KeyCode) {
+    *scroll = match code {
+        KeyCode::Down | KeyCode::Char('j') => scroll.saturating_add(1),
+        KeyCode::Up | KeyCode::Char('k') => scroll.saturating_sub(1),
+        KeyCode::PageDown => scroll.saturating_add(10),
+        KeyCode::PageUp => scroll.saturating_sub(10),
+        KeyCode::Home => 0,
+        KeyCode::End => u16::MAX,
+        _ => return,
+    };
+}
+
+// Окно закрывают Esc и клавиши действия, которое его открыло
+fn close_hint(keymap: &Keymap, action: Action) -> String {
+    match keymap.keys(action) {
+        Some(keys) => format!("{}, Esc - закрыть", keys),
+        None => "Esc - закрыть".to_string(),
+    }
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    suppress_alsa_warnings();
-    // Увеличиваем размер аудиобуфера для предотвращения underrun
-    env::set_var("RUST_AUDIO_BACKEND_BUFFER_SIZE", "16384");
-    env::set_var("RUST_AUDIO_LATENCY", "1");
+    // Аргументы и конфиг - до глушения stderr, иначе ошибки никто не увидит
     let cli = Cli::parse();
+    let mut config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Ошибка в настройках: {}", e);
+            std::process::exit(2);
+        }
+    };
+
+    suppress_alsa_warnings();
+    theme::set(std::mem::take(&mut config.theme));
 
-    // Создаем приложение
-    let mut app = App::new(cli.folder)?;
+    // Создаем приложение: режим из командной строки главнее конфига
+    let options = OpenOptions {
+        dsd_rate: cli.dsd_rate,
+        strict: cli.strict,
+    };
+    let mode = cli
+        .mode
+        .or(config.general.playback_mode)
+        .unwrap_or_default();
+    let mut app = App::new(
+        cli.folder,
+        options,
+        mode,
+        cli.library_roots,
+        !cli.no_restore,
+        config,
+    )?;
 
     // Настраиваем терминал
     enable_raw_mode()?;
     let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen)?;
+    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
     // Главный цикл
     'main: loop {
-        // ОБНОВЛЯЕМ ПРОГРЕСС ВОСПРОИЗВЕДЕНИЯ
-        app.update_playback_progress();
-        // Проверяем окончание воспроизведения
-        app.check_playback_finished();
+        // ОБНОВЛЯЕМ СОСТОЯНИЕ ИЗ ПОТОКА ВОСПРОИЗВЕДЕНИЯ
+        app.process_player_events();
+        app.process_probe_results();
+        app.process_library_scan();
+        app.process_relink();
+        if app.session_saved_at.elapsed() >= SESSION_SAVE_EVERY {
+            // Не записалось - попробуем при выходе
+            let _ = app.save_session();
+        }
 
         // Отрисовываем интерфейс
-        terminal.draw(|f| ui(f, &app))?;
+        let mut screen = ScreenAreas::default();
+        terminal.draw(|f| screen = ui(f, &app))?;
+        app.screen = screen;
 
         // ★★★ ОБРАБОТКА ДИАЛОГА ★★★
         // В главном цикле, где обрабатывается диалог:
         // В главном цикле, где обрабатывается диалог:
         if let Some(dialog) = &app.save_dialog {
             if dialog.visible {
-                match event::read()? {
-                    Event::Key(key) => {
-                        if let Err(e) = app.handle_save_dialog_input(key) {
-                            eprintln!("Ошибка в диалоге сохранения: {}", e);
-                            // Не закрываем приложение при ошибке, просто логируем
-                        }
+                if let Event::Key(key) = event::read()? {
+                    if let Err(e) = app.handle_save_dialog_input(key) {
+                        // Не закрываем приложение при ошибке, просто сообщаем
+                        app.messages
+                            .error(format!("Ошибка в диалоге сохранения: {}", e));
                     }
-                    _ => {}
                 }
                 continue;
             }
@@ -1250,140 +2658,92 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         // Обрабатываем ввод
         if event::poll(Duration::from_millis(100))? {
-            if let Event::Key(key) = event::read()? {
-                match key.code {
-                    _ if app.show_help => {
-                        match key.code {
-                            KeyCode::Esc => app.show_help = false,
-                            KeyCode::Down | KeyCode::Char('j') => {
-                                app.help_scroll = app.help_scroll.saturating_add(1);
-                            }
-                            KeyCode::Up | KeyCode::Char('k') => {
-                                app.help_scroll = app.help_scroll.saturating_sub(1);
-                            }
-                            KeyCode::PageDown => {
-                                app.help_scroll = app.help_scroll.saturating_add(10);
-                            }
-                            KeyCode::PageUp => {
-                                app.help_scroll = app.help_scroll.saturating_sub(10);
-                            }
-                            KeyCode::Home => {
-                                app.help_scroll = 0;
-                            }
-                            KeyCode::End => {
-                                app.help_scroll = u16::MAX;
-                            }
-                            _ => {} // Игнорируем другие клавиши в справке
-                        }
-                    }
-
-                    // Группа 1: Основное управление (F1-F4)
-                    KeyCode::F(1) => app.show_help(),
-                    KeyCode::F(2) => {
-                        if let Err(e) = app.play() {
-                            eprintln!("Ошибка воспроизведения: {}", e);
-                        }
-                    }
-                    KeyCode::F(3) => app.pause(),
-                    KeyCode::F(4) => app.stop(),
-
-                    // Группа 2: Навигация по трекам (F5-F8)
-                    KeyCode::F(5) => {
-                        if let Err(e) = app.previous_track() {
-                            eprintln!("Ошибка переключения трека: {}", e);
-                        }
-                    }
-                    KeyCode::F(6) => {
-                        if let Err(e) = app.next_track() {
-                            eprintln!("Ошибка переключения трека: {}", e);
-                        }
-                    }
-                    // KeyCode::F(7) => {
-                    //     if let Err(e) = app.rewind_backward() {
-                    //         eprintln!("Ошибка перемотки назад: {}", e);
-                    //     }
-                    // }
-                    // KeyCode::F(8) => {
-                    //     if let Err(e) = app.rewind_forward() {
-                    //         eprintln!("Ошибка перемотки вперед: {}", e);
-                    //     }
-                    // }
-                    KeyCode::Char('q') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                        break 'main;
-                    }
-                    KeyCode::Tab => app.switch_panel(),
-                    KeyCode::F(9) => {
-                        if app.save_dialog.is_none() {
-                            if let Err(e) = app.show_save_dialog() {
-                                eprintln!("Ошибка открытия диалога сохранения: {}", e);
-                            }
-                        } else {
-                            app.hide_save_dialog();
-                        }
-                    }
-
-                    // Громкость
-                    KeyCode::Char('+') => {
-                        app.volume_up();
-                    }
-                    KeyCode::Char('-') => {
-                        app.volume_down();
-                    }
-
-                    // Навигация и выделение
-                    KeyCode::Down => {
-                        if key.modifiers.contains(KeyModifiers::CONTROL) {
-                            app.toggle_current_selection();
-                            app.next_item();
-                        } else {
-                            app.next_item();
-                        }
+            let key = match event::read()? {
+                Event::Key(key) => key,
+                Event::Mouse(mouse) => {
+                    app.handle_mouse(mouse);
+                    continue;
+                }
+                _ => continue,
+            };
+            match key.code {
+                _ if app.show_track_info => {
+                    if key.code == KeyCode::Esc || app.keymap.feed(&key) == Some(Action::TrackInfo)
+                    {
+                        app.show_track_info = false;
                     }
-                    KeyCode::Up => {
-                        if key.modifiers.contains(KeyModifiers::CONTROL) {
-                            app.toggle_current_selection();
-                            app.previous_item();
-                        } else {
-                            app.previous_item();
-                        }
+                }
+                // Окно итога закрывает любая клавиша; R и Shift+Delete сразу делают свое
+                _ if app.load_report.is_some() => {
+                    app.load_report = None;
+                    if let Some(action @ (Action::Relink | Action::PurgeBroken)) =
+                        app.keymap.feed(&key)
+                    {
+                        app.perform(action);
                     }
-                    KeyCode::Right => {
-                        if let Err(e) = app.handle_right_key() {
-                            eprintln!("Ошибка: {}", e);
-                        }
+                }
+                _ if app.is_search_editing() => app.handle_search_input(key),
+                _ if app.show_help => {
+                    if key.code == KeyCode::Esc || app.keymap.feed(&key) == Some(Action::Help) {
+                        app.show_help = false;
+                    } else {
+                        scroll_by_key(&mut app.help_scroll, key.code);
                     }
-                    KeyCode::Left => {
-                        if let Err(e) = app.leave_directory() {
-                            eprintln!("Ошибка: {}", e);
-                        }
+                }
+                _ if app.show_messages => {
+                    if key.code == KeyCode::Esc || app.keymap.feed(&key) == Some(Action::Messages) {
+                        app.show_messages = false;
+                    } else {
+                        scroll_by_key(&mut app.messages_scroll, key.code);
                     }
+                }
 
-                    // Действия
-                    KeyCode::Enter => {
-                        if let Err(e) = app.add_to_playlist() {
-                            eprintln!("Ошибка добавления в плейлист: {}", e);
+                _ => match app.keymap.feed(&key) {
+                    Some(Action::Quit) => break 'main,
+                    Some(action) => app.perform(action),
+                    // Цифры, не занятые раскладкой, - переход по треку
+                    None if app.keymap.pending().is_none() => {
+                        if let KeyCode::Char(c @ '0'..='9') = key.code {
+                            let percent = c.to_digit(10).unwrap_or(0) as f64 / 10.0;
+                            app.seek_percent(percent);
                         }
                     }
-                    KeyCode::Delete => app.remove_from_playlist(),
-
-                    _ => {}
-                }
+                    None => {}
+                },
             }
         }
     }
 
+    // Кэш пробы - только ускорение, ошибка записи не мешает выходу
+    let _ = app.prober.save_cache();
+    let session_result = app.save_session();
+    if let Err(e) = &session_result {
+        app.messages
+            .error(format!("Не удалось сохранить сессию: {}", e));
+    }
+
     // Восстанавливаем терминал
     disable_raw_mode()?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen,)?;
+    execute!(
+        terminal.backend_mut(),
+        LeaveAlternateScreen,
+        DisableMouseCapture
+    )?;
     terminal.show_cursor()?;
 
+    // stderr закрыт - после выхода из альтернативного экрана пишем в stdout
+    if let Err(e) = session_result {
+        println!("Не удалось сохранить сессию: {}", e);
+    }
     println!("🎵 До свидания!");
     Ok(())
 }
 
-fn ui(frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>, app: &App) {
+// Возвращает, где нарисованы списки и полоса прогресса - по ним попадает мышь
+fn ui(frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>, app: &App) -> ScreenAreas {
     // use theme::*;
     use styles::*;
+    let mut screen = ScreenAreas::default();
 
     // Фон всего приложения
     frame.render_widget(Block::default().style(background()), frame.size());
@@ -1402,151 +2762,177 @@ fn ui(frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>, app: &App) {
         .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
         .split(chunks[0]);
 
-    // Файловый менеджер - разделяем на заголовок, пустую строку и контент
-    let files_chunks = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints([
-            Constraint::Length(1), // Заголовок
-            Constraint::Length(1), // Пустая строка (разделитель)
-            Constraint::Min(1),    // Список файлов
-        ])
-        .split(columns[0]);
-    // Рендерим диалог сохранения поверх основного интерфейса
-    // Рендерим диалог сохранения поверх основного интерфейса
-
-    // Рендерим заголовок файлового менеджера
-    let files_title_style = if app.active_panel == 0 {
-        active_panel()
-    } else {
-        inactive_panel()
-    };
-
-    let files_title = Paragraph::new(Line::from(Span::styled(
-        " FILE MANAGER ",
-        files_title_style,
-    )))
-    .style(surface());
-    frame.render_widget(files_title, files_chunks[0]);
-
-    // Рендерим пустую строку-разделитель
-    let empty_line = Paragraph::new("").style(surface());
-    frame.render_widget(empty_line, files_chunks[1]);
-
-    // Рендерим список файлов вручную для контроля выравнивания
-    let files_area = files_chunks[2];
-    let mut y = 0;
-
-    // Вычисляем смещение для скроллинга
-    let files_scroll_offset = if let Some(selected) = app.files_list_state.selected() {
-        let visible_items = files_area.height as usize;
-        if selected >= visible_items {
-            selected - visible_items + 1
-        } else {
-            0
-        }
+    if app.library_shown {
+        screen.left = render_library(frame, app, columns[0]);
     } else {
-        0
-    };
-
-    // Рендерим только видимые элементы
-    for (i, entry) in app.files.iter().enumerate().skip(files_scroll_offset) {
-        if y >= files_area.height as usize {
-            break;
-        }
-
-        let icon = if entry.is_dir { " " } else { " " };
-        let selection_indicator = if entry.selected { " ●" } else { "  " };
+        // Файловый менеджер - разделяем на заголовок, пустую строку и контент
+        let files_chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(1), // Заголовок
+                Constraint::Length(1), // Пустая строка (разделитель)
+                Constraint::Min(1),    // Список файлов
+            ])
+            .split(columns[0]);
+        // Рендерим диалог сохранения поверх основного интерфейса
+        // Рендерим диалог сохранения поверх основного интерфейса
 
-        let duration_text = if entry.is_dir {
-            "".to_string()
-        } else if entry.path.extension().map_or(false, |ext| ext == "m3u") {
-            "".to_string() // Для M3U файлов не показываем длительность
+        // Рендерим заголовок файлового менеджера
+        let files_title_style = if app.active_panel == 0 {
+            active_panel()
         } else {
-            format_duration(entry.duration)
+            inactive_panel()
         };
 
-        // Вычисляем оригинальный индекс для подсветки
-        let original_index = i;
-
-        let style = if app.active_panel == 0 {
-            if Some(original_index) == app.files_list_state.selected() {
-                Style::default()
-                    .fg(theme::TEXT_PRIMARY)
-                    .add_modifier(Modifier::BOLD)
-            } else if entry.selected {
-                selected_file()
-            } else if entry.is_dir {
-                folder()
+        let files_title = Paragraph::new(Line::from(vec![
+            Span::styled(" FILE MANAGER ", files_title_style),
+            Span::styled(
+                app.search_title(0).unwrap_or_default(),
+                Style::default().fg(theme::palette().warning),
+            ),
+        ]))
+        .style(surface());
+        frame.render_widget(files_title, files_chunks[0]);
+
+        // Рендерим пустую строку-разделитель
+        let empty_line = Paragraph::new("").style(surface());
+        frame.render_widget(empty_line, files_chunks[1]);
+
+        // Рендерим список файлов вручную для контроля выравнивания
+        let files_area = files_chunks[2];
+
+        // Строки с учетом фильтра поиска; выделение - индекс в полном списке
+        let files_rows = app.visible_rows(0);
+        let files_selected_row = app
+            .files_list_state
+            .selected()
+            .and_then(|selected| files_rows.iter().position(|&i| i == selected));
+
+        // Вычисляем смещение для скроллинга
+        let files_scroll_offset = if let Some(selected) = files_selected_row {
+            let visible_items = files_area.height as usize;
+            if selected >= visible_items {
+                selected - visible_items + 1
             } else {
-                normal_file()
+                0
             }
         } else {
-            styles::inactive_text()
+            0
+        };
+        screen.left = ListArea {
+            area: files_area,
+            rows: files_rows
+                .iter()
+                .skip(files_scroll_offset)
+                .take(files_area.height as usize)
+                .copied()
+                .collect(),
         };
 
-        let duration_style = if app.active_panel == 0 {
-            if Some(original_index) == app.files_list_state.selected() {
-                Style::default()
-                    .fg(theme::TEXT_PRIMARY)
-                    .add_modifier(Modifier::BOLD)
-            } else if entry.selected {
-                selected_file()
-            } else if entry.is_dir {
-                folder()
-            } else {
-                normal_file()
+        // Рендерим только видимые элементы
+        for (y, &i) in files_rows.iter().skip(files_scroll_offset).enumerate() {
+            if y >= files_area.height as usize {
+                break;
             }
-        } else {
-            styles::inactive_text()
-        };
-        // Создаем Rect для текущей строки
-        let line_rect = Rect::new(files_area.x, files_area.y + y as u16, files_area.width, 1);
+            let entry = &app.files[i];
 
-        // Разделяем строку на левую и правую части
-        let line_chunks = Layout::default()
-            .direction(Direction::Horizontal)
-            .constraints([
-                Constraint::Min(0),    // Левая часть - имя файла
-                Constraint::Length(7), // Правая часть - длительность
-            ])
-            .split(line_rect);
+            let icon = " ";
+            let selection_indicator = if entry.selected { " ●" } else { "  " };
 
-        // Левая часть - имя файла
-        let name_text = format!("{}{}{}", selection_indicator, icon, entry.name);
-        let name_paragraph =
-            Paragraph::new(Line::from(Span::styled(name_text, style))).style(surface());
-        frame.render_widget(name_paragraph, line_chunks[0]);
+            let duration_text = if entry.is_dir || playlist_file::is_playlist(&entry.path) {
+                "".to_string() // Для папок и плейлистов не показываем длительность
+            } else if app.prober.is_pending(&entry.path) {
+                "[..:..]".to_string() // Длительность еще читается в фоне
+            } else {
+                format_duration(entry.info.duration)
+            };
 
-        // Правая часть - длительность (выровнена по правому краю)
-        if !entry.is_dir {
-            let duration_paragraph =
-                Paragraph::new(Line::from(Span::styled(duration_text, duration_style)))
-                    .style(surface())
-                    .alignment(ratatui::layout::Alignment::Right);
-            frame.render_widget(duration_paragraph, line_chunks[1]);
-        }
+            // Вычисляем оригинальный индекс для подсветки
+            let original_index = i;
 
-        y += 1;
-    }
+            let style = if app.active_panel == 0 {
+                if Some(original_index) == app.files_list_state.selected() {
+                    Style::default()
+                        .fg(theme::palette().text_primary)
+                        .add_modifier(Modifier::BOLD)
+                } else if entry.selected {
+                    selected_file()
+                } else if entry.is_dir {
+                    folder()
+                } else {
+                    normal_file()
+                }
+            } else {
+                styles::inactive_text()
+            };
 
-    // Подсветка выбранного элемента (только если он видим)
-    if let Some(selected) = app.files_list_state.selected() {
-        if selected >= files_scroll_offset
-            && (selected - files_scroll_offset) < files_area.height as usize
-        {
-            let highlight_y = (selected - files_scroll_offset) as u16;
-            let highlight_rect = Rect::new(
-                files_area.x,
-                files_area.y + highlight_y,
-                files_area.width,
-                1,
-            );
-            let highlight = Paragraph::new("").style(if app.active_panel == 0 {
-                styles::highlight_active()
+            let duration_style = if app.active_panel == 0 {
+                if Some(original_index) == app.files_list_state.selected() {
+                    Style::default()
+                        .fg(theme::palette().text_primary)
+                        .add_modifier(Modifier::BOLD)
+                } else if entry.selected {
+                    selected_file()
+                } else if entry.is_dir {
+                    folder()
+                } else {
+                    normal_file()
+                }
             } else {
-                styles::highlight_inactive()
-            });
-            frame.render_widget(highlight, highlight_rect);
+                styles::inactive_text()
+            };
+            // Создаем Rect для текущей строки
+            let line_rect = Rect::new(files_area.x, files_area.y + y as u16, files_area.width, 1);
+
+            // Разделяем строку на левую и правую части
+            let line_chunks = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([
+                    Constraint::Min(0),    // Левая часть - имя файла
+                    Constraint::Length(7), // Правая часть - длительность
+                ])
+                .split(line_rect);
+
+            // Левая часть - имя файла
+            let name_text = format!(
+                "{}{}{}",
+                selection_indicator,
+                icon,
+                display_name(&entry.info, &entry.name)
+            );
+            let name_paragraph =
+                Paragraph::new(Line::from(Span::styled(name_text, style))).style(surface());
+            frame.render_widget(name_paragraph, line_chunks[0]);
+
+            // Правая часть - длительность (выровнена по правому краю)
+            if !entry.is_dir {
+                let duration_paragraph =
+                    Paragraph::new(Line::from(Span::styled(duration_text, duration_style)))
+                        .style(surface())
+                        .alignment(ratatui::layout::Alignment::Right);
+                frame.render_widget(duration_paragraph, line_chunks[1]);
+            }
+        }
+
+        // Подсветка выбранного элемента (только если он видим)
+        if let Some(selected) = files_selected_row {
+            if selected >= files_scroll_offset
+                && (selected - files_scroll_offset) < files_area.height as usize
+            {
+                let highlight_y = (selected - files_scroll_offset) as u16;
+                let highlight_rect = Rect::new(
+                    files_area.x,
+                    files_area.y + highlight_y,
+                    files_area.width,
+                    1,
+                );
+                let highlight = Paragraph::new("").style(if app.active_panel == 0 {
+                    styles::highlight_active()
+                } else {
+                    styles::highlight_inactive()
+                });
+                frame.render_widget(highlight, highlight_rect);
+            }
         }
     }
     // Плейлист - аналогично разделяем на заголовок, пустую строку и контент
@@ -1566,23 +2952,45 @@ fn ui(frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>, app: &App) {
         styles::inactive_panel()
     };
 
-    let playlist_title =
-        Paragraph::new(Line::from(Span::styled(" PLAYLIST ", playlist_title_style)))
-            .style(styles::surface());
+    let playlist_title = Paragraph::new(Line::from(vec![
+        Span::styled(" PLAYLIST ", playlist_title_style),
+        Span::styled(
+            app.search_title(1).unwrap_or_default(),
+            Style::default().fg(theme::palette().warning),
+        ),
+    ]))
+    .style(styles::surface());
     frame.render_widget(playlist_title, playlist_chunks[0]);
 
     // Рендерим пустую строку-разделитель для плейлиста
     let empty_line_playlist = Paragraph::new("").style(styles::surface());
     frame.render_widget(empty_line_playlist, playlist_chunks[1]);
 
-    // Рендерим список плейлиста
-    // Рендерим плейлист вручную для контроля выравнивания
+    // Список "дальше" - отдельный блок над плейлистом, не больше трети колонки
+    let playlist_area = if app.up_next.is_empty() {
+        playlist_chunks[2]
+    } else {
+        let column = playlist_chunks[2];
+        let height = (app.up_next.len() as u16 + 2).min((column.height / 3).max(3));
+        let parts = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(height), Constraint::Min(1)])
+            .split(column);
+        render_up_next(frame, app, parts[0]);
+        parts[1]
+    };
+
     // Рендерим плейлист вручную для контроля выравнивания
-    let playlist_area = playlist_chunks[2];
-    let mut y = 0;
+
+    // Строки с учетом фильтра поиска
+    let playlist_rows = app.visible_rows(1);
+    let playlist_selected_row = app
+        .playlist_list_state
+        .selected()
+        .and_then(|selected| playlist_rows.iter().position(|&i| i == selected));
 
     // Вычисляем смещение для скроллинга
-    let playlist_scroll_offset = if let Some(selected) = app.playlist_list_state.selected() {
+    let playlist_scroll_offset = if let Some(selected) = playlist_selected_row {
         let visible_items = playlist_area.height as usize;
         if selected >= visible_items {
             selected - visible_items + 1
@@ -1592,20 +3000,38 @@ fn ui(frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>, app: &App) {
     } else {
         0
     };
+    screen.playlist = ListArea {
+        area: playlist_area,
+        rows: playlist_rows
+            .iter()
+            .skip(playlist_scroll_offset)
+            .take(playlist_area.height as usize)
+            .copied()
+            .collect(),
+    };
 
     // Рендерим только видимые элементы
-    for (i, entry) in app.playlist.iter().enumerate().skip(playlist_scroll_offset) {
+    for (y, &i) in playlist_rows
+        .iter()
+        .skip(playlist_scroll_offset)
+        .enumerate()
+    {
         if y >= playlist_area.height as usize {
             break;
         }
+        let entry = &app.playlist[i];
 
         let icon = if entry.playing { "▶ " } else { " " };
         let selection_indicator = "  ";
 
-        let duration_text = if entry.path.extension().map_or(false, |ext| ext == "m3u") {
-            "".to_string() // Для M3U файлов в плейлисте не показываем длительность
+        let duration_text = if let Some(broken) = entry.broken {
+            broken.tag().to_string()
+        } else if playlist_file::is_playlist(&entry.path) {
+            "".to_string() // Для плейлистов в плейлисте не показываем длительность
+        } else if entry.info.duration.is_none() && app.prober.is_pending(&entry.path) {
+            "[..:..]".to_string() // У треков CUE длительность известна и без пробы
         } else {
-            format_duration(entry.duration)
+            format_duration(entry.info.duration)
         };
 
         // Вычисляем оригинальный индекс для подсветки
@@ -1614,10 +3040,12 @@ fn ui(frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>, app: &App) {
         let style = if app.active_panel == 1 {
             if Some(original_index) == app.playlist_list_state.selected() {
                 Style::default()
-                    .fg(theme::TEXT_PRIMARY)
+                    .fg(theme::palette().text_primary)
                     .add_modifier(Modifier::BOLD)
             } else if entry.playing {
                 styles::playing_track()
+            } else if entry.broken.is_some() {
+                styles::inactive_text() // Файл не сыграть
             } else {
                 styles::normal_file()
             }
@@ -1630,10 +3058,12 @@ fn ui(frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>, app: &App) {
         let duration_style = if app.active_panel == 1 {
             if Some(original_index) == app.playlist_list_state.selected() {
                 Style::default()
-                    .fg(theme::TEXT_PRIMARY)
+                    .fg(theme::palette().text_primary)
                     .add_modifier(Modifier::BOLD)
             } else if entry.playing {
                 styles::playing_track()
+            } else if entry.broken.is_some() {
+                styles::inactive_text() // Файл не сыграть
             } else {
                 styles::normal_file()
             }
@@ -1659,7 +3089,16 @@ fn ui(frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>, app: &App) {
             .split(line_rect);
 
         // Левая часть - имя трека
-        let name_text = format!("{}{}{}", selection_indicator, icon, entry.name);
+        let mut name_text = format!(
+            "{}{}{}",
+            selection_indicator,
+            icon,
+            display_name(&entry.info, &entry.name)
+        );
+        if entry.skipped_packets > 0 {
+            // Трек играл с пропуском поврежденных пакетов
+            name_text.push_str(&format!(" ⚠{}", entry.skipped_packets));
+        }
         let name_paragraph =
             Paragraph::new(Line::from(Span::styled(name_text, style))).style(styles::surface());
         frame.render_widget(name_paragraph, line_chunks[0]);
@@ -1670,37 +3109,17 @@ fn ui(frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>, app: &App) {
                 .style(styles::surface())
                 .alignment(ratatui::layout::Alignment::Right);
         frame.render_widget(duration_paragraph, line_chunks[1]);
-
-        y += 1;
     }
 
     // Подсветка выбранного элемента в плейлисте (только если он видим)
-    if let Some(selected) = app.playlist_list_state.selected() {
-        if selected >= playlist_scroll_offset
-            && (selected - playlist_scroll_offset) < playlist_area.height as usize
-        {
-            let highlight_y = (selected - playlist_scroll_offset) as u16;
-            let highlight_rect = Rect::new(
-                playlist_area.x,
-                playlist_area.y + highlight_y,
-                playlist_area.width,
-                1,
-            );
-            let highlight = Paragraph::new("").style(if app.active_panel == 1 {
-                styles::highlight_active()
-            } else {
-                styles::highlight_inactive()
-            });
-            frame.render_widget(highlight, highlight_rect);
-        }
-    }
-
-    // Подсветка выбранного элемента в плейлисте
-    if let Some(selected) = app.playlist_list_state.selected() {
-        if selected < app.playlist.len() && (selected as u16) < playlist_area.height {
+    if let Some(selected) = playlist_selected_row {
+        if selected >= playlist_scroll_offset
+            && (selected - playlist_scroll_offset) < playlist_area.height as usize
+        {
+            let highlight_y = (selected - playlist_scroll_offset) as u16;
             let highlight_rect = Rect::new(
                 playlist_area.x,
-                playlist_area.y + selected as u16,
+                playlist_area.y + highlight_y,
                 playlist_area.width,
                 1,
             );
@@ -1713,24 +3132,6 @@ fn ui(frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>, app: &App) {
         }
     }
 
-    // Подсветка выбранного элемента
-    if let Some(selected) = app.files_list_state.selected() {
-        if selected < app.files.len() && (selected as u16) < files_area.height {
-            let highlight_rect = Rect::new(
-                files_area.x,
-                files_area.y + selected as u16,
-                files_area.width,
-                1,
-            );
-            let highlight = Paragraph::new("").style(if app.active_panel == 0 {
-                styles::highlight_active()
-            } else {
-                styles::highlight_inactive()
-            });
-            frame.render_widget(highlight, highlight_rect);
-        }
-    }
-
     // Рендерим разделитель (две пустые строки) между панелями и статусной строкой
     let separator = Paragraph::new("").style(background());
     frame.render_widget(separator, chunks[1]);
@@ -1745,15 +3146,23 @@ fn ui(frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>, app: &App) {
         .split(chunks[2]);
 
     // Левая часть - текущий трек и управление плейлистом
-    let left_status_text = if let Some(current_path) = &app.current_playing_path {
+    let left_status_text = if let Some(current_path) = app.current_track.as_ref().map(|t| &t.path) {
         if let Some(file_name) = current_path.file_name().and_then(|n| n.to_str()) {
-            // Обрезаем длинные названия
-            let display_name = if file_name.len() > 30 {
-                format!("{}...", &file_name[..27])
+            let name = display_name(&app.current_track_info, file_name);
+            // Обрезаем длинные названия (по символам - в тегах бывает кириллица)
+            let display_name = if name.chars().count() > 30 {
+                format!("{}...", name.chars().take(27).collect::<String>())
             } else {
-                file_name.to_string()
+                name
             };
-            format!(" Now: {} ", display_name)
+            if app.current_skipped_packets > 0 {
+                format!(
+                    " Now: {} ⚠ пропущено пакетов: {} ",
+                    display_name, app.current_skipped_packets
+                )
+            } else {
+                format!(" Now: {} ", display_name)
+            }
         } else {
             " No track ".to_string()
         }
@@ -1765,13 +3174,13 @@ fn ui(frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>, app: &App) {
         Span::styled(
             &left_status_text,
             Style::default()
-                .fg(theme::TEXT_PRIMARY)
+                .fg(theme::palette().text_primary)
                 .add_modifier(Modifier::BOLD),
         ),
         Span::styled(
             " [F9]Save ",
             Style::default()
-                .fg(theme::TEXT_SECONDARY)
+                .fg(theme::palette().text_secondary)
                 .add_modifier(Modifier::DIM),
         ),
     ]))
@@ -1786,11 +3195,7 @@ fn ui(frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>, app: &App) {
     // В функции ui(), заменяем весь блок прогресс-бара на:
 
     // Получаем общую длительность текущего трека
-    let total_duration = if let Some(current_path) = &app.current_playing_path {
-        get_audio_duration(current_path)
-    } else {
-        None
-    };
+    let total_duration = app.current_track_duration;
 
     // Создаем прогресс-бар
     let (filled, empty, current_time, total_time) = if let (Some(total), Some(current)) =
@@ -1813,36 +3218,67 @@ fn ui(frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>, app: &App) {
     };
 
     // Объединяем с информацией о состоянии
-    let status_icon = if let Some(sink) = &app.sink {
-        if sink.is_paused() {
-            "⏸ "
-        } else if app.is_playing {
-            "▶ "
-        } else {
-            "⏹ "
-        }
-    } else {
-        "⏹ "
+    let status_icon = match app.player_state {
+        PlayerState::Paused => "⏸ ",
+        PlayerState::Playing => "▶ ",
+        PlayerState::Stopped => "⏹ ",
     };
 
-    let volume_text = if let Some(sink) = &app.sink {
-        format!("{:.0}%", sink.volume() * 100.0)
-    } else {
-        "100%".to_string()
-    };
+    let volume_text = format!("{:.0}%", app.volume * 100.0);
+
+    // Начатая последовательность клавиш, как в vim
+    let pending = Span::styled(
+        app.keymap
+            .pending()
+            .map(|keys| format!("{}… ", keys))
+            .unwrap_or_default(),
+        Style::default().fg(theme::palette().warning),
+    );
+    let status_icon = Span::raw(status_icon);
+    let (pending_width, icon_width) = (pending.width() as u16, status_icon.width() as u16);
 
     // Создаем цветной прогресс-бар с Spans
-    let status_line = Line::from(vec![
-        Span::raw(status_icon),
-        Span::styled("■".repeat(filled), Style::default().fg(theme::PRIMARY)), // ЗАПОЛНЕННЫЕ - цветные
-        Span::styled("▫".repeat(empty), Style::default().fg(theme::TEXT_DISABLED)), // ПУСТЫЕ - серые
+    let progress_line = Line::from(vec![
+        pending,
+        status_icon,
+        Span::styled("■".repeat(filled), Style::default().fg(theme::palette().primary)), // ЗАПОЛНЕННЫЕ - цветные
+        Span::styled("▫".repeat(empty), Style::default().fg(theme::palette().text_disabled)), // ПУСТЫЕ - серые
         Span::raw(format!(
-            " {}/{} | Volume: {}",
+            " {}/{} | Volume: {} | ",
             current_time, total_time, volume_text
         )),
+        Span::styled(
+            app.playback_mode.label(),
+            if app.playback_mode == PlaybackMode::Sequential {
+                Style::default().fg(theme::palette().text_secondary)
+            } else {
+                Style::default().fg(theme::palette().primary)
+            },
+        ),
     ]);
 
-    let status_paragraph = Paragraph::new(status_line)
+    // Вторая строка - что на самом деле играет и в каком формате уходит на устройство
+    let format_line = Line::from(Span::styled(
+        app.stream_status().unwrap_or_default(),
+        Style::default().fg(theme::palette().text_secondary),
+    ));
+
+    // Строка выровнена вправо: ее начало - от правого края
+    let status_area = status_chunks[1];
+    let icon_x = status_area.x
+        + status_area
+            .width
+            .saturating_sub(progress_line.width() as u16)
+        + pending_width;
+    screen.status_icon = Rect::new(icon_x, status_area.y, icon_width, 1);
+    screen.progress = Rect::new(
+        icon_x + icon_width,
+        status_area.y,
+        (filled + empty) as u16,
+        1,
+    );
+
+    let status_paragraph = Paragraph::new(vec![progress_line, format_line])
         .style(styles::surface())
         .alignment(ratatui::layout::Alignment::Right);
 
@@ -1857,7 +3293,7 @@ fn ui(frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>, app: &App) {
             frame.render_widget(Clear, overlay);
 
             // 2. Рисуем непрозрачный фон
-            let background = Block::default().style(Style::default().bg(theme::BACKGROUND));
+            let background = Block::default().style(Style::default().bg(theme::palette().background));
             frame.render_widget(background, overlay);
 
             // 3. Рисуем диалог
@@ -1876,14 +3312,14 @@ fn ui(frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>, app: &App) {
                     Constraint::Length(1), // [0] Текущий путь
                     Constraint::Length(3), // [1] Поле ввода
                     Constraint::Min(10),   // [2] Список файлов
-                    Constraint::Length(1), // [3] ПУСТАЯ СТРОКА-РАЗДЕЛИТЕЛЬ
+                    Constraint::Length(1), // [3] Формат и вид путей
                     Constraint::Length(1), // [4] Подсказки
                 ])
                 .split(dialog_area);
 
             // Текущий путь
             let path_text = Paragraph::new(format!("Path: {}", dialog.current_dir.display()))
-                .style(Style::default().fg(theme::TEXT_DISABLED));
+                .style(Style::default().fg(theme::palette().text_disabled));
             frame.render_widget(path_text, inner_chunks[0]);
 
             // Поле ввода с курсором
@@ -1898,7 +3334,7 @@ fn ui(frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>, app: &App) {
                 if dialog.cursor_position > 0 {
                     spans.push(Span::styled(
                         chars[..dialog.cursor_position].iter().collect::<String>(),
-                        Style::default().fg(theme::SUCCESS),
+                        Style::default().fg(theme::palette().success),
                     ));
                 }
 
@@ -1906,7 +3342,7 @@ fn ui(frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>, app: &App) {
                 if dialog.cursor_position < chars.len() {
                     spans.push(Span::styled(
                         chars[dialog.cursor_position].to_string(),
-                        Style::default().fg(theme::BACKGROUND).bg(theme::SUCCESS),
+                        Style::default().fg(theme::palette().background).bg(theme::palette().success),
                     ));
 
                     // Добавляем оставшийся текст
@@ -1915,7 +3351,7 @@ fn ui(frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>, app: &App) {
                             chars[dialog.cursor_position + 1..]
                                 .iter()
                                 .collect::<String>(),
-                            Style::default().fg(theme::TEXT_PRIMARY),
+                            Style::default().fg(theme::palette().text_primary),
                         ));
                     }
                 } else {
@@ -1923,8 +3359,8 @@ fn ui(frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>, app: &App) {
                     spans.push(Span::styled(
                         " ",
                         Style::default()
-                            .fg(theme::BACKGROUND)
-                            .bg(theme::TEXT_PRIMARY),
+                            .fg(theme::palette().background)
+                            .bg(theme::palette().text_primary),
                     ));
                 }
 
@@ -1940,7 +3376,6 @@ fn ui(frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>, app: &App) {
 
             // СПИСОК ФАЙЛОВ С ПРАВИЛЬНЫМ СКРОЛЛИНГОМ
             let files_area = inner_chunks[2];
-            let mut y = 0;
 
             // ВЫЧИСЛЯЕМ СКРОЛЛ ДИНАМИЧЕСКИ (как в основном интерфейсе)
             let scroll_offset = if let Some(selected) = dialog.list_state.selected() {
@@ -1955,7 +3390,13 @@ fn ui(frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>, app: &App) {
             };
 
             // Рендерим только видимые элементы
-            for (i, entry) in dialog.files.iter().enumerate().skip(scroll_offset) {
+            for (y, (i, entry)) in dialog
+                .files
+                .iter()
+                .enumerate()
+                .skip(scroll_offset)
+                .enumerate()
+            {
                 if y >= files_area.height as usize {
                     break;
                 }
@@ -1965,7 +3406,7 @@ fn ui(frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>, app: &App) {
 
                 let style = if Some(original_index) == dialog.list_state.selected() {
                     Style::default()
-                        .fg(theme::TEXT_PRIMARY)
+                        .fg(theme::palette().text_primary)
                         .add_modifier(Modifier::BOLD)
                 } else if entry.is_dir {
                     styles::folder()
@@ -1978,8 +3419,6 @@ fn ui(frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>, app: &App) {
                 let name_paragraph = Paragraph::new(Line::from(Span::styled(&entry.name, style)))
                     .style(styles::surface());
                 frame.render_widget(name_paragraph, line_rect);
-
-                y += 1;
             }
 
             // ПОДСВЕТКА ВЫБРАННОГО ЭЛЕМЕНТА (только если он видим)
@@ -1994,27 +3433,50 @@ fn ui(frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>, app: &App) {
                         files_area.width,
                         1,
                     );
-                    let highlight = Paragraph::new("").style(Style::default().bg(theme::SELECTED));
+                    let highlight = Paragraph::new("").style(Style::default().bg(theme::palette().selected));
                     frame.render_widget(highlight, highlight_rect);
                 }
             }
-            // ПУСТАЯ СТРОКА-РАЗДЕЛИТЕЛЬ
-            let separator_area = inner_chunks[3];
-            let separator = Paragraph::new("").style(styles::surface());
-            frame.render_widget(separator, separator_area);
+            // Что получится: формат по расширению и вид путей
+            let save_format = Paragraph::new(Line::from(vec![
+                Span::styled(
+                    dialog.format_hint(),
+                    Style::default().fg(theme::palette().warning),
+                ),
+                Span::styled(
+                    format!(" Пути: {} ", dialog.path_style.label()),
+                    Style::default().fg(theme::palette().text_secondary),
+                ),
+            ]))
+            .style(styles::surface());
+            frame.render_widget(save_format, inner_chunks[3]);
 
             // Подсказки
             let hints = Paragraph::new(Line::from(vec![
-                Span::styled("Enter: Save  ", Style::default().fg(theme::TEXT_SECONDARY)),
-                Span::styled(" Esc: Cancel  ", Style::default().fg(theme::TEXT_SECONDARY)),
+                Span::styled("Enter: Save  ", Style::default().fg(theme::palette().text_secondary)),
+                Span::styled(" Esc: Cancel  ", Style::default().fg(theme::palette().text_secondary)),
                 Span::styled(
                     " Ctrl+←/→: Navigate ",
-                    Style::default().fg(theme::TEXT_SECONDARY),
+                    Style::default().fg(theme::palette().text_secondary),
+                ),
+                Span::styled(
+                    " Tab: Paths ",
+                    Style::default().fg(theme::palette().text_secondary),
                 ),
             ]));
             frame.render_widget(hints, inner_chunks[4]);
         }
     }
+    if app.show_track_info {
+        render_track_info(frame, app);
+    }
+    if let Some(report) = &app.load_report {
+        render_load_report(frame, app, report);
+    }
+    if app.show_messages {
+        render_messages(frame, app);
+    }
+
     // РЕНДЕРИМ СПРАВКУ ЕСЛИ ОНА ВКЛЮЧЕНА
     if app.show_help {
         let help_area = centered_rect(60, 70, frame.size());
@@ -2037,149 +3499,42 @@ fn ui(frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>, app: &App) {
             ])
             .split(help_area);
 
-        // УВЕЛИЧИВАЕМ ТЕКСТ СПРАВКИ
-        // Текст справки
-        let help_text = vec![
-            Line::from(""),
-            Line::from(vec![Span::styled(
-                "Основное управление:",
-                Style::default()
-                    .fg(theme::PRIMARY)
-                    .add_modifier(Modifier::BOLD),
-            )]),
-            Line::from(""),
-            Line::from(vec![
-                Span::raw("  "),
-                Span::styled("F2", Style::default().fg(theme::SUCCESS)),
-                Span::raw(" - Воспроизведение"),
-            ]),
-            Line::from(vec![
-                Span::raw("  "),
-                Span::styled("F3", Style::default().fg(theme::SUCCESS)),
-                Span::raw(" - Пауза"),
-            ]),
-            Line::from(vec![
-                Span::raw("  "),
-                Span::styled("F4", Style::default().fg(theme::SUCCESS)),
-                Span::raw(" - Стоп"),
-            ]),
-            Line::from(vec![
-                Span::raw("  "),
-                Span::styled("F5", Style::default().fg(theme::SUCCESS)),
-                Span::raw(" - Предыдущий трек"),
-            ]),
-            Line::from(vec![
-                Span::raw("  "),
-                Span::styled("F6", Style::default().fg(theme::SUCCESS)),
-                Span::raw(" - Следующий трек"),
-            ]),
-            Line::from(vec![
-                Span::raw("  "),
-                Span::styled("F9", Style::default().fg(theme::SUCCESS)),
-                Span::raw(" - Сохранить плейлист"),
-            ]),
-            Line::from(""),
-            Line::from(vec![Span::styled(
-                "Навигация:",
-                Style::default()
-                    .fg(theme::PRIMARY)
-                    .add_modifier(Modifier::BOLD),
-            )]),
-            Line::from(""),
-            Line::from(vec![
-                Span::raw("  "),
-                Span::styled("Tab", Style::default().fg(theme::WARNING)),
-                Span::raw(" - Переключение между панелями"),
-            ]),
-            Line::from(vec![
-                Span::raw("  "),
-                Span::styled("↑/↓", Style::default().fg(theme::WARNING)),
-                Span::raw(" - Навигация по спискам"),
-            ]),
-            Line::from(vec![
-                Span::raw("  "),
-                Span::styled("→", Style::default().fg(theme::WARNING)),
-                Span::raw(" - Войти в папку / Добавить в плейлист"),
-            ]),
-            Line::from(vec![
-                Span::raw("  "),
-                Span::styled("←", Style::default().fg(theme::WARNING)),
-                Span::raw(" - Выйти из папки"),
-            ]),
-            Line::from(vec![
-                Span::raw("  "),
-                Span::styled("Enter", Style::default().fg(theme::WARNING)),
-                Span::raw(" - Добавить в плейлист"),
-            ]),
-            Line::from(vec![
-                Span::raw("  "),
-                Span::styled("Delete", Style::default().fg(theme::WARNING)),
-                Span::raw(" - Удалить из плейлиста"),
-            ]),
-            Line::from(""),
-            Line::from(vec![Span::styled(
-                "Громкость:",
-                Style::default()
-                    .fg(theme::PRIMARY)
-                    .add_modifier(Modifier::BOLD),
-            )]),
-            Line::from(""),
-            Line::from(vec![
-                Span::raw("  "),
-                Span::styled("+", Style::default().fg(theme::SECONDARY)),
-                Span::raw(" - Увеличить громкость"),
-            ]),
-            Line::from(vec![
-                Span::raw("  "),
-                Span::styled("-", Style::default().fg(theme::SECONDARY)),
-                Span::raw(" - Уменьшить громкость"),
-            ]),
+        // Клавиши берутся из действующей раскладки, ниже - неизменная часть
+        let mut help_text = help_keymap_lines(&app.keymap);
+        help_text.extend(vec![
             Line::from(""),
             Line::from(vec![Span::styled(
                 "Управление в справке:",
                 Style::default()
-                    .fg(theme::PRIMARY)
+                    .fg(theme::palette().primary)
                     .add_modifier(Modifier::BOLD),
             )]),
             Line::from(""),
             Line::from(vec![
                 Span::raw("  "),
-                Span::styled("↑/↓", Style::default().fg(theme::WARNING)),
+                Span::styled("↑/↓", Style::default().fg(theme::palette().warning)),
                 Span::raw(" - Прокрутка"),
             ]),
             Line::from(vec![
                 Span::raw("  "),
-                Span::styled("PageUp/PageDown", Style::default().fg(theme::WARNING)),
+                Span::styled("PageUp/PageDown", Style::default().fg(theme::palette().warning)),
                 Span::raw(" - Быстрая прокрутка"),
             ]),
             Line::from(vec![
                 Span::raw("  "),
-                Span::styled("Home/End", Style::default().fg(theme::WARNING)),
+                Span::styled("Home/End", Style::default().fg(theme::palette().warning)),
                 Span::raw(" - В начало/конец"),
             ]),
             Line::from(vec![
                 Span::raw("  "),
-                Span::styled("Esc", Style::default().fg(theme::WARNING)),
+                Span::styled("Esc", Style::default().fg(theme::palette().warning)),
                 Span::raw(" - Закрыть справку"),
             ]),
             Line::from(""),
-            Line::from(vec![Span::styled(
-                "Закрытие приложения:",
-                Style::default()
-                    .fg(theme::PRIMARY)
-                    .add_modifier(Modifier::BOLD),
-            )]),
-            Line::from(""),
-            Line::from(vec![
-                Span::raw("  "),
-                Span::styled("Ctrl+Q", Style::default().fg(theme::WARNING)),
-                Span::raw(" - Выйти из приложения"),
-            ]),
-            Line::from(""),
             Line::from(vec![Span::styled(
                 "Поддерживаемые форматы:",
                 Style::default()
-                    .fg(theme::PRIMARY)
+                    .fg(theme::palette().primary)
                     .add_modifier(Modifier::BOLD),
             )]),
             Line::from(""),
@@ -2187,18 +3542,23 @@ fn ui(frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>, app: &App) {
             Line::from("  • WAV - несжатый аудио"),
             Line::from("  • OGG/Vorbis - открытый формат"),
             Line::from("  • AIFF/AIFC - Audio Interchange File Format"),
+            Line::from("  • CAF - Core Audio Format (ALAC, PCM)"),
             Line::from("  • ALAC - Apple Lossless Audio Codec (в M4A/MP4)"),
+            Line::from("  • MP3, AAC - с учетом задержки кодера (gapless)"),
+            Line::from("  • DSD (DSF/DFF) - преобразуется в PCM, см. --dsd-rate"),
             Line::from(""),
             Line::from(vec![Span::styled(
                 "Особенности:",
                 Style::default()
-                    .fg(theme::PRIMARY)
+                    .fg(theme::palette().primary)
                     .add_modifier(Modifier::BOLD),
             )]),
             Line::from(""),
             Line::from("  • Hi-Res аудио поддержка"),
-            Line::from("  • Плейлисты M3U"),
+            Line::from("  • Плейлисты M3U, M3U8, PLS, XSPF и CUE"),
+            Line::from("  • Относительные пути в плейлистах, R - поиск пропавших файлов"),
             Line::from("  • Текстовый интерфейс (TUI)"),
+            Line::from("  • Мышь: выбор, двойной щелчок, колесо, перемотка по полосе"),
             Line::from("  • Написан на Rust"),
             Line::from(""),
             Line::from("Для качественного звука рекомендуется:"),
@@ -2208,7 +3568,7 @@ fn ui(frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>, app: &App) {
             Line::from(""),
             Line::from("Приятного прослушивания! 🎵"),
             Line::from(""),
-        ];
+        ]);
 
         let help_paragraph = Paragraph::new(help_text)
             .style(styles::surface())
@@ -2218,19 +3578,562 @@ fn ui(frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>, app: &App) {
         frame.render_widget(help_paragraph, inner_chunks[0]);
 
         // Обновляем подсказку
+        let close = close_hint(&app.keymap, Action::Help);
         let hint_text = if app.help_scroll > 0 {
             format!(
-                " ↑/↓ - прокрутка | Esc - закрыть (позиция: {}) ",
-                app.help_scroll
+                " ↑/↓ - прокрутка | {} (позиция: {}) ",
+                close, app.help_scroll
             )
         } else {
-            " ↑/↓ - прокрутка | Esc - закрыть ".to_string()
+            format!(" ↑/↓ - прокрутка | {} ", close)
         };
 
         let hint = Paragraph::new(Line::from(Span::styled(
             hint_text,
-            Style::default().fg(theme::TEXT_SECONDARY),
+            Style::default().fg(theme::palette().text_secondary),
         )));
         frame.render_widget(hint, inner_chunks[1]);
     }
+    // Поверх всего, даже диалога сохранения: его ошибки тоже должно быть видно.
+    // В открытом журнале те же сообщения и так видны
+    if !app.show_messages {
+        render_toasts(frame, app, chunks[2].y);
+    }
+    screen
+}
+
+fn level_style(level: Level) -> Style {
+    match level {
+        Level::Info => Style::default().fg(theme::palette().text_primary),
+        Level::Warning => Style::default().fg(theme::palette().warning),
+        Level::Error => Style::default()
+            .fg(theme::palette().error)
+            .add_modifier(Modifier::BOLD),
+    }
+}
+
+// Свежие сообщения справа над строкой статуса, самое новое - нижнее
+fn render_toasts(frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>, app: &App, bottom: u16) {
+    let size = frame.size();
+    let max_width = size.width.saturating_sub(2) * 2 / 3;
+    for (i, message) in app.messages.toasts().into_iter().rev().enumerate() {
+        let Some(y) = bottom.checked_sub(i as u16 + 1) else {
+            break;
+        };
+        let text = format!(" {}: {} ", message.level.label(), message.text);
+        let width = (text.chars().count() as u16).min(max_width);
+        let area = Rect::new(size.width.saturating_sub(width + 1), y, width, 1);
+        frame.render_widget(Clear, area);
+        frame.render_widget(
+            Paragraph::new(text).style(level_style(message.level).bg(theme::palette().selected)),
+            area,
+        );
+    }
+}
+
+// L - все сообщения за сеанс, новые сверху
+fn render_messages(frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>, app: &App) {
+    let area = centered_rect(70, 70, frame.size());
+    frame.render_widget(Clear, area);
+
+    let block = Block::default()
+        .style(styles::surface())
+        .borders(ratatui::widgets::Borders::ALL)
+        .border_style(styles::active_panel())
+        .title(" Журнал сообщений ");
+    frame.render_widget(block, area);
+
+    let inner_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1)
+        .constraints([Constraint::Min(1), Constraint::Length(1)])
+        .split(area);
+
+    let mut lines: Vec<Line> = app
+        .messages
+        .log()
+        .map(|message| {
+            Line::from(vec![
+                Span::styled(
+                    format!(" {} ", message.time),
+                    Style::default().fg(theme::palette().text_secondary),
+                ),
+                Span::styled(
+                    format!("{}: ", message.level.label()),
+                    level_style(message.level),
+                ),
+                Span::raw(message.text.clone()),
+            ])
+        })
+        .collect();
+    if lines.is_empty() {
+        lines.push(Line::from(" Сообщений пока нет"));
+    }
+    // End листает до упора - дальше последней строки не уходим
+    let scroll = app
+        .messages_scroll
+        .min(lines.len().saturating_sub(1) as u16);
+    frame.render_widget(
+        Paragraph::new(lines)
+            .style(styles::surface())
+            .wrap(Wrap { trim: false })
+            .scroll((scroll, 0)),
+        inner_chunks[0],
+    );
+    let hint = Paragraph::new(Line::from(Span::styled(
+        format!(
+            " ↑/↓ - прокрутка | {} ",
+            close_hint(&app.keymap, Action::Messages)
+        ),
+        Style::default().fg(theme::palette().text_secondary),
+    )));
+    frame.render_widget(hint, inner_chunks[1]);
+}
+
+fn render_load_report(
+    frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>,
+    app: &App,
+    report: &LoadReport,
+) {
+    let area = centered_rect(60, 50, frame.size());
+    frame.render_widget(Clear, area);
+
+    let block = Block::default()
+        .style(styles::surface())
+        .borders(ratatui::widgets::Borders::ALL)
+        .border_style(styles::active_panel())
+        .title(format!(" Плейлист {} ", report.name));
+    frame.render_widget(block, area);
+
+    let inner_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1)
+        .constraints([Constraint::Min(1), Constraint::Length(1)])
+        .split(area);
+
+    let field = |name: &str, value: String| {
+        Line::from(vec![
+            Span::styled(
+                format!("  {:<14}", name),
+                Style::default().fg(theme::palette().text_secondary),
+            ),
+            Span::raw(value),
+        ])
+    };
+
+    let mut lines = vec![Line::from("")];
+    if let Some(error) = &report.error {
+        lines.push(field("Ошибка", error.clone()));
+    } else {
+        let entries = app.playlist.get(report.added.clone()).unwrap_or_default();
+        let broken: Vec<&PlaylistEntry> = entries.iter().filter(|e| e.broken.is_some()).collect();
+        let pending = entries
+            .iter()
+            .filter(|e| e.broken.is_none() && app.prober.is_pending(&e.path))
+            .count();
+        lines.push(field("Добавлено", entries.len().to_string()));
+        lines.push(field("Недоступны", broken.len().to_string()));
+        if pending > 0 {
+            lines.push(field("Проверяются", pending.to_string()));
+        }
+        if !broken.is_empty() {
+            lines.push(Line::from(""));
+        }
+        // Сколько строк влезает; последняя уходит на "и еще"
+        let room = (inner_chunks[0].height as usize).saturating_sub(lines.len());
+        let shown = if broken.len() > room {
+            room.saturating_sub(1)
+        } else {
+            broken.len()
+        };
+        for entry in &broken[..shown] {
+            lines.push(Line::from(vec![
+                Span::raw(format!("  {} - ", entry.name)),
+                Span::styled(
+                    entry.broken.map_or("", Broken::reason),
+                    Style::default().fg(theme::palette().warning),
+                ),
+            ]));
+        }
+        if shown < broken.len() {
+            lines.push(Line::from(format!("  ... и еще {}", broken.len() - shown)));
+        }
+    }
+
+    frame.render_widget(
+        Paragraph::new(lines).style(styles::surface()),
+        inner_chunks[0],
+    );
+    // Подсказка по текущей раскладке: не назначенное действие не показываем
+    let mut hint: Vec<String> = [
+        (Action::Relink, "найти"),
+        (Action::PurgeBroken, "убрать недоступные"),
+    ]
+    .into_iter()
+    .filter_map(|(action, label)| Some(format!("{} - {}", app.keymap.keys(action)?, label)))
+    .collect();
+    hint.push("Esc - закрыть".to_string());
+    let hint = Paragraph::new(Line::from(Span::styled(
+        format!(" {} ", hint.join(" | ")),
+        Style::default().fg(theme::palette().text_secondary),
+    )));
+    frame.render_widget(hint, inner_chunks[1]);
+}
+
+// Разделы справки по раскладке: клавиши окрашены по разделу
+fn help_keymap_lines(keymap: &Keymap) -> Vec<Line<'static>> {
+    let mut lines = Vec::new();
+    for (group, entries) in keymap.help() {
+        let color = match group {
+            Group::Playback => theme::palette().success,
+            Group::Volume => theme::palette().secondary,
+            Group::Navigation | Group::Application => theme::palette().warning,
+        };
+        lines.push(Line::from(""));
+        lines.push(Line::from(vec![Span::styled(
+            group.title(),
+            Style::default()
+                .fg(theme::palette().primary)
+                .add_modifier(Modifier::BOLD),
+        )]));
+        lines.push(Line::from(""));
+        for entry in entries {
+            lines.push(Line::from(vec![
+                Span::raw("  "),
+                Span::styled(entry.keys, Style::default().fg(color)),
+                Span::raw(format!(" - {}", entry.description)),
+            ]));
+        }
+        // Переход по цифрам не переназначается
+        if group == Group::Playback {
+            lines.push(Line::from(vec![
+                Span::raw("  "),
+                Span::styled("0-9", Style::default().fg(color)),
+                Span::raw(" - Переход к 0%..90% трека"),
+            ]));
+        }
+    }
+    lines
+}
+
+// Окно свойств текущего трека: теги и технические параметры потока
+fn render_track_info(frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>, app: &App) {
+    let area = centered_rect(60, 70, frame.size());
+    frame.render_widget(Clear, area);
+
+    let block = Block::default()
+        .style(styles::surface())
+        .borders(ratatui::widgets::Borders::ALL)
+        .border_style(styles::active_panel())
+        .title(" Свойства трека ");
+    frame.render_widget(block, area);
+
+    let inner_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1)
+        .constraints([Constraint::Min(1), Constraint::Length(1)])
+        .split(area);
+    let hint = Paragraph::new(Line::from(Span::styled(
+        format!(" {} ", close_hint(&app.keymap, Action::TrackInfo)),
+        Style::default().fg(theme::palette().text_secondary),
+    )));
+    frame.render_widget(hint, inner_chunks[1]);
+
+    let section = |title: &'static str| {
+        Line::from(Span::styled(
+            title,
+            Style::default()
+                .fg(theme::palette().primary)
+                .add_modifier(Modifier::BOLD),
+        ))
+    };
+    let field = |name: &str, value: String| {
+        Line::from(vec![
+            Span::styled(
+                format!("  {:<14}", name),
+                Style::default().fg(theme::palette().text_secondary),
+            ),
+            Span::raw(value),
+        ])
+    };
+    let of_total = |n: Option<u32>, total: Option<u32>| match (n, total) {
+        (Some(n), Some(total)) => Some(format!("{}/{}", n, total)),
+        (Some(n), None) => Some(n.to_string()),
+        _ => None,
+    };
+
+    let mut lines = vec![Line::from("")];
+    let Some(Track { path, span }) = &app.current_track else {
+        lines.push(Line::from("  Ничего не играет"));
+        frame.render_widget(Paragraph::new(lines), inner_chunks[0]);
+        return;
+    };
+
+    let info = &app.current_track_info;
+    lines.push(section("Теги:"));
+    lines.push(field(
+        "Файл",
+        path.file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    ));
+    // Трек CUE: где он лежит внутри образа
+    if let Some(span) = span {
+        let end = span.end.map_or_else(|| "конец".to_string(), format_time);
+        lines.push(field(
+            "Часть образа",
+            format!("{} - {}", format_time(span.start), end),
+        ));
+    }
+    let tags = [
+        ("Название", info.title.clone()),
+        ("Исполнитель", info.artist.clone()),
+        ("Альбом", info.album.clone()),
+        ("Исп. альбома", info.album_artist.clone()),
+        ("Композитор", info.composer.clone()),
+        ("Жанр", info.genre.clone()),
+        ("Дата", info.date.clone()),
+        ("Трек", of_total(info.track_number, info.track_total)),
+        ("Диск", of_total(info.disc_number, info.disc_total)),
+    ];
+    for (name, value) in tags {
+        if let Some(value) = value {
+            lines.push(field(name, value));
+        }
+    }
+
+    if let Some(stream) = &app.current_stream_info {
+        lines.push(Line::from(""));
+        lines.push(section("Поток:"));
+        lines.push(field("Кодек", stream.codec.to_string()));
+        lines.push(field(
+            "Частота",
+            format!("{} kHz", audio_engine::khz(stream.sample_rate)),
+        ));
+        lines.push(field(
+            "Разрядность",
+            stream
+                .bits_per_sample
+                .map_or("-".to_string(), |bits| format!("{} бит", bits)),
+        ));
+        lines.push(field("Каналы", stream.channels.to_string()));
+        lines.push(field(
+            "Битрейт",
+            stream
+                .bitrate
+                .map_or("-".to_string(), audio_engine::format_bitrate),
+        ));
+        lines.push(field(
+            "Кадров",
+            stream.n_frames.map_or("-".to_string(), |n| n.to_string()),
+        ));
+        lines.push(field("Длительность", format_duration(info.duration)));
+        if stream.pcm_rate != stream.sample_rate {
+            lines.push(field(
+                "PCM",
+                format!("{} kHz", audio_engine::khz(stream.pcm_rate)),
+            ));
+        }
+
+        lines.push(Line::from(""));
+        lines.push(section("Вывод:"));
+        match &app.output_format {
+            Some(output) => {
+                lines.push(field("Устройство", output.to_string()));
+                let buffer = match output.buffer_size {
+                    Some(frames) => format!("{} кадров", frames),
+                    None => "по умолчанию драйвера".to_string(),
+                };
+                lines.push(field("Буфер", buffer));
+                let resampling = if output.sample_rate == stream.pcm_rate {
+                    "нет".to_string()
+                } else {
+                    format!(
+                        "{} → {} kHz",
+                        audio_engine::khz(stream.pcm_rate),
+                        audio_engine::khz(output.sample_rate)
+                    )
+                };
+                lines.push(field("Ресемплинг", resampling));
+            }
+            None => lines.push(field("Устройство", "формат неизвестен".to_string())),
+        }
+    }
+
+    frame.render_widget(
+        Paragraph::new(lines).style(styles::surface()),
+        inner_chunks[0],
+    );
+}
+
+// Библиотека на месте файлового менеджера: заголовок с путем по дереву и список
+// Блок "дальше": заголовок, треки по порядку, пустая строка-разделитель
+fn render_up_next(frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>, app: &App, area: Rect) {
+    let mut lines = vec![Line::from(vec![
+        Span::styled(" UP NEXT ", styles::active_panel()),
+        Span::styled(
+            format!(" {} ", app.up_next.len()),
+            Style::default().fg(theme::palette().text_secondary),
+        ),
+    ])];
+    let rows = area.height.saturating_sub(2) as usize;
+    let width = area.width as usize;
+    for (i, entry) in app.up_next.iter().enumerate().take(rows) {
+        let duration = if entry.info.duration.is_none() && app.prober.is_pending(&entry.path) {
+            "[..:..]".to_string()
+        } else {
+            format_duration(entry.info.duration)
+        };
+        // Не влезли - последняя строка говорит, сколько еще
+        let name = if i + 1 == rows && app.up_next.len() > rows {
+            format!("  … еще {}", app.up_next.len() - i)
+        } else {
+            format!("  {}. {}", i + 1, display_name(&entry.info, &entry.name))
+        };
+        let name: String = name
+            .chars()
+            .take(width.saturating_sub(duration.chars().count() + 1))
+            .collect();
+        let gap = width.saturating_sub(name.chars().count() + duration.chars().count());
+        lines.push(Line::from(vec![
+            Span::styled(name, styles::normal_file()),
+            Span::raw(" ".repeat(gap)),
+            Span::styled(duration, styles::inactive_text()),
+        ]));
+    }
+    frame.render_widget(Paragraph::new(lines).style(styles::surface()), area);
+}
+
+fn render_library(
+    frame: &mut ratatui::Frame<CrosstermBackend<io::Stdout>>,
+    app: &App,
+    area: Rect,
+) -> ListArea {
+    use styles::*;
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(1), // Заголовок
+            Constraint::Length(1), // Пустая строка (разделитель)
+            Constraint::Min(1),    // Список
+        ])
+        .split(area);
+    let active = app.active_panel == 2;
+
+    // Путь по дереву: LIBRARY › Исполнитель › Альбом
+    let artists = app.library.artists();
+    let mut title = " LIBRARY ".to_string();
+    match app.library_level {
+        LibraryLevel::Artists => {}
+        LibraryLevel::Albums(artist) => {
+            title.push_str(&format!("› {} ", artists[artist].name));
+        }
+        LibraryLevel::Tracks(artist, album) => {
+            title.push_str(&format!(
+                "› {} › {} ",
+                artists[artist].name, artists[artist].albums[album].title
+            ));
+        }
+    }
+
+    // Справа - ход сканирования или размер библиотеки
+    let status = if let Some(progress) = app.library.scan_progress() {
+        if progress.to_probe > 0 {
+            format!("сканирование: {}/{} ", progress.probed, progress.to_probe)
+        } else {
+            format!("сканирование: найдено {} ", progress.found)
+        }
+    } else if app.library.roots().is_empty() {
+        "нет папок: --library DIR ".to_string()
+    } else {
+        let mut status = format!("{} треков ", app.library.track_count());
+        if let Some(summary) = app
+            .library_summary
+            .filter(|s| s.added + s.updated + s.removed > 0)
+        {
+            status.push_str(&format!(
+                "(+{} ~{} -{}) ",
+                summary.added, summary.updated, summary.removed
+            ));
+        }
+        status
+    };
+
+    let title_chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Min(0),
+            Constraint::Length(status.chars().count() as u16),
+        ])
+        .split(chunks[0]);
+    let title_style = if active {
+        active_panel()
+    } else {
+        inactive_panel()
+    };
+    frame.render_widget(
+        Paragraph::new(Line::from(Span::styled(title, title_style))).style(surface()),
+        title_chunks[0],
+    );
+    frame.render_widget(
+        Paragraph::new(Line::from(Span::styled(status, inactive_text()))).style(surface()),
+        title_chunks[1],
+    );
+    frame.render_widget(Paragraph::new("").style(surface()), chunks[1]);
+
+    let list_area = chunks[2];
+    let visible = list_area.height as usize;
+    let selected = app.library_list_state.selected();
+    let scroll_offset = match selected {
+        Some(selected) if selected >= visible => selected - visible + 1,
+        _ => 0,
+    };
+    let shown = visible.min(app.library_len().saturating_sub(scroll_offset));
+
+    for y in 0..shown {
+        let row = scroll_offset + y;
+        let (name, right) = app.library_row(row);
+        let style = if !active {
+            inactive_text()
+        } else if Some(row) == selected {
+            Style::default()
+                .fg(theme::palette().text_primary)
+                .add_modifier(Modifier::BOLD)
+        } else if matches!(app.library_level, LibraryLevel::Tracks(..)) {
+            normal_file()
+        } else {
+            folder()
+        };
+
+        let line_rect = Rect::new(list_area.x, list_area.y + y as u16, list_area.width, 1);
+        let line_chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Min(0), Constraint::Length(9)])
+            .split(line_rect);
+        frame.render_widget(
+            Paragraph::new(Line::from(Span::styled(format!("   {}", name), style)))
+                .style(surface()),
+            line_chunks[0],
+        );
+        frame.render_widget(
+            Paragraph::new(Line::from(Span::styled(right, style)))
+                .style(surface())
+                .alignment(ratatui::layout::Alignment::Right),
+            line_chunks[1],
+        );
+
+        if Some(row) == selected {
+            let highlight = Paragraph::new("").style(if active {
+                highlight_active()
+            } else {
+                highlight_inactive()
+            });
+            frame.render_widget(highlight, line_rect);
+        }
+    }
+    ListArea {
+        area: list_area,
+        rows: (scroll_offset..scroll_offset + shown).collect(),
+    }
 }
@@ -0,0 +1,147 @@
+// Сообщения для пользователя: всплывают над строкой статуса и копятся в журнале.
+// stderr отправлен в /dev/null (см. suppress_alsa_warnings) и спрятан за
+// альтернативным экраном - eprintln никто не увидит
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Журнал в памяти не растет бесконечно - в файле остается все
+const LOG_LIMIT: usize = 500;
+// Сколько видно всплывающее сообщение; ошибку дольше - ее нужно успеть прочитать
+const TOAST_TIME: Duration = Duration::from_secs(4);
+const ERROR_TOAST_TIME: Duration = Duration::from_secs(8);
+const MAX_TOASTS: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Level {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Level {
+    pub fn label(self) -> &'static str {
+        match self {
+            Level::Info => "инфо",
+            Level::Warning => "внимание",
+            Level::Error => "ошибка",
+        }
+    }
+
+    fn toast_time(self) -> Duration {
+        match self {
+            Level::Error => ERROR_TOAST_TIME,
+            Level::Info | Level::Warning => TOAST_TIME,
+        }
+    }
+}
+
+pub struct Message {
+    pub level: Level,
+    pub text: String,
+    /// Местное время ЧЧ:ММ:СС - для журнала
+    pub time: String,
+    at: Instant,
+}
+
+#[derive(Default)]
+pub struct Messages {
+    log: VecDeque<Message>,
+    file: Option<File>,
+}
+
+impl Messages {
+    /// log_file - куда дублировать журнал, дописывая в конец
+    pub fn new(log_file: Option<&Path>) -> Self {
+        let mut messages = Messages::default();
+        if let Some(path) = log_file {
+            match open_log(path) {
+                Ok(file) => messages.file = Some(file),
+                Err(e) => messages.warning(format!("Журнал {} не открылся: {}", path.display(), e)),
+            }
+        }
+        messages
+    }
+
+    pub fn info(&mut self, text: impl Into<String>) {
+        self.push(Level::Info, text.into());
+    }
+
+    pub fn warning(&mut self, text: impl Into<String>) {
+        self.push(Level::Warning, text.into());
+    }
+
+    pub fn error(&mut self, text: impl Into<String>) {
+        self.push(Level::Error, text.into());
+    }
+
+    fn push(&mut self, level: Level, text: String) {
+        // Сообщение - одна строка и на экране, и в файле
+        let text = text.replace(['\n', '\r'], " ");
+        let (date, time) = local_time(SystemTime::now());
+        if let Some(file) = &mut self.file {
+            // Диск кончился или файл удалили - дальше только в памяти
+            if writeln!(file, "{} {} [{}] {}", date, time, level.label(), text).is_err() {
+                self.file = None;
+            }
+        }
+        if self.log.len() == LOG_LIMIT {
+            self.log.pop_front();
+        }
+        self.log.push_back(Message {
+            level,
+            text,
+            time,
+            at: Instant::now(),
+        });
+    }
+
+    /// Еще не погасшие сообщения, новое - последним
+    pub fn toasts(&self) -> Vec<&Message> {
+        let mut toasts: Vec<&Message> = self
+            .log
+            .iter()
+            .rev()
+            .filter(|message| message.at.elapsed() < message.level.toast_time())
+            .take(MAX_TOASTS)
+            .collect();
+        toasts.reverse();
+        toasts
+    }
+
+    /// Журнал от новых к старым
+    pub fn log(&self) -> impl Iterator<Item = &Message> {
+        self.log.iter().rev()
+    }
+}
+
+fn open_log(path: &Path) -> io::Result<File> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+// Дата и время по часовому поясу системы
+fn local_time(time: SystemTime) -> (String, String) {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs()) as libc::time_t;
+    // localtime_r только заполняет переданную структуру
+    let tm = unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        libc::localtime_r(&seconds, &mut tm);
+        tm
+    };
+    (
+        format!(
+            "{:04}-{:02}-{:02}",
+            tm.tm_year + 1900,
+            tm.tm_mon + 1,
+            tm.tm_mday
+        ),
+        format!("{:02}:{:02}:{:02}", tm.tm_hour, tm.tm_min, tm.tm_sec),
+    )
+}
@@ -0,0 +1,34 @@
+// Каталоги программы по XDG Base Directory
+use std::env;
+use std::path::PathBuf;
+
+const APP_DIR: &str = "hi-res-player";
+
+/// $XDG_CACHE_HOME/hi-res-player, по умолчанию ~/.cache/hi-res-player
+pub fn cache_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_CACHE_HOME", ".cache")
+}
+
+/// $XDG_DATA_HOME/hi-res-player, по умолчанию ~/.local/share/hi-res-player
+pub fn data_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_DATA_HOME", ".local/share")
+}
+
+/// $XDG_CONFIG_HOME/hi-res-player, по умолчанию ~/.config/hi-res-player
+pub fn config_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// $XDG_STATE_HOME/hi-res-player, по умолчанию ~/.local/state/hi-res-player
+pub fn state_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_STATE_HOME", ".local/state")
+}
+
+fn xdg_dir(var: &str, fallback: &str) -> Option<PathBuf> {
+    // По спецификации относительные пути в XDG_* игнорируются
+    let base = env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(fallback)))?;
+    Some(base.join(APP_DIR))
+}
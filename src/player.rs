@@ -0,0 +1,814 @@
+// Движок воспроизведения в отдельном потоке.
+// UI (и любое будущее удаленное управление) отправляет команды и читает события -
+// медленная загрузка папки больше не мешает переключению треков.
+mod order;
+
+use crate::audio_engine::{self, khz, OpenOptions, SourceHandle, Span, StreamInfo, TrackInfo};
+use order::PlayOrder;
+pub use order::PlaybackMode;
+use rodio::cpal::traits::{HostTrait, StreamTrait};
+use rodio::cpal::{BufferSize, FromSample, SizedSample, SupportedBufferSize};
+use rodio::dynamic_mixer::{self, DynamicMixer, DynamicMixerController};
+use rodio::{cpal, DeviceTrait, OutputStream, OutputStreamHandle, PlayError, Sink};
+use std::collections::VecDeque;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+// Как часто движок проверяет конец трека и публикует позицию
+const TICK: Duration = Duration::from_millis(50);
+
+/// Трек очереди: файл целиком или его часть (трек CUE в образе альбома)
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Track {
+    pub path: PathBuf,
+    pub span: Option<Span>,
+}
+
+impl Track {
+    pub fn file(path: PathBuf) -> Self {
+        Track { path, span: None }
+    }
+}
+
+pub enum Command {
+    /// Продолжить после паузы
+    Play,
+    /// Запустить трек очереди по индексу
+    PlayIndex(usize),
+    /// Запустить файл вне очереди (из файловой панели)
+    PlayFile(Track),
+    /// Открыть трек очереди на паузе с заданной позиции (восстановление сессии)
+    Cue(usize, Duration),
+    Pause,
+    Stop,
+    Seek(Duration),
+    Next,
+    Prev,
+    SetVolume(f32),
+    SetMode(PlaybackMode),
+    /// Заменить очередь, не прерывая текущий трек
+    LoadQueue(Vec<Track>),
+    /// Сыграть треки сразу после текущего, раньше остального списка "дальше"
+    QueueNext(Vec<Track>),
+    /// Добавить треки в конец списка "дальше"
+    QueueAppend(Vec<Track>),
+    ClearUpNext,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PlayerState {
+    Stopped,
+    Playing,
+    Paused,
+}
+
+pub enum Event {
+    TrackStarted {
+        index: Option<usize>,
+        track: Track,
+        info: Box<TrackInfo>,
+        stream: StreamInfo,
+    },
+    /// Устройство вывода открыто в этом формате
+    OutputOpened(OutputFormat),
+    Position(Duration),
+    /// Сколько поврежденных пакетов текущего трека пропущено
+    PacketsSkipped(u64),
+    TrackEnded(Track),
+    /// Список "дальше" изменился: трек начал играть или добавлены новые
+    UpNextChanged(Vec<Track>),
+    StateChanged(PlayerState),
+    Error(String),
+}
+
+/// Формат, согласованный с устройством вывода.
+/// Если частота отличается от частоты трека, rodio ресемплирует
+#[derive(Clone)]
+pub struct OutputFormat {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub sample_format: String,
+    /// Буфер в кадрах; None - размер выбрал драйвер
+    pub buffer_size: Option<u32>,
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} kHz {}ch {}",
+            khz(self.sample_rate),
+            self.channels,
+            self.sample_format
+        )
+    }
+}
+
+// Устройство вывода, к которому подключается каждый новый Sink
+enum Output {
+    // Свой поток cpal: rodio::OutputStream не дает задать размер буфера
+    Mixer {
+        mixer: Arc<DynamicMixerController<f32>>,
+        _stream: cpal::Stream,
+    },
+    // Запасной путь: устройство и формат подобрал rodio
+    Rodio {
+        _stream: OutputStream,
+        handle: OutputStreamHandle,
+    },
+}
+
+impl Output {
+    fn sink(&self) -> Result<Sink, PlayError> {
+        match self {
+            Output::Mixer { mixer, .. } => {
+                let (sink, queue) = Sink::new_idle();
+                mixer.add(queue);
+                Ok(sink)
+            }
+            Output::Rodio { handle, .. } => Sink::try_new(handle),
+        }
+    }
+}
+
+/// Канал управления движком; поток завершается вместе с Player
+pub struct Player {
+    commands: Sender<Command>,
+    events: Receiver<Event>,
+}
+
+impl Player {
+    /// buffer_size - желаемый буфер вывода в кадрах
+    pub fn spawn(options: OpenOptions, mode: PlaybackMode, buffer_size: u32) -> Self {
+        let (command_tx, command_rx) = mpsc::channel();
+        let (event_tx, event_rx) = mpsc::channel();
+
+        thread::Builder::new()
+            .name("player".into())
+            .spawn(move || Engine::new(options, mode, buffer_size, event_tx).run(command_rx))
+            .expect("не удалось запустить поток воспроизведения");
+
+        Player {
+            commands: command_tx,
+            events: event_rx,
+        }
+    }
+
+    pub fn send(&self, command: Command) {
+        // Поток живет, пока жив Player - ошибка отправки невозможна
+        let _ = self.commands.send(command);
+    }
+
+    pub fn try_recv(&self) -> Option<Event> {
+        self.events.try_recv().ok()
+    }
+}
+
+// Откуда взят запускаемый трек
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Origin {
+    Queue(usize),
+    UpNext,
+    File,
+}
+
+struct CurrentTrack {
+    // None - трек запущен вне очереди или удален из нее
+    index: Option<usize>,
+    track: Track,
+    handle: SourceHandle,
+}
+
+// Следующий трек, заранее открытый и стоящий в очереди Sink (gapless)
+struct QueuedTrack {
+    origin: Origin,
+    track: Track,
+    handle: SourceHandle,
+    info: TrackInfo,
+    stream: StreamInfo,
+}
+
+struct Engine {
+    options: OpenOptions,
+    events: Sender<Event>,
+    queue: Vec<Track>,
+    // Играют раньше очереди; пока они играют, очередь ждет на next_index
+    up_next: VecDeque<Track>,
+
+    // Устройство вывода открывается один раз и живет между треками.
+    // Поток cpal не Send, поэтому создается прямо в потоке движка
+    output: Option<Output>,
+    buffer_size: u32,
+    sink: Option<Sink>,
+    volume: f32,
+
+    current: Option<CurrentTrack>,
+    order: PlayOrder,
+    // Что играть после текущего трека
+    next_index: Option<usize>,
+    // Трек запускается по F5 - история перемешивания уже на месте
+    returning: bool,
+    queued: Option<QueuedTrack>,
+    // Уже пытались заранее открыть следующий трек
+    preload_attempted: bool,
+    last_position: Option<Duration>,
+    last_skipped: u64,
+}
+
+impl Engine {
+    fn new(
+        options: OpenOptions,
+        mode: PlaybackMode,
+        buffer_size: u32,
+        events: Sender<Event>,
+    ) -> Self {
+        Engine {
+            options,
+            events,
+            queue: Vec::new(),
+            up_next: VecDeque::new(),
+            output: None,
+            buffer_size,
+            sink: None,
+            volume: 1.0,
+            current: None,
+            order: PlayOrder::new(mode),
+            next_index: None,
+            returning: false,
+            queued: None,
+            preload_attempted: false,
+            last_position: None,
+            last_skipped: 0,
+        }
+    }
+
+    fn run(mut self, commands: Receiver<Command>) {
+        loop {
+            match commands.recv_timeout(TICK) {
+                Ok(command) => self.handle(command),
+                Err(RecvTimeoutError::Timeout) => {}
+                // Player уничтожен - выходим, Sink и устройство закроются сами
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            self.tick();
+        }
+    }
+
+    fn emit(&self, event: Event) {
+        let _ = self.events.send(event);
+    }
+
+    fn state(&self) -> PlayerState {
+        match &self.sink {
+            Some(sink) if sink.is_paused() => PlayerState::Paused,
+            Some(_) => PlayerState::Playing,
+            None => PlayerState::Stopped,
+        }
+    }
+
+    fn handle(&mut self, command: Command) {
+        let result = match command {
+            Command::Play => {
+                if let Some(sink) = &self.sink {
+                    sink.play();
+                }
+                Ok(())
+            }
+            Command::PlayIndex(index) => self.play_index(index),
+            Command::PlayFile(track) => self.play_track(&track, Origin::File, false),
+            Command::Cue(index, position) => self.cue(index, position),
+            Command::Pause => {
+                if let Some(sink) = &self.sink {
+                    sink.pause();
+                }
+                Ok(())
+            }
+            Command::Stop => {
+                self.stop();
+                Ok(())
+            }
+            Command::Seek(target) => {
+                if let Some(current) = &self.current {
+                    current.handle.seek(target);
+                }
+                Ok(())
+            }
+            Command::Next => self.play_next(true),
+            Command::Prev => self.play_previous(),
+            Command::SetVolume(volume) => {
+                self.volume = volume.clamp(0.0, 1.0);
+                if let Some(sink) = &self.sink {
+                    sink.set_volume(self.volume);
+                }
+                Ok(())
+            }
+            Command::SetMode(mode) => {
+                let current = self.current.as_ref().and_then(|c| c.index);
+                self.order.set_mode(mode, current, self.queue.len());
+                self.refresh_next();
+                Ok(())
+            }
+            Command::LoadQueue(queue) => {
+                self.load_queue(queue);
+                Ok(())
+            }
+            Command::QueueNext(tracks) => {
+                for (i, track) in tracks.into_iter().enumerate() {
+                    self.up_next.insert(i, track);
+                }
+                self.up_next_changed();
+                Ok(())
+            }
+            Command::QueueAppend(tracks) => {
+                self.up_next.extend(tracks);
+                self.up_next_changed();
+                Ok(())
+            }
+            Command::ClearUpNext => {
+                self.up_next.clear();
+                self.up_next_changed();
+                Ok(())
+            }
+        };
+
+        if let Err(e) = result {
+            self.emit(Event::Error(e.to_string()));
+        }
+        self.emit(Event::StateChanged(self.state()));
+    }
+
+    // Новый Sink на общем устройстве вывода (оно открывается один раз за сессию)
+    fn new_sink(&mut self) -> Result<Sink, Box<dyn std::error::Error>> {
+        if self.output.is_none() {
+            let output = match open_default_output(self.buffer_size, &self.events) {
+                Some((output, format)) => {
+                    self.emit(Event::OutputOpened(format));
+                    output
+                }
+                // Формат по умолчанию не подошел - пусть rodio сам подберет устройство
+                None => {
+                    let (stream, handle) = OutputStream::try_default()?;
+                    Output::Rodio {
+                        _stream: stream,
+                        handle,
+                    }
+                }
+            };
+            self.output = Some(output);
+        }
+        let output = self.output.as_ref().expect("устройство только что открыто");
+        Ok(output.sink()?)
+    }
+
+    fn stop(&mut self) {
+        if let Some(sink) = self.sink.take() {
+            sink.stop();
+        }
+        self.current = None;
+        self.next_index = None;
+        self.queued = None;
+        self.preload_attempted = false;
+        self.last_position = None;
+    }
+
+    // Запускает трек с нуля в новом Sink на том же устройстве
+    fn play_track(
+        &mut self,
+        track: &Track,
+        origin: Origin,
+        paused: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // Очередь продолжится с того места, где ее прервал список "дальше"
+        let resume = self.next_index;
+        self.stop();
+
+        let sink = self.new_sink()?;
+        sink.set_volume(self.volume);
+        let opened = match audio_engine::open_track(&track.path, track.span, self.options) {
+            Ok(opened) => opened,
+            Err(e) => {
+                // Очередь продолжится со следующего трека, а не встанет на этом
+                self.next_index = match origin {
+                    Origin::Queue(i) => self.order.next(Some(i), i + 1, self.queue.len(), false),
+                    _ => resume,
+                };
+                return Err(e);
+            }
+        };
+        // Пауза до append - ни один сэмпл не успеет прозвучать
+        if paused {
+            sink.pause();
+        }
+        sink.append(opened.source);
+
+        self.sink = Some(sink);
+        self.next_index = resume;
+        self.start_track(
+            origin,
+            track.clone(),
+            opened.handle,
+            opened.info,
+            opened.stream,
+        );
+        self.preload_next();
+        Ok(())
+    }
+
+    fn play_index(&mut self, index: usize) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(track) = self.queue.get(index).cloned() else {
+            return Ok(());
+        };
+        self.play_track(&track, Origin::Queue(index), false)
+    }
+
+    fn cue(&mut self, index: usize, position: Duration) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(track) = self.queue.get(index).cloned() else {
+            return Ok(());
+        };
+        self.play_track(&track, Origin::Queue(index), true)?;
+        if let Some(current) = &self.current {
+            current.handle.seek(position);
+        }
+        Ok(())
+    }
+
+    fn start_track(
+        &mut self,
+        origin: Origin,
+        track: Track,
+        handle: SourceHandle,
+        info: TrackInfo,
+        stream: StreamInfo,
+    ) {
+        let index = match origin {
+            Origin::Queue(i) => {
+                self.order.started(i, std::mem::take(&mut self.returning));
+                self.next_index = self.order.next(Some(i), i + 1, self.queue.len(), false);
+                Some(i)
+            }
+            Origin::UpNext => None,
+            Origin::File => {
+                self.next_index = None;
+                None
+            }
+        };
+        // Повтор трека заранее открывает тот же индекс еще раз
+        self.preload_attempted = false;
+        self.last_position = None;
+        self.last_skipped = 0;
+        self.emit(Event::TrackStarted {
+            index,
+            track: track.clone(),
+            info: Box::new(info),
+            stream,
+        });
+        self.current = Some(CurrentTrack {
+            index,
+            track,
+            handle,
+        });
+    }
+
+    // manual - F6: повтор трека не держит на месте, переходим дальше
+    fn play_next(&mut self, manual: bool) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.up_next.is_empty() {
+            if let (Some(sink), Some(queued)) = (&self.sink, &self.queued) {
+                if queued.origin == Origin::UpNext {
+                    sink.skip_one();
+                    self.promote_queued_track();
+                    return Ok(());
+                }
+            }
+            // Снимаем трек со списка сразу: если он не откроется, F6 не застрянет на нем
+            let track = self.take_up_next();
+            return self.play_track(&track, Origin::UpNext, false);
+        }
+
+        let len = self.queue.len();
+        let next = match &self.current {
+            Some(CurrentTrack { index: Some(i), .. }) if manual => {
+                self.order.next(Some(*i), i + 1, len, true)
+            }
+            _ => self.next_index,
+        };
+        let Some(next_index) = next.filter(|&i| i < len) else {
+            // Очередь закончилась
+            self.stop();
+            return Ok(());
+        };
+
+        // Следующий трек уже открыт и стоит в очереди - просто пропускаем текущий
+        if let (Some(sink), Some(queued)) = (&self.sink, &self.queued) {
+            if queued.origin == Origin::Queue(next_index) {
+                sink.skip_one();
+                self.promote_queued_track();
+                return Ok(());
+            }
+        }
+
+        self.play_index(next_index)
+    }
+
+    // Автопереход: трек, который не открылся (файла нет, формат не читается),
+    // пропускаем - но не больше одного круга по очереди
+    fn advance(&mut self) {
+        for _ in 0..=self.queue.len() + self.up_next.len() {
+            match self.play_next(false) {
+                Ok(()) => return,
+                Err(e) => self.emit(Event::Error(e.to_string())),
+            }
+        }
+        self.stop();
+    }
+
+    fn play_previous(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let previous = match &self.current {
+            Some(CurrentTrack { index: Some(i), .. }) => {
+                let previous = self.order.previous(*i, self.queue.len());
+                self.returning = previous.is_some();
+                previous
+            }
+            // Текущий трек удален из очереди - предыдущий стоит перед следующим
+            _ => self.next_index.and_then(|n| n.checked_sub(1)),
+        };
+        let result = match previous {
+            Some(index) => self.play_index(index),
+            None => Ok(()),
+        };
+        self.returning = false;
+        result
+    }
+
+    // Что заиграет после текущего трека: сначала список "дальше", потом очередь
+    fn upcoming(&self) -> Option<(Origin, Track)> {
+        if let Some(track) = self.up_next.front() {
+            return Some((Origin::UpNext, track.clone()));
+        }
+        let index = self.next_index?;
+        Some((Origin::Queue(index), self.queue.get(index)?.clone()))
+    }
+
+    fn take_up_next(&mut self) -> Track {
+        let track = self.up_next.pop_front().unwrap_or_default();
+        self.emit(Event::UpNextChanged(self.up_next.iter().cloned().collect()));
+        track
+    }
+
+    fn up_next_changed(&mut self) {
+        self.emit(Event::UpNextChanged(self.up_next.iter().cloned().collect()));
+        self.refresh_next_queued();
+    }
+
+    // Заранее открываем следующий трек и ставим в очередь того же Sink:
+    // переход произойдет ровно на последнем сэмпле текущего трека
+    fn preload_next(&mut self) {
+        if self.queued.is_some() || self.preload_attempted {
+            return;
+        }
+        let Some((origin, track)) = self.upcoming() else {
+            return;
+        };
+        let Some(sink) = &self.sink else {
+            return;
+        };
+
+        self.preload_attempted = true;
+        // Не открылся - play_next позже покажет ошибку при обычном переключении
+        if let Ok(opened) = audio_engine::open_track(&track.path, track.span, self.options) {
+            sink.append(opened.source);
+            self.queued = Some(QueuedTrack {
+                origin,
+                track,
+                handle: opened.handle,
+                info: opened.info,
+                stream: opened.stream,
+            });
+        }
+    }
+
+    // Очередь Sink перешла к заранее открытому треку - переключаем состояние
+    fn promote_queued_track(&mut self) {
+        let Some(queued) = self.queued.take() else {
+            return;
+        };
+        if queued.origin == Origin::UpNext {
+            self.take_up_next();
+        }
+        self.start_track(
+            queued.origin,
+            queued.track,
+            queued.handle,
+            queued.info,
+            queued.stream,
+        );
+        self.preload_next();
+    }
+
+    // Новая очередь от UI: находим в ней текущий трек по пути и части файла,
+    // ближайшее совпадение к старой позиции (в плейлисте бывают дубли)
+    fn load_queue(&mut self, queue: Vec<Track>) {
+        let old_queue = std::mem::replace(&mut self.queue, queue);
+        let len = self.queue.len();
+        let mapping: Vec<Option<usize>> = old_queue
+            .iter()
+            .enumerate()
+            .map(|(i, track)| locate(&self.queue, track, i))
+            .collect();
+        self.order.remap(&mapping, len);
+
+        // Первый уцелевший трек начиная со старой позиции
+        let survivor = |from: usize| {
+            mapping
+                .get(from..)
+                .and_then(|rest| rest.iter().find_map(|i| *i))
+                .unwrap_or(len)
+        };
+        if let Some(current) = &mut self.current {
+            match current.index {
+                Some(old_index) => {
+                    current.index = mapping[old_index];
+                    self.next_index = match current.index {
+                        Some(i) => self.order.next(Some(i), i + 1, len, false),
+                        // Текущий трек удалили - дальше играет первый уцелевший после него
+                        None => self.order.next(None, survivor(old_index + 1), len, false),
+                    };
+                }
+                // Играет трек из списка "дальше" - сдвигаем место, где продолжится очередь
+                None => {
+                    if let Some(next) = self.next_index {
+                        self.next_index = self.order.next(None, survivor(next), len, false);
+                    }
+                }
+            }
+        }
+        self.refresh_next_queued();
+    }
+
+    // Режим сменился - пересчитываем следующий трек
+    fn refresh_next(&mut self) {
+        if let Some(i) = self.current.as_ref().and_then(|c| c.index) {
+            self.next_index = self.order.next(Some(i), i + 1, self.queue.len(), false);
+        }
+        self.refresh_next_queued();
+    }
+
+    fn refresh_next_queued(&mut self) {
+        // Заранее открытый трек больше не следующий - убираем его из Sink
+        if let Some(queued) = self.queued.take() {
+            match self.upcoming() {
+                Some((origin, track)) if track == queued.track => {
+                    self.queued = Some(QueuedTrack { origin, ..queued });
+                }
+                _ => queued.handle.cancel(),
+            }
+        }
+        self.preload_attempted = false;
+        self.preload_next();
+    }
+
+    fn tick(&mut self) {
+        let Some(sink) = &self.sink else {
+            return;
+        };
+
+        if self.queued.is_some() && sink.len() < 2 {
+            // Текущий трек доигран, Sink без паузы перешел к следующему
+            if self.finish_track() {
+                self.promote_queued_track();
+            }
+        } else if sink.empty() && !sink.is_paused() {
+            if self.finish_track() {
+                self.advance();
+            }
+            self.emit(Event::StateChanged(self.state()));
+        } else {
+            // В очередь могли добавить треки после текущего
+            self.preload_next();
+        }
+
+        if let Some(current) = &self.current {
+            // Позиция по кадрам, которые источник реально отдал в Sink
+            let position = current.handle.position();
+            if self.last_position != Some(position) {
+                self.last_position = Some(position);
+                self.emit(Event::Position(position));
+            }
+
+            let skipped = current.handle.skipped_packets();
+            if skipped != self.last_skipped {
+                self.last_skipped = skipped;
+                self.emit(Event::PacketsSkipped(skipped));
+            }
+        }
+    }
+
+    // Текущий трек закончился. false - он оборвался с ошибкой в строгом режиме,
+    // и воспроизведение остановлено
+    fn finish_track(&mut self) -> bool {
+        let Some(current) = &self.current else {
+            return true;
+        };
+        self.emit(Event::TrackEnded(current.track.clone()));
+
+        let Some(failure) = current.handle.failure() else {
+            return true;
+        };
+        let path = &current.track.path;
+        let name = path.file_name().map_or_else(
+            || path.display().to_string(),
+            |n| n.to_string_lossy().into_owned(),
+        );
+        self.emit(Event::Error(format!("{}: {}", name, failure)));
+
+        if self.options.strict {
+            self.stop();
+            return false;
+        }
+        true
+    }
+}
+
+// Открывает устройство по умолчанию в его родном формате - так же, как rodio,
+// но формат остается известен, а буфер задан настройками.
+// Размер прижимается к диапазону устройства; не принят - буфер драйвера
+fn open_default_output(buffer_size: u32, events: &Sender<Event>) -> Option<(Output, OutputFormat)> {
+    let device = cpal::default_host().default_output_device()?;
+    let supported = device.default_output_config().ok()?;
+    let fixed = match *supported.buffer_size() {
+        SupportedBufferSize::Range { min, max } => buffer_size.clamp(min, max.max(min)),
+        SupportedBufferSize::Unknown => buffer_size,
+    };
+    let mut config = supported.config();
+    let mut format = OutputFormat {
+        sample_rate: config.sample_rate.0,
+        channels: config.channels,
+        sample_format: format!("{:?}", supported.sample_format()),
+        buffer_size: None,
+    };
+    for size in [BufferSize::Fixed(fixed), BufferSize::Default] {
+        config.buffer_size = size;
+        let (mixer, source) = dynamic_mixer::mixer(config.channels, config.sample_rate.0);
+        let stream = match supported.sample_format() {
+            cpal::SampleFormat::I8 => build_output::<i8>(&device, &config, source, events),
+            cpal::SampleFormat::I16 => build_output::<i16>(&device, &config, source, events),
+            cpal::SampleFormat::I32 => build_output::<i32>(&device, &config, source, events),
+            cpal::SampleFormat::I64 => build_output::<i64>(&device, &config, source, events),
+            cpal::SampleFormat::U8 => build_output::<u8>(&device, &config, source, events),
+            cpal::SampleFormat::U16 => build_output::<u16>(&device, &config, source, events),
+            cpal::SampleFormat::U32 => build_output::<u32>(&device, &config, source, events),
+            cpal::SampleFormat::U64 => build_output::<u64>(&device, &config, source, events),
+            cpal::SampleFormat::F32 => build_output::<f32>(&device, &config, source, events),
+            cpal::SampleFormat::F64 => build_output::<f64>(&device, &config, source, events),
+            _ => return None,
+        };
+        if let Some(stream) = stream.filter(|stream| stream.play().is_ok()) {
+            if let BufferSize::Fixed(frames) = size {
+                format.buffer_size = Some(frames);
+            }
+            let output = Output::Mixer {
+                mixer,
+                _stream: stream,
+            };
+            return Some((output, format));
+        }
+    }
+    None
+}
+
+// Поток вывода, который забирает сэмплы из микшера в формате устройства
+fn build_output<T: SizedSample + FromSample<f32>>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    mut source: DynamicMixer<f32>,
+    events: &Sender<Event>,
+) -> Option<cpal::Stream> {
+    let events = events.clone();
+    device
+        .build_output_stream(
+            config,
+            move |data: &mut [T], _| {
+                for sample in data.iter_mut() {
+                    *sample = source.next().map_or(T::EQUILIBRIUM, T::from_sample);
+                }
+            },
+            move |e| {
+                let _ = events.send(Event::Error(format!("Устройство вывода: {}", e)));
+            },
+            None,
+        )
+        .ok()
+}
+
+// Индекс трека в очереди, ближайший к прежней позиции
+fn locate(queue: &[Track], track: &Track, near: usize) -> Option<usize> {
+    queue
+        .iter()
+        .enumerate()
+        .filter(|(_, t)| *t == track)
+        .min_by_key(|(i, _)| i.abs_diff(near))
+        .map(|(i, _)| i)
+}
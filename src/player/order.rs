@@ -0,0 +1,296 @@
+// Порядок воспроизведения: по очереди, по кругу, повтор трека, перемешивание.
+// Все индексы - позиции в очереди движка.
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Сколько сыгранных треков помнить для F5 в режиме перемешивания
+const HISTORY_LIMIT: usize = 1000;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PlaybackMode {
+    #[default]
+    Sequential,
+    RepeatAll,
+    RepeatOne,
+    Shuffle,
+}
+
+impl PlaybackMode {
+    /// Следующий режим по клавише
+    pub fn cycle(self) -> Self {
+        match self {
+            PlaybackMode::Sequential => PlaybackMode::RepeatAll,
+            PlaybackMode::RepeatAll => PlaybackMode::RepeatOne,
+            PlaybackMode::RepeatOne => PlaybackMode::Shuffle,
+            PlaybackMode::Shuffle => PlaybackMode::Sequential,
+        }
+    }
+
+    /// Короткая подпись для строки состояния
+    pub fn label(self) -> &'static str {
+        match self {
+            PlaybackMode::Sequential => "→ Seq",
+            PlaybackMode::RepeatAll => "↻ All",
+            PlaybackMode::RepeatOne => "↻ One",
+            PlaybackMode::Shuffle => "⇄ Shuffle",
+        }
+    }
+}
+
+impl fmt::Display for PlaybackMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            PlaybackMode::Sequential => "sequential",
+            PlaybackMode::RepeatAll => "repeat-all",
+            PlaybackMode::RepeatOne => "repeat-one",
+            PlaybackMode::Shuffle => "shuffle",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for PlaybackMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "sequential" | "seq" => Ok(PlaybackMode::Sequential),
+            "repeat-all" | "all" => Ok(PlaybackMode::RepeatAll),
+            "repeat-one" | "one" => Ok(PlaybackMode::RepeatOne),
+            "shuffle" => Ok(PlaybackMode::Shuffle),
+            other => Err(format!(
+                "неизвестный режим: {} (sequential, repeat-all, repeat-one, shuffle)",
+                other
+            )),
+        }
+    }
+}
+
+pub struct PlayOrder {
+    mode: PlaybackMode,
+    // Перемешивание: еще не сыгранные в этом круге, следующий - последний
+    bag: Vec<usize>,
+    // Фактический порядок воспроизведения, текущий - последний
+    history: Vec<usize>,
+    // Куда вернуться вперед после F5
+    forward: Vec<usize>,
+    rng: Rng,
+}
+
+impl PlayOrder {
+    pub fn new(mode: PlaybackMode) -> Self {
+        PlayOrder {
+            mode,
+            bag: Vec::new(),
+            history: Vec::new(),
+            forward: Vec::new(),
+            rng: Rng::new(),
+        }
+    }
+
+    /// Смена режима; перемешивание начинает новый круг с текущего трека
+    pub fn set_mode(&mut self, mode: PlaybackMode, current: Option<usize>, len: usize) {
+        if mode == PlaybackMode::Shuffle && self.mode != PlaybackMode::Shuffle {
+            self.history = current.into_iter().collect();
+            self.forward.clear();
+            self.refill(current, len);
+            self.bag.retain(|&i| Some(i) != current);
+        }
+        self.mode = mode;
+    }
+
+    /// Трек очереди начал играть. back - пришли сюда по F5, история уже на месте
+    pub fn started(&mut self, index: usize, back: bool) {
+        if back {
+            return;
+        }
+        if self.forward.last() == Some(&index) {
+            self.forward.pop();
+        } else {
+            // Явный выбор трека - старая ветка "вперед" больше не нужна
+            self.forward.clear();
+        }
+        self.bag.retain(|&i| i != index);
+        self.history.push(index);
+        if self.history.len() > HISTORY_LIMIT {
+            self.history.remove(0);
+        }
+    }
+
+    /// Что играть после current. sequential - следующая позиция по порядку очереди
+    /// (может равняться len); manual - F6, а не конец трека
+    pub fn next(
+        &mut self,
+        current: Option<usize>,
+        sequential: usize,
+        len: usize,
+        manual: bool,
+    ) -> Option<usize> {
+        if len == 0 {
+            return None;
+        }
+        match self.mode {
+            PlaybackMode::Sequential => (sequential < len).then_some(sequential),
+            PlaybackMode::RepeatOne if !manual && current.is_some() => current,
+            PlaybackMode::RepeatAll | PlaybackMode::RepeatOne => Some(sequential % len),
+            PlaybackMode::Shuffle => {
+                if let Some(&index) = self.forward.last() {
+                    return Some(index);
+                }
+                if self.bag.is_empty() {
+                    self.refill(current, len);
+                }
+                self.bag.last().copied()
+            }
+        }
+    }
+
+    /// F5: в перемешивании - назад по истории, иначе - предыдущий в очереди
+    pub fn previous(&mut self, current: usize, len: usize) -> Option<usize> {
+        match self.mode {
+            PlaybackMode::Shuffle => {
+                if self.history.len() < 2 {
+                    return None;
+                }
+                let left = self.history.pop()?;
+                self.forward.push(left);
+                self.history.last().copied()
+            }
+            PlaybackMode::RepeatAll if current == 0 => len.checked_sub(1),
+            _ => current.checked_sub(1),
+        }
+    }
+
+    /// Очередь изменилась: mapping[старый индекс] - новый индекс или None (удален).
+    /// Новые треки попадают в текущий круг перемешивания
+    pub fn remap(&mut self, mapping: &[Option<usize>], len: usize) {
+        let map = |list: &mut Vec<usize>| {
+            *list = list
+                .iter()
+                .filter_map(|&i| mapping.get(i).copied().flatten())
+                .collect();
+        };
+        map(&mut self.bag);
+        map(&mut self.history);
+        map(&mut self.forward);
+
+        let mut known = vec![false; len];
+        for &i in mapping.iter().flatten() {
+            known[i] = true;
+        }
+        for (index, _) in known.iter().enumerate().filter(|(_, known)| !**known) {
+            let at = self.rng.below(self.bag.len() + 1);
+            self.bag.insert(at, index);
+        }
+    }
+
+    // Новый круг: все треки в случайном порядке, но не начинаем с только что сыгранного
+    fn refill(&mut self, last: Option<usize>, len: usize) {
+        self.bag = (0..len).collect();
+        for i in (1..self.bag.len()).rev() {
+            let j = self.rng.below(i + 1);
+            self.bag.swap(i, j);
+        }
+        if len > 1 && self.bag.last().copied() == last {
+            self.bag.swap(0, len - 1);
+        }
+    }
+}
+
+// xorshift64*: для перемешивания плейлиста криптостойкость не нужна
+struct Rng(u64);
+
+impl Rng {
+    fn new() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0x2545_f491_4f6c_dd1d);
+        Rng(seed | 1)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545_f491_4f6c_dd1d) % n as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shuffled(seed: u64) -> PlayOrder {
+        let mut order = PlayOrder::new(PlaybackMode::Shuffle);
+        order.rng = Rng(seed | 1);
+        order
+    }
+
+    // Как плеер: после каждого трека спрашивает следующий и запускает его
+    fn play(
+        order: &mut PlayOrder,
+        current: &mut Option<usize>,
+        len: usize,
+        count: usize,
+    ) -> Vec<usize> {
+        (0..count)
+            .map(|_| {
+                let sequential = current.map_or(0, |i| i + 1);
+                let index = order.next(*current, sequential, len, false).unwrap();
+                order.started(index, false);
+                *current = Some(index);
+                index
+            })
+            .collect()
+    }
+
+    #[test]
+    fn shuffle_pass_plays_every_track_once() {
+        for seed in 0..100 {
+            let mut order = shuffled(seed);
+            let mut current = None;
+            for _ in 0..3 {
+                let mut pass = play(&mut order, &mut current, 10, 10);
+                pass.sort_unstable();
+                assert_eq!(pass, (0..10).collect::<Vec<_>>(), "seed {}", seed);
+            }
+        }
+    }
+
+    #[test]
+    fn reshuffle_does_not_repeat_the_last_track() {
+        for len in [2, 3, 10] {
+            for seed in 0..100 {
+                let mut order = shuffled(seed);
+                let mut current = None;
+                for _ in 0..5 {
+                    let last = *play(&mut order, &mut current, len, len).last().unwrap();
+                    let first = order.next(current, last + 1, len, false);
+                    assert_ne!(first, Some(last), "len {} seed {}", len, seed);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn previous_walks_back_through_history() {
+        let mut order = shuffled(7);
+        let mut current = None;
+        // Два круга, чтобы история пересекла перемешивание
+        let played = play(&mut order, &mut current, 5, 8);
+
+        let mut index = played[7];
+        for expected in played[..7].iter().rev() {
+            index = order.previous(index, 5).unwrap();
+            assert_eq!(index, *expected);
+            order.started(index, true);
+        }
+        assert_eq!(order.previous(index, 5), None);
+
+        // После F5 вперед идем по той же истории, а не по новому кругу
+        let mut current = Some(index);
+        assert_eq!(play(&mut order, &mut current, 5, 7), played[1..]);
+    }
+}
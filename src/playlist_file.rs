@@ -0,0 +1,654 @@
+// Файлы плейлистов: M3U/M3U8, PLS и XSPF - чтение и запись.
+// CUE тоже считается плейлистом, но читается модулем cue: его треки - части файлов.
+// Формат определяется по расширению. Пишем всегда UTF-8; при чтении текст,
+// который не является UTF-8, считаем CP1251 - так сохраняют старые программы под Windows
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fmt::Write as _;
+use std::fs;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    M3u,
+    M3u8,
+    Pls,
+    Xspf,
+    Cue,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        match ext.as_str() {
+            "m3u" => Some(Format::M3u),
+            "m3u8" => Some(Format::M3u8),
+            "pls" => Some(Format::Pls),
+            "xspf" => Some(Format::Xspf),
+            "cue" => Some(Format::Cue),
+            _ => None,
+        }
+    }
+
+    pub fn can_write(self) -> bool {
+        self != Format::Cue
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Format::M3u => "M3U",
+            Format::M3u8 => "M3U8",
+            Format::Pls => "PLS",
+            Format::Xspf => "XSPF",
+            Format::Cue => "CUE",
+        }
+    }
+}
+
+/// Как записывать пути в сохраняемый плейлист
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PathStyle {
+    #[default]
+    Absolute,
+    /// От папки плейлиста - плейлист переносится вместе с музыкой
+    Relative,
+    /// От корневой папки библиотеки - музыку можно подключить в другое место
+    Library,
+}
+
+impl PathStyle {
+    pub fn cycle(self) -> Self {
+        match self {
+            PathStyle::Absolute => PathStyle::Relative,
+            PathStyle::Relative => PathStyle::Library,
+            PathStyle::Library => PathStyle::Absolute,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PathStyle::Absolute => "абсолютные",
+            PathStyle::Relative => "от плейлиста",
+            PathStyle::Library => "от библиотеки",
+        }
+    }
+}
+
+impl FromStr for PathStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "absolute" => Ok(PathStyle::Absolute),
+            "relative" => Ok(PathStyle::Relative),
+            "library" => Ok(PathStyle::Library),
+            other => Err(format!(
+                "неизвестный вид путей: {} (absolute, relative, library)",
+                other
+            )),
+        }
+    }
+}
+
+/// Запись плейлиста: путь и то, что о треке записано в самом плейлисте
+#[derive(Clone)]
+pub struct Item {
+    pub path: PathBuf,
+    pub title: Option<String>,
+    pub duration: Option<Duration>,
+    /// Размер файла (#EXTBYT в M3U) - по нему ищется переехавший файл
+    pub size: Option<u64>,
+}
+
+pub fn is_playlist(path: &Path) -> bool {
+    Format::from_path(path).is_some()
+}
+
+/// Относительные пути считаются от папки плейлиста, а если там файла нет -
+/// от корневых папок roots. Записи без файла тоже возвращаются
+pub fn read(path: &Path, roots: &[PathBuf]) -> Result<Vec<Item>, Box<dyn std::error::Error>> {
+    let format = Format::from_path(path)
+        .ok_or_else(|| format!("{}: неизвестный формат плейлиста", path.display()))?;
+    let text = decode(&fs::read(path)?);
+    let mut items = match format {
+        Format::M3u | Format::M3u8 => parse_m3u(&text),
+        Format::Pls => parse_pls(&text),
+        Format::Xspf => parse_xspf(&text),
+        Format::Cue => {
+            return Err(format!("{}: CUE читается через cue::load", path.display()).into())
+        }
+    };
+    let base = path.parent().unwrap_or(Path::new("."));
+    for item in &mut items {
+        if item.path.is_relative() {
+            item.path = std::iter::once(base)
+                .chain(roots.iter().map(PathBuf::as_path))
+                .map(|dir| dir.join(&item.path))
+                .find(|candidate| candidate.exists())
+                .unwrap_or_else(|| base.join(&item.path));
+        }
+    }
+    Ok(items)
+}
+
+/// Пути пишутся в виде style; для PathStyle::Library файлы вне roots остаются абсолютными
+pub fn write(
+    path: &Path,
+    items: &[Item],
+    style: PathStyle,
+    roots: &[PathBuf],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let format = Format::from_path(path)
+        .filter(|format| format.can_write())
+        .ok_or_else(|| {
+            format!(
+                "{}: сохранить можно в .m3u, .m3u8, .pls или .xspf",
+                path.display()
+            )
+        })?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let items = items
+        .iter()
+        .map(|item| {
+            let mut path = location(&item.path, dir, style, roots);
+            // M3U и PLS пишут путь не в UTF-8 как file:// URI, а он бывает только абсолютным
+            if format != Format::Xspf && path.to_str().is_none() {
+                path = item.path.clone();
+                if !path.is_absolute() {
+                    return Err(format!(
+                        "{}: путь не в UTF-8 можно сохранить в {} только абсолютным",
+                        path.display(),
+                        format.label()
+                    ));
+                }
+            }
+            Ok(Item {
+                path,
+                ..item.clone()
+            })
+        })
+        .collect::<Result<Vec<Item>, _>>()?;
+    let content = match format {
+        Format::M3u | Format::M3u8 => write_m3u(&items),
+        Format::Pls => write_pls(&items),
+        Format::Xspf => write_xspf(&items),
+        Format::Cue => unreachable!(),
+    };
+    fs::write(path, content)?;
+    Ok(())
+}
+
+/// UTF-8 (BOM отбрасывается), иначе CP1251
+pub fn decode(bytes: &[u8]) -> String {
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => bytes.iter().map(|&b| cp1251(b)).collect(),
+    }
+}
+
+fn cp1251(byte: u8) -> char {
+    const HIGH: &str =
+        "ЂЃ‚ѓ„…†‡€‰Љ‹ЊЌЋЏђ‘’“”•–—\u{FFFD}™љ›њќћџ\u{A0}ЎўЈ¤Ґ¦§Ё©Є«¬\u{AD}®Ї°±Ііґµ¶·ё№є»јЅѕї";
+    match byte {
+        0x00..=0x7F => byte as char,
+        0x80..=0xBF => HIGH.chars().nth(byte as usize - 0x80).unwrap_or('\u{FFFD}'),
+        // А..я идут подряд
+        _ => char::from_u32(0x410 + (byte as u32 - 0xC0)).unwrap_or('\u{FFFD}'),
+    }
+}
+
+/// Путь из плейлиста: абсолютный или относительно папки плейлиста
+pub fn resolve(base: &Path, location: &str) -> PathBuf {
+    let path = Path::new(location);
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        base.join(path)
+    }
+}
+
+// Путь в том виде, в каком он попадет в плейлист
+fn location(path: &Path, dir: &Path, style: PathStyle, roots: &[PathBuf]) -> PathBuf {
+    let relative = match style {
+        PathStyle::Absolute => None,
+        PathStyle::Relative => relative_path(path, dir),
+        PathStyle::Library => roots
+            .iter()
+            .find_map(|root| path.strip_prefix(root).ok())
+            .map(Path::to_path_buf),
+    };
+    relative.unwrap_or_else(|| path.to_path_buf())
+}
+
+// Путь от dir до path, при необходимости через ".."
+fn relative_path(path: &Path, dir: &Path) -> Option<PathBuf> {
+    if !path.is_absolute() || !dir.is_absolute() {
+        return None;
+    }
+    let mut path_parts = path.components().peekable();
+    let mut dir_parts = dir.components().peekable();
+    while let (Some(a), Some(b)) = (path_parts.peek(), dir_parts.peek()) {
+        if a != b {
+            break;
+        }
+        path_parts.next();
+        dir_parts.next();
+    }
+    let mut relative = PathBuf::new();
+    for part in dir_parts {
+        if part != Component::CurDir {
+            relative.push("..");
+        }
+    }
+    relative.extend(path_parts);
+    Some(relative)
+}
+
+fn single_line(text: &str) -> String {
+    text.replace(['\r', '\n'], " ")
+}
+
+// ------------ M3U ---------------------------
+
+fn parse_m3u(text: &str) -> Vec<Item> {
+    let mut items = Vec::new();
+    let mut extinf: Option<(Option<Duration>, Option<String>)> = None;
+    let mut size = None;
+    for line in text.lines().map(str::trim) {
+        if let Some(info) = line.strip_prefix("#EXTINF:") {
+            // #EXTINF:секунды [атрибуты],Название - название может содержать запятые
+            let (head, title) = info.split_once(',').unwrap_or((info, ""));
+            let seconds = head
+                .split_whitespace()
+                .next()
+                .and_then(|s| s.parse::<f64>().ok());
+            let duration = seconds.filter(|&s| s >= 0.0).map(Duration::from_secs_f64);
+            let title = Some(title.trim().to_string()).filter(|t| !t.is_empty());
+            extinf = Some((duration, title));
+        } else if let Some(bytes) = line.strip_prefix("#EXTBYT:") {
+            size = bytes.trim().parse::<u64>().ok();
+        } else if !line.is_empty() && !line.starts_with('#') {
+            let (duration, title) = extinf.take().unwrap_or_default();
+            let size = size.take();
+            let Some(path) = location_path(line) else {
+                continue;
+            };
+            items.push(Item {
+                path,
+                title,
+                duration,
+                size,
+            });
+        }
+    }
+    items
+}
+
+fn write_m3u(items: &[Item]) -> String {
+    let mut content = String::from("#EXTM3U\n");
+    for item in items {
+        let seconds = item.duration.map_or(-1, |d| d.as_secs() as i64);
+        let title = item.title.as_deref().map(single_line).unwrap_or_default();
+        let _ = writeln!(content, "#EXTINF:{},{}", seconds, title);
+        if let Some(size) = item.size {
+            let _ = writeln!(content, "#EXTBYT:{}", size);
+        }
+        let _ = writeln!(content, "{}", m3u_location(&item.path));
+    }
+    content
+}
+
+// Строка с '#' в начале - комментарий, поэтому относительный путь "#01 Intro.flac"
+// пишем как "./#01 Intro.flac"
+fn m3u_location(path: &Path) -> String {
+    let location = text_location(path);
+    if location.starts_with('#') {
+        format!("./{}", location)
+    } else {
+        location
+    }
+}
+
+// ------------ PLS ---------------------------
+
+// Ключи FileN/TitleN/LengthN могут идти в любом порядке - собираем по номеру
+#[derive(Default)]
+struct PlsEntry {
+    path: Option<PathBuf>,
+    title: Option<String>,
+    duration: Option<Duration>,
+}
+
+fn parse_pls(text: &str) -> Vec<Item> {
+    let mut entries: BTreeMap<u32, PlsEntry> = BTreeMap::new();
+    for line in text.lines().map(str::trim) {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_lowercase();
+        let value = value.trim();
+        let field = ["file", "title", "length"]
+            .into_iter()
+            .find_map(|name| Some((name, key.strip_prefix(name)?.parse::<u32>().ok()?)));
+        let Some((name, number)) = field else {
+            continue;
+        };
+        let entry = entries.entry(number).or_default();
+        match name {
+            "file" => entry.path = location_path(value),
+            "title" => entry.title = Some(value.to_string()).filter(|t| !t.is_empty()),
+            _ => {
+                entry.duration = value
+                    .parse::<i64>()
+                    .ok()
+                    .filter(|&s| s >= 0)
+                    .map(|s| Duration::from_secs(s as u64))
+            }
+        }
+    }
+    entries
+        .into_values()
+        .filter_map(|entry| {
+            Some(Item {
+                path: entry.path?,
+                title: entry.title,
+                duration: entry.duration,
+                size: None,
+            })
+        })
+        .collect()
+}
+
+fn write_pls(items: &[Item]) -> String {
+    let mut content = String::from("[playlist]\n");
+    for (i, item) in items.iter().enumerate() {
+        let n = i + 1;
+        let _ = writeln!(content, "File{}={}", n, text_location(&item.path));
+        if let Some(title) = &item.title {
+            let _ = writeln!(content, "Title{}={}", n, single_line(title));
+        }
+        let seconds = item.duration.map_or(-1, |d| d.as_secs() as i64);
+        let _ = writeln!(content, "Length{}={}", n, seconds);
+    }
+    let _ = writeln!(content, "NumberOfEntries={}", items.len());
+    content.push_str("Version=2\n");
+    content
+}
+
+// ------------ XSPF ---------------------------
+
+fn parse_xspf(text: &str) -> Vec<Item> {
+    let mut items = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("<track>").or_else(|| rest.find("<track ")) {
+        let body = &rest[start..];
+        let end = body.find("</track>").unwrap_or(body.len());
+        let track = &body[..end];
+        rest = &body[end..];
+
+        let Some(path) = element(track, "location")
+            .map(|location| xml_unescape(location.trim()))
+            .and_then(|location| uri_path(&location))
+        else {
+            continue;
+        };
+        items.push(Item {
+            path,
+            title: element(track, "title").map(xml_unescape),
+            duration: element(track, "duration")
+                .and_then(|ms| ms.trim().parse().ok())
+                .map(Duration::from_millis),
+            size: None,
+        });
+    }
+    items
+}
+
+fn write_xspf(items: &[Item]) -> String {
+    let mut content = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n  <trackList>\n",
+    );
+    for item in items {
+        content.push_str("    <track>\n");
+        let _ = writeln!(
+            content,
+            "      <location>{}</location>",
+            xml_escape(&uri(&item.path))
+        );
+        if let Some(title) = &item.title {
+            let _ = writeln!(content, "      <title>{}</title>", xml_escape(title));
+        }
+        if let Some(duration) = item.duration {
+            let _ = writeln!(
+                content,
+                "      <duration>{}</duration>",
+                duration.as_millis()
+            );
+        }
+        content.push_str("    </track>\n");
+    }
+    content.push_str("  </trackList>\n</playlist>\n");
+    content
+}
+
+// Текст первого <name>...</name>; вложенных одноименных тегов в XSPF нет
+fn element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let open = format!("<{}>", name);
+    let close = format!("</{}>", name);
+    let start = xml.find(&open)? + open.len();
+    let end = xml[start..].find(&close)? + start;
+    let text = &xml[start..end];
+    Some(
+        text.strip_prefix("<![CDATA[")
+            .and_then(|t| t.strip_suffix("]]>"))
+            .unwrap_or(text),
+    )
+}
+
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn xml_unescape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        result.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let entity = rest.find(';').map(|end| (&rest[1..end], end));
+        let decoded = entity.and_then(|(name, _)| match name {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => {
+                let code = match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => name.strip_prefix('#')?.parse().ok(),
+                };
+                char::from_u32(code?)
+            }
+        });
+        match (decoded, entity) {
+            (Some(c), Some((_, end))) => {
+                result.push(c);
+                rest = &rest[end + 1..];
+            }
+            _ => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+// ------------ URI ---------------------------
+
+// file:// с процентным кодированием байтов имени - так путь переживает любые символы.
+// Относительный путь - относительная ссылка без схемы
+fn uri(path: &Path) -> String {
+    let mut uri = String::from(if path.is_absolute() { "file://" } else { "" });
+    for &byte in path.as_os_str().as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            let _ = write!(uri, "%{:02X}", byte);
+        }
+    }
+    uri
+}
+
+// M3U/PLS: путь как есть, а если он не в UTF-8 - file:// URI, иначе байты имени потеряются
+fn text_location(path: &Path) -> String {
+    match path.to_str() {
+        Some(text) => text.to_string(),
+        None => uri(path),
+    }
+}
+
+// M3U/PLS: обычный путь или file://. Потоки (http:// и т.п.) не поддерживаем - None.
+// Относительный путь read разрешает сам
+fn location_path(location: &str) -> Option<PathBuf> {
+    if location.starts_with("file://") {
+        uri_path(location)
+    } else if location.contains("://") {
+        None
+    } else {
+        Some(PathBuf::from(location))
+    }
+}
+
+// XSPF: file:// или относительная ссылка, обе в процентном кодировании
+fn uri_path(uri: &str) -> Option<PathBuf> {
+    if let Some(rest) = uri.strip_prefix("file://") {
+        // file:///путь или file://localhost/путь
+        let path = rest.strip_prefix("localhost").unwrap_or(rest);
+        return Some(PathBuf::from(percent_decode(path)));
+    }
+    if uri.contains("://") {
+        return None;
+    }
+    Some(PathBuf::from(percent_decode(uri)))
+}
+
+fn percent_decode(text: &str) -> OsString {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match hex {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    OsString::from_vec(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("hi-res-player-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn item(path: PathBuf, title: &str, seconds: u64) -> Item {
+        Item {
+            path,
+            title: Some(title.to_string()),
+            duration: Some(Duration::from_secs(seconds)),
+            size: None,
+        }
+    }
+
+    fn round_trip(file_name: &str) {
+        let dir = temp_dir(file_name);
+        let music = dir.join("Музыка");
+        let items = vec![
+            item(
+                music.join("Кино - Группа крови.flac"),
+                "Кино, Группа крови",
+                286,
+            ),
+            item(dir.join("#01 Intro.flac"), "Вступление = 1 & 2", 61),
+            item(music.join("<Тишина>.wav"), "<Тишина> & \"эхо\"", 3),
+            // Имя не в UTF-8 (CP1251) - должно пережить сохранение байт в байт
+            item(
+                music.join(OsString::from_vec(b"\xCF\xE5\xF1\xED\xFF.flac".to_vec())),
+                "Песня",
+                200,
+            ),
+        ];
+
+        for style in [PathStyle::Absolute, PathStyle::Relative] {
+            let playlist = dir.join(file_name);
+            write(&playlist, &items, style, &[]).unwrap();
+            let read_back = read(&playlist, &[]).unwrap();
+
+            assert_eq!(read_back.len(), items.len(), "{} {:?}", file_name, style);
+            for (written, read) in items.iter().zip(&read_back) {
+                assert_eq!(read.path, written.path, "{} {:?}", file_name, style);
+                assert_eq!(read.title, written.title, "{} {:?}", file_name, style);
+                assert_eq!(read.duration, written.duration, "{} {:?}", file_name, style);
+            }
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn m3u8_round_trip() {
+        round_trip("список.m3u8");
+    }
+
+    #[test]
+    fn pls_round_trip() {
+        round_trip("список.pls");
+    }
+
+    #[test]
+    fn xspf_round_trip() {
+        round_trip("список.xspf");
+    }
+
+    #[test]
+    fn decode_falls_back_to_cp1251() {
+        // "Привет Ёж №1" в CP1251
+        let bytes = b"\xCF\xF0\xE8\xE2\xE5\xF2 \xA8\xE6 \xB91";
+        assert_eq!(decode(bytes), "Привет Ёж №1");
+        assert_eq!(decode("\u{FEFF}Привет".as_bytes()), "Привет");
+    }
+}
@@ -0,0 +1,152 @@
+// Фоновое чтение тегов и длительности.
+// Вход в папку с сотнями hi-res файлов больше не замораживает UI:
+// ячейки заполняются по мере готовности, а результаты живут в кэше между запусками.
+mod cache;
+
+use crate::audio_engine::{self, EmbeddedCue, TrackInfo};
+use crate::paths;
+use cache::ProbeCache;
+pub use cache::{FileStamp, Probed};
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+const CACHE_FILE: &str = "probe.tsv";
+// Проба упирается в диск, а не в процессор - много потоков не нужно
+const MAX_WORKERS: usize = 4;
+
+struct ProbeResult {
+    path: PathBuf,
+    stamp: Option<FileStamp>,
+    probed: Probed,
+}
+
+/// Пул потоков пробы; потоки завершаются вместе с Prober
+pub struct Prober {
+    jobs: Sender<PathBuf>,
+    results: Receiver<ProbeResult>,
+    cache: ProbeCache,
+    cache_path: Option<PathBuf>,
+    pending: HashSet<PathBuf>,
+}
+
+impl Prober {
+    pub fn spawn() -> Self {
+        let (job_tx, job_rx) = mpsc::channel::<PathBuf>();
+        let (result_tx, result_rx) = mpsc::channel();
+        let job_rx = Arc::new(Mutex::new(job_rx));
+
+        let workers = thread::available_parallelism()
+            .map(|n| n.get().min(MAX_WORKERS))
+            .unwrap_or(2);
+        for i in 0..workers {
+            let jobs = Arc::clone(&job_rx);
+            let results = result_tx.clone();
+            thread::Builder::new()
+                .name(format!("probe-{}", i))
+                .spawn(move || probe_worker(jobs, results))
+                .expect("не удалось запустить поток пробы");
+        }
+
+        let cache_path = paths::cache_dir().map(|dir| dir.join(CACHE_FILE));
+        let cache = cache_path
+            .as_deref()
+            .map(ProbeCache::load)
+            .unwrap_or_default();
+
+        Prober {
+            jobs: job_tx,
+            results: result_rx,
+            cache,
+            cache_path,
+            pending: HashSet::new(),
+        }
+    }
+
+    /// Теги из кэша, если файл не менялся; иначе ставит пробу в очередь и возвращает None.
+    /// У неразобравшегося файла теги пустые
+    pub fn lookup(&mut self, path: &Path) -> Option<TrackInfo> {
+        if let Some(probed) = FileStamp::of(path).and_then(|stamp| self.cache.get(path, stamp)) {
+            return Some(probed.info.clone().unwrap_or_default());
+        }
+        if self.pending.insert(path.to_path_buf()) {
+            // Потоки живут, пока жив Prober - ошибка отправки невозможна
+            let _ = self.jobs.send(path.to_path_buf());
+        }
+        None
+    }
+
+    pub fn is_pending(&self, path: &Path) -> bool {
+        self.pending.contains(path)
+    }
+
+    /// Проба уже показала, что файл не прочитать
+    pub fn is_unreadable(&self, path: &Path) -> bool {
+        let cached = FileStamp::of(path).and_then(|stamp| self.cache.get(path, stamp));
+        cached.is_some_and(|probed| probed.info.is_none())
+    }
+
+    /// Разметка треков внутри файла, как ее нашла проба.
+    /// Пробы еще не было - ставит ее в очередь, разметка придет с результатом
+    pub fn embedded_cue(&mut self, path: &Path) -> Option<EmbeddedCue> {
+        match FileStamp::of(path).and_then(|stamp| self.cache.get(path, stamp)) {
+            Some(probed) => probed.cue.clone(),
+            None => {
+                self.lookup(path);
+                None
+            }
+        }
+    }
+
+    /// Готовый результат пробы; он же сразу попадает в кэш. None вместо тегов - файл не прочитать
+    pub fn try_recv(&mut self) -> Option<(PathBuf, Probed)> {
+        let result = self.results.try_recv().ok()?;
+        self.pending.remove(&result.path);
+        if let Some(stamp) = result.stamp {
+            self.cache
+                .insert(result.path.clone(), stamp, result.probed.clone());
+        }
+        Some((result.path, result.probed))
+    }
+
+    pub fn save_cache(&mut self) -> io::Result<()> {
+        match &self.cache_path {
+            Some(path) => self.cache.save(path),
+            None => Ok(()),
+        }
+    }
+}
+
+fn probe_worker(jobs: Arc<Mutex<Receiver<PathBuf>>>, results: Sender<ProbeResult>) {
+    loop {
+        let job = jobs
+            .lock()
+            .map_err(|_| ())
+            .and_then(|rx| rx.recv().map_err(|_| ()));
+        let Ok(path) = job else {
+            return;
+        };
+
+        // Отпечаток снимаем до чтения: если файл поменяется во время пробы,
+        // запись в кэше просто не совпадет и проба повторится
+        let stamp = FileStamp::of(&path);
+        // Нечитаемый файл тоже кэшируем, чтобы не пробовать его каждый раз
+        let info = audio_engine::probe_track(&path).map(|(info, _)| info);
+        // Встроенный CUE ищем здесь же: ради него заголовки читаются целиком
+        let cue = match info {
+            Some(_) => audio_engine::embedded_cue(&path),
+            None => None,
+        };
+        let result = ProbeResult {
+            path,
+            stamp,
+            probed: Probed { info, cue },
+        };
+        if results.send(result).is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,157 @@
+// Кэш результатов пробы между запусками.
+// Текстовый TSV: одна строка на файл, запись годна, пока совпадают mtime и размер.
+use crate::audio_engine::{EmbeddedCue, TrackInfo};
+use crate::tsv;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+const HEADER: &str = "# hi-res-player probe cache v3";
+
+/// Отпечаток файла: если он изменился, теги нужно читать заново
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+    pub mtime_ns: u64,
+    pub size: u64,
+}
+
+impl FileStamp {
+    pub fn of(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(FileStamp {
+            mtime_ns: mtime.as_nanos() as u64,
+            size: metadata.len(),
+        })
+    }
+}
+
+/// Что проба узнала о файле
+#[derive(Clone, Default)]
+pub struct Probed {
+    /// None - файл не разобрался: битый или кодек не поддерживается
+    pub info: Option<TrackInfo>,
+    /// Разметка треков внутри файла (образ альбома во FLAC)
+    pub cue: Option<EmbeddedCue>,
+}
+
+#[derive(Default)]
+pub struct ProbeCache {
+    entries: HashMap<PathBuf, (FileStamp, Probed)>,
+    dirty: bool,
+}
+
+impl ProbeCache {
+    /// Битые строки и чужие версии формата просто пропускаются
+    pub fn load(path: &Path) -> Self {
+        let mut cache = ProbeCache::default();
+        let Ok(content) = fs::read_to_string(path) else {
+            return cache;
+        };
+        let mut lines = content.lines();
+        if lines.next() != Some(HEADER) {
+            return cache;
+        }
+        for line in lines {
+            if let Some((path, stamp, probed)) = parse_line(line) {
+                cache.entries.insert(path, (stamp, probed));
+            }
+        }
+        cache
+    }
+
+    pub fn get(&self, path: &Path, stamp: FileStamp) -> Option<&Probed> {
+        match self.entries.get(path) {
+            Some((cached, probed)) if *cached == stamp => Some(probed),
+            _ => None,
+        }
+    }
+
+    pub fn insert(&mut self, path: PathBuf, stamp: FileStamp, probed: Probed) {
+        self.entries.insert(path, (stamp, probed));
+        self.dirty = true;
+    }
+
+    /// Записывает кэш, выкидывая записи об удаленных файлах
+    pub fn save(&mut self, path: &Path) -> io::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let lines = self
+            .entries
+            .iter()
+            .filter(|(file, _)| file.exists())
+            .filter_map(|(file, (stamp, probed))| {
+                Some(format_line(file.to_str()?, *stamp, probed))
+            });
+        tsv::write_file(path, HEADER, lines)?;
+
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+// путь, mtime, размер, 1/0 - разобрался ли файл, теги (см. tsv::push_info)
+// и последним - встроенный CUE
+fn format_line(path: &str, stamp: FileStamp, probed: &Probed) -> String {
+    let info = &probed.info;
+    let mut fields = vec![
+        tsv::escape(path),
+        stamp.mtime_ns.to_string(),
+        stamp.size.to_string(),
+        if info.is_some() { "1" } else { "0" }.to_string(),
+    ];
+    tsv::push_info(&mut fields, info.as_ref().unwrap_or(&TrackInfo::default()));
+    fields.push(probed.cue.as_ref().map(format_cue).unwrap_or_default());
+    fields.join("\t")
+}
+
+fn parse_line(line: &str) -> Option<(PathBuf, FileStamp, Probed)> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    if fields.len() != 5 + tsv::INFO_FIELDS {
+        return None;
+    }
+
+    let path = PathBuf::from(tsv::unescape(fields[0]));
+    let stamp = FileStamp {
+        mtime_ns: fields[1].parse().ok()?,
+        size: fields[2].parse().ok()?,
+    };
+    let info = (fields[3] == "1").then(|| tsv::parse_info(&fields[4..4 + tsv::INFO_FIELDS]));
+    let cue = match fields[4 + tsv::INFO_FIELDS] {
+        "" => None,
+        field => Some(parse_cue(field)?),
+    };
+    Some((path, stamp, Probed { info, cue }))
+}
+
+// T и текст CUE или S, начала треков в нс через запятую, ";" и конец диска
+fn format_cue(cue: &EmbeddedCue) -> String {
+    match cue {
+        EmbeddedCue::Text(text) => format!("T{}", tsv::escape(text)),
+        EmbeddedCue::Starts(starts, lead_out) => {
+            let starts: Vec<String> = starts.iter().map(|s| s.as_nanos().to_string()).collect();
+            let lead_out = lead_out.map(|end| end.as_nanos().to_string());
+            format!("S{};{}", starts.join(","), lead_out.unwrap_or_default())
+        }
+    }
+}
+
+fn parse_cue(field: &str) -> Option<EmbeddedCue> {
+    let nanos = |value: &str| value.parse().ok().map(Duration::from_nanos);
+    if let Some(text) = field.strip_prefix('T') {
+        return Some(EmbeddedCue::Text(tsv::unescape(text)));
+    }
+    let (starts, lead_out) = field.strip_prefix('S')?.split_once(';')?;
+    let starts = match starts {
+        "" => Vec::new(),
+        starts => starts.split(',').map(nanos).collect::<Option<_>>()?,
+    };
+    let lead_out = match lead_out {
+        "" => None,
+        end => Some(nanos(end)?),
+    };
+    Some(EmbeddedCue::Starts(starts, lead_out))
+}
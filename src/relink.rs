@@ -0,0 +1,62 @@
+// Поиск пропавших файлов плейлиста: файл с тем же именем в корневых папках.
+// Размер, если он записан в плейлисте, отличает тезок - 01.flac есть почти в каждом альбоме
+use crate::library;
+use std::collections::{HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+/// Пропавший файл и его новое место, если нашлось
+pub type Relinked = Vec<(PathBuf, Option<PathBuf>)>;
+
+/// Поиск в отдельном потоке: обход большой библиотеки занимает секунды.
+/// Результат приходит одним сообщением
+pub fn spawn(missing: Vec<(PathBuf, Option<u64>)>, roots: Vec<PathBuf>) -> Receiver<Relinked> {
+    let (tx, rx) = mpsc::channel();
+    thread::Builder::new()
+        .name("relink".into())
+        .spawn(move || {
+            let found = find(&missing, &roots);
+            let relinked = missing.into_iter().map(|(path, _)| path).zip(found);
+            // Получатель мог уйти вместе с приложением
+            let _ = tx.send(relinked.collect());
+        })
+        .expect("не удалось запустить поток поиска файлов");
+    rx
+}
+
+// Новое место каждого файла по порядку. None - не нашли или нашли несколько
+// одинаковых кандидатов: угадывать не беремся
+fn find(missing: &[(PathBuf, Option<u64>)], roots: &[PathBuf]) -> Vec<Option<PathBuf>> {
+    let wanted: HashSet<&OsStr> = missing
+        .iter()
+        .filter_map(|(path, _)| path.file_name())
+        .collect();
+    let mut by_name: HashMap<OsString, Vec<PathBuf>> = HashMap::new();
+    for root in roots {
+        for path in library::audio_files(root) {
+            if let Some(name) = path.file_name().filter(|name| wanted.contains(name)) {
+                by_name.entry(name.to_owned()).or_default().push(path);
+            }
+        }
+    }
+    // Корни могут быть вложены друг в друга - один файл найдется дважды
+    for candidates in by_name.values_mut() {
+        candidates.sort();
+        candidates.dedup();
+    }
+
+    missing
+        .iter()
+        .map(|(path, size)| {
+            let candidates = by_name.get(path.file_name()?)?;
+            let mut matches = candidates.iter().filter(|candidate| {
+                size.is_none_or(|size| fs::metadata(candidate).is_ok_and(|m| m.len() == size))
+            });
+            let found = matches.next()?;
+            matches.next().is_none().then(|| found.clone())
+        })
+        .collect()
+}
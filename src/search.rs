@@ -0,0 +1,39 @@
+// Нечеткий поиск для фильтра панелей (/)
+
+/// Запрос, разобранный один раз на слова в нижнем регистре
+pub struct Matcher {
+    terms: Vec<Vec<char>>,
+}
+
+impl Matcher {
+    pub fn new(query: &str) -> Self {
+        Matcher {
+            terms: query
+                .split_whitespace()
+                .map(|term| term.to_lowercase().chars().collect())
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// Каждое слово запроса должно найтись хотя бы в одном поле как
+    /// подпоследовательность: "pnk flyd" находит "Pink Floyd"
+    pub fn matches(&self, fields: &[&str]) -> bool {
+        self.terms
+            .iter()
+            .all(|term| fields.iter().any(|field| is_subsequence(term, field)))
+    }
+}
+
+fn is_subsequence(term: &[char], text: &str) -> bool {
+    let mut rest = term.iter().peekable();
+    for c in text.chars().flat_map(char::to_lowercase) {
+        if rest.peek() == Some(&&c) {
+            rest.next();
+        }
+    }
+    rest.peek().is_none()
+}
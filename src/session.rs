@@ -0,0 +1,121 @@
+// Сессия между запусками: плейлист, текущий трек и позиция, громкость, папка.
+// Хранится в $XDG_STATE_HOME - это состояние, а не настройки и не кэш.
+use crate::audio_engine::Span;
+use crate::paths;
+use crate::tsv;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+const SESSION_FILE: &str = "session.tsv";
+const HEADER: &str = "# hi-res-player session v1";
+
+/// Запись плейлиста: путь и название из #EXTINF или имени файла.
+/// span - трек CUE внутри образа альбома
+#[derive(Clone, PartialEq)]
+pub struct SessionTrack {
+    pub path: PathBuf,
+    pub name: String,
+    pub span: Option<Span>,
+}
+
+#[derive(Clone, PartialEq)]
+pub struct Session {
+    pub dir: Option<PathBuf>,
+    pub volume: f32,
+    pub playlist: Vec<SessionTrack>,
+    /// Индекс в плейлисте и позиция трека, который играл при выходе
+    pub current: Option<(usize, Duration)>,
+}
+
+impl Session {
+    /// None - сессии нет или файл другой версии
+    pub fn load() -> Option<Self> {
+        let content = fs::read_to_string(session_path()?).ok()?;
+        let mut lines = content.lines();
+        if lines.next() != Some(HEADER) {
+            return None;
+        }
+
+        let mut session = Session {
+            dir: None,
+            volume: 1.0,
+            playlist: Vec::new(),
+            current: None,
+        };
+        for line in lines {
+            let fields: Vec<&str> = line.split('\t').collect();
+            match fields[..] {
+                ["dir", dir] => session.dir = Some(tsv::unescape_path(dir)),
+                ["volume", volume] => {
+                    if let Ok(volume) = volume.parse::<f32>() {
+                        session.volume = volume.clamp(0.0, 1.0);
+                    }
+                }
+                ["current", index, position_ms] => {
+                    if let (Ok(index), Ok(ms)) = (index.parse(), position_ms.parse()) {
+                        session.current = Some((index, Duration::from_millis(ms)));
+                    }
+                }
+                ["track", path, name] => session.playlist.push(SessionTrack {
+                    path: tsv::unescape_path(path),
+                    name: tsv::unescape(name),
+                    span: None,
+                }),
+                // Трек CUE: начало и конец в миллисекундах, пустой конец - до конца файла
+                ["track", path, name, start_ms, end_ms] => {
+                    let Ok(start) = start_ms.parse() else {
+                        continue;
+                    };
+                    let end = end_ms.parse().ok().map(Duration::from_millis);
+                    session.playlist.push(SessionTrack {
+                        path: tsv::unescape_path(path),
+                        name: tsv::unescape(name),
+                        span: Some(Span {
+                            start: Duration::from_millis(start),
+                            end,
+                        }),
+                    });
+                }
+                _ => {}
+            }
+        }
+        Some(session)
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let Some(path) = session_path() else {
+            return Ok(());
+        };
+        let mut lines = Vec::new();
+        if let Some(dir) = &self.dir {
+            lines.push(format!("dir\t{}", tsv::escape_path(dir)));
+        }
+        lines.push(format!("volume\t{:.2}", self.volume));
+        if let Some((index, position)) = self.current {
+            lines.push(format!("current\t{}\t{}", index, position.as_millis()));
+        }
+        lines.extend(self.playlist.iter().map(|track| {
+            let mut line = format!(
+                "track\t{}\t{}",
+                tsv::escape_path(&track.path),
+                tsv::escape(&track.name)
+            );
+            if let Some(span) = track.span {
+                let end = span.end.map(|end| end.as_millis().to_string());
+                line.push_str(&format!(
+                    "\t{}\t{}",
+                    span.start.as_millis(),
+                    end.unwrap_or_default()
+                ));
+            }
+            line
+        }));
+        tsv::write_file(&path, HEADER, lines)
+    }
+}
+
+fn session_path() -> Option<PathBuf> {
+    paths::state_dir().map(|dir| dir.join(SESSION_FILE))
+}
@@ -0,0 +1,172 @@
+// Общий построчный TSV для кэша пробы и индекса библиотеки
+use crate::audio_engine::TrackInfo;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Сколько полей занимает TrackInfo в строке
+pub const INFO_FIELDS: usize = 12;
+
+/// Пишет заголовок и строки во временный файл и переименовывает:
+/// оборванная запись не испортит старый файл
+pub fn write_file(
+    path: &Path,
+    header: &str,
+    lines: impl IntoIterator<Item = String>,
+) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("tmp");
+    let mut out = BufWriter::new(fs::File::create(&tmp)?);
+    writeln!(out, "{}", header)?;
+    for line in lines {
+        writeln!(out, "{}", line)?;
+    }
+    out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    fs::rename(&tmp, path)
+}
+
+// длительность в мс, затем текстовые теги и номера
+pub fn push_info(fields: &mut Vec<String>, info: &TrackInfo) {
+    let texts = [
+        &info.title,
+        &info.artist,
+        &info.album,
+        &info.album_artist,
+        &info.composer,
+        &info.genre,
+        &info.date,
+    ];
+    let numbers = [
+        info.track_number,
+        info.track_total,
+        info.disc_number,
+        info.disc_total,
+    ];
+
+    fields.push(
+        info.duration
+            .map(|d| d.as_millis().to_string())
+            .unwrap_or_default(),
+    );
+    fields.extend(
+        texts
+            .iter()
+            .map(|t| t.as_deref().map(escape).unwrap_or_default()),
+    );
+    fields.extend(numbers.iter().map(|n| number(*n)));
+}
+
+/// Разбирает INFO_FIELDS полей, записанных push_info
+pub fn parse_info(fields: &[&str]) -> TrackInfo {
+    let text = |i: usize| Some(unescape(fields[i])).filter(|s| !s.is_empty());
+    let number = |i: usize| fields[i].parse().ok();
+
+    TrackInfo {
+        duration: fields[0].parse().ok().map(Duration::from_millis),
+        title: text(1),
+        artist: text(2),
+        album: text(3),
+        album_artist: text(4),
+        composer: text(5),
+        genre: text(6),
+        date: text(7),
+        track_number: number(8),
+        track_total: number(9),
+        disc_number: number(10),
+        disc_total: number(11),
+    }
+}
+
+/// Пустое поле для None
+pub fn number<T: ToString>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+pub fn escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+pub fn unescape(value: &str) -> String {
+    String::from_utf8_lossy(&unescape_bytes(value)).into_owned()
+}
+
+/// Как escape, но байты имени, которые не являются UTF-8, пишутся как \xHH
+pub fn escape_path(path: &Path) -> String {
+    let mut out = String::new();
+    for chunk in path.as_os_str().as_bytes().utf8_chunks() {
+        out.push_str(&escape(chunk.valid()));
+        for byte in chunk.invalid() {
+            out.push_str(&format!("\\x{:02X}", byte));
+        }
+    }
+    out
+}
+
+pub fn unescape_path(value: &str) -> PathBuf {
+    PathBuf::from(OsString::from_vec(unescape_bytes(value)))
+}
+
+fn unescape_bytes(value: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(value.len());
+    let mut chars = value.chars();
+    let mut buf = [0; 4];
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.extend(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push(b'\t'),
+            Some('n') => out.push(b'\n'),
+            Some('r') => out.push(b'\r'),
+            Some('x') => {
+                let byte = chars
+                    .as_str()
+                    .get(..2)
+                    .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                match byte {
+                    Some(byte) => {
+                        out.push(byte);
+                        chars.nth(1);
+                    }
+                    None => out.push(b'x'),
+                }
+            }
+            Some(other) => out.extend(other.encode_utf8(&mut buf).as_bytes()),
+            None => out.push(b'\\'),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn non_utf8_path_survives_escaping() {
+        let path = PathBuf::from(OsString::from_vec(
+            b"/music/\xCF\xE5\xF1\xED\xFF\t\\x41.flac".to_vec(),
+        ));
+        let escaped = escape_path(&path);
+        assert!(!escaped.contains('\t'));
+        assert_eq!(unescape_path(&escaped), path);
+        assert_eq!(unescape(&escape("a\\x41\tb")), "a\\x41\tb");
+    }
+}